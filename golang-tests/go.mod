@@ -2,6 +2,8 @@ module rusty-chunkenc-golang-tests
 
 go 1.23.0
 
+require github.com/prometheus/prometheus v0.54.1
+
 require (
 	github.com/davecgh/go-spew v1.1.1 // indirect
 	github.com/pmezard/go-difflib v1.0.0 // indirect
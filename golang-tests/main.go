@@ -4,11 +4,15 @@ import (
 	"encoding/base64"
 	"encoding/binary"
 	"encoding/json"
+	"fmt"
 	"log"
 	"math"
 	"os"
 	"strconv"
 
+	"github.com/prometheus/prometheus/model/histogram"
+	promchunkenc "github.com/prometheus/prometheus/tsdb/chunkenc"
+
 	"rusty-chunkenc-golang-tests/chunkenc"
 )
 
@@ -37,11 +41,60 @@ type JChunk struct {
 	Encoded []byte    `json:"e"`
 }
 
+type JSpan struct {
+	Offset int32  `json:"o"`
+	Length uint32 `json:"l"`
+}
+
+type JHistogramSample struct {
+	Timestamp       int64   `json:"ts"`
+	Schema          int32   `json:"schema"`
+	ZeroThreshold   float64 `json:"zt"`
+	ZeroCount       uint64  `json:"zc"`
+	Count           uint64  `json:"c"`
+	Sum             float64 `json:"sum"`
+	PositiveSpans   []JSpan `json:"ps"`
+	NegativeSpans   []JSpan `json:"ns"`
+	PositiveBuckets []int64 `json:"pb"`
+	NegativeBuckets []int64 `json:"nb"`
+}
+
+type JFloatHistogramSample struct {
+	Timestamp       int64     `json:"ts"`
+	Schema          int32     `json:"schema"`
+	ZeroThreshold   float64   `json:"zt"`
+	ZeroCount       float64   `json:"zc"`
+	Count           float64   `json:"c"`
+	Sum             float64   `json:"sum"`
+	PositiveSpans   []JSpan   `json:"ps"`
+	NegativeSpans   []JSpan   `json:"ns"`
+	PositiveBuckets []float64 `json:"pb"`
+	NegativeBuckets []float64 `json:"nb"`
+}
+
+// The samples of a series, appended one by one with Prometheus' appender,
+// and the chunks it produced, including the recoded and the cut ones.
+type JHistogramSeries struct {
+	Gauge   bool               `json:"g"`
+	Samples []JHistogramSample `json:"s"`
+	Chunks  []JEncoded         `json:"c"`
+}
+
+type JFloatHistogramSeries struct {
+	Gauge   bool                    `json:"g"`
+	Samples []JFloatHistogramSample `json:"s"`
+	Chunks  []JEncoded              `json:"c"`
+}
+
+type JEncoded []byte
+
 type TestJson struct {
-	VarbitInts  []JVarbitInt  `json:"varbit_ints"`
-	VarbitUints []JVarbitUint `json:"varbit_uints"`
-	Uvarints    []JUvarint    `json:"uvarints"`
-	Chunks      []JChunk      `json:"chunks"`
+	VarbitInts           []JVarbitInt            `json:"varbit_ints"`
+	VarbitUints          []JVarbitUint           `json:"varbit_uints"`
+	Uvarints             []JUvarint              `json:"uvarints"`
+	Chunks               []JChunk                `json:"chunks"`
+	HistogramSeries      []JHistogramSeries      `json:"histogram_series"`
+	FloatHistogramSeries []JFloatHistogramSeries `json:"float_histogram_series"`
 }
 
 func (i JVarbitInt) MarshalJSON() ([]byte, error) {
@@ -77,6 +130,22 @@ func (u JUvarint) MarshalJSON() ([]byte, error) {
 	})
 }
 
+func (e JEncoded) MarshalJSON() ([]byte, error) {
+	return json.Marshal(base64.RawStdEncoding.EncodeToString(e))
+}
+
+func to_jspans(spans []histogram.Span) []JSpan {
+	jspans := make([]JSpan, 0, len(spans))
+	for _, span := range spans {
+		jspans = append(jspans, JSpan{span.Offset, span.Length})
+	}
+	return jspans
+}
+
+func copy_bytes(b []byte) JEncoded {
+	return append(JEncoded{}, b...)
+}
+
 func make_varbitint_samples() []JVarbitInt {
 	// Define some example numbers
 	numbers := []int64{
@@ -206,6 +275,145 @@ func make_chunk_samples(n int) []JChunk {
 	return chunks
 }
 
+func make_histogram_series() []JHistogramSeries {
+	spans := []histogram.Span{{Offset: 0, Length: 2}, {Offset: 1, Length: 1}}
+	wider_spans := []histogram.Span{{Offset: -1, Length: 3}, {Offset: 1, Length: 2}}
+
+	counter := []*histogram.Histogram{
+		{Schema: 1, ZeroThreshold: 0.001, ZeroCount: 1, Count: 11, Sum: 12.5,
+			PositiveSpans: spans, PositiveBuckets: []int64{3, -1, 2},
+			NegativeSpans: []histogram.Span{{Offset: 2, Length: 1}}, NegativeBuckets: []int64{1}},
+		{Schema: 1, ZeroThreshold: 0.001, ZeroCount: 2, Count: 16, Sum: 20.25,
+			PositiveSpans: spans, PositiveBuckets: []int64{4, -1, 3},
+			NegativeSpans: []histogram.Span{{Offset: 2, Length: 1}}, NegativeBuckets: []int64{1}},
+		{Schema: 1, ZeroThreshold: 0.001, ZeroCount: 2, Count: 20, Sum: 31.0,
+			PositiveSpans: spans, PositiveBuckets: []int64{5, -1, 4},
+			NegativeSpans: []histogram.Span{{Offset: 2, Length: 1}}, NegativeBuckets: []int64{1}},
+		// New buckets: the previous samples are recoded with the wider spans.
+		{Schema: 1, ZeroThreshold: 0.001, ZeroCount: 3, Count: 30, Sum: 35.75,
+			PositiveSpans: wider_spans, PositiveBuckets: []int64{1, 5, -2, 5, -4},
+			NegativeSpans: []histogram.Span{{Offset: 2, Length: 1}}, NegativeBuckets: []int64{2}},
+		{Schema: 1, ZeroThreshold: 0.001, ZeroCount: 3, Count: 1000036, Sum: 1e6,
+			PositiveSpans: wider_spans, PositiveBuckets: []int64{1, 6, -3, 5, 1000001},
+			NegativeSpans: []histogram.Span{{Offset: 2, Length: 1}}, NegativeBuckets: []int64{2}},
+		// Counter reset: a new chunk is cut, with the counter reset header.
+		{Schema: 1, ZeroThreshold: 0.001, ZeroCount: 0, Count: 5, Sum: 2.5,
+			PositiveSpans: wider_spans, PositiveBuckets: []int64{0, 1, 0, 1, -1},
+			NegativeSpans: []histogram.Span{{Offset: 2, Length: 1}}, NegativeBuckets: []int64{0}},
+		{Schema: 1, ZeroThreshold: 0.001, ZeroCount: 1, Count: 8, Sum: 5.5,
+			PositiveSpans: wider_spans, PositiveBuckets: []int64{1, 0, 0, 1, 0},
+			NegativeSpans: []histogram.Span{{Offset: 2, Length: 1}}, NegativeBuckets: []int64{0}},
+	}
+
+	gauge := []*histogram.Histogram{
+		{CounterResetHint: histogram.GaugeType, Schema: 0, ZeroThreshold: 0.01, ZeroCount: 2, Count: 5, Sum: -3.5,
+			PositiveSpans: []histogram.Span{{Offset: 1, Length: 1}}, PositiveBuckets: []int64{1},
+			NegativeSpans: []histogram.Span{{Offset: 0, Length: 2}}, NegativeBuckets: []int64{1, 0}},
+		{CounterResetHint: histogram.GaugeType, Schema: 0, ZeroThreshold: 0.01, ZeroCount: 1, Count: 3, Sum: -1.25,
+			PositiveSpans: []histogram.Span{{Offset: 1, Length: 1}}, PositiveBuckets: []int64{1},
+			NegativeSpans: []histogram.Span{{Offset: 0, Length: 2}}, NegativeBuckets: []int64{1, -1}},
+	}
+
+	series := make([]JHistogramSeries, 0)
+	for _, histograms := range [][]*histogram.Histogram{counter, gauge} {
+		jseries := JHistogramSeries{Gauge: histograms[0].CounterResetHint == histogram.GaugeType}
+		var chunk promchunkenc.Chunk = promchunkenc.NewHistogramChunk()
+		app, err := chunk.Appender()
+		if err != nil {
+			log.Fatalf("Failed to create appender: %v", err)
+		}
+		for i, h := range histograms {
+			ts := int64(1234123324 + i*15000)
+			// The appender may modify the histogram, so it is saved first.
+			jseries.Samples = append(jseries.Samples, JHistogramSample{
+				ts, h.Schema, h.ZeroThreshold, h.ZeroCount, h.Count, h.Sum,
+				to_jspans(h.PositiveSpans), to_jspans(h.NegativeSpans),
+				append([]int64{}, h.PositiveBuckets...), append([]int64{}, h.NegativeBuckets...),
+			})
+			new_chunk, recoded, new_app, err := app.AppendHistogram(nil, ts, h.Copy(), false)
+			if err != nil {
+				log.Fatalf("Failed to append histogram: %v", err)
+			}
+			if new_chunk != nil {
+				if !recoded {
+					jseries.Chunks = append(jseries.Chunks, copy_bytes(chunk.Bytes()))
+				}
+				chunk = new_chunk
+				app = new_app
+			}
+		}
+		jseries.Chunks = append(jseries.Chunks, copy_bytes(chunk.Bytes()))
+		series = append(series, jseries)
+	}
+	return series
+}
+
+func make_float_histogram_series() []JFloatHistogramSeries {
+	spans := []histogram.Span{{Offset: -2, Length: 3}, {Offset: 2, Length: 2}}
+	wider_spans := []histogram.Span{{Offset: -2, Length: 3}, {Offset: 1, Length: 3}}
+
+	// The bucket values change in the same few mantissa bits,
+	// so the leading and trailing bits windows are reused.
+	counter := []*histogram.FloatHistogram{
+		{Schema: 3, ZeroThreshold: 1e-128, ZeroCount: 0.5, Count: 10.5, Sum: 123.25,
+			PositiveSpans: spans, PositiveBuckets: []float64{1, 2, 2.5, 3, 1.5}},
+		{Schema: 3, ZeroThreshold: 1e-128, ZeroCount: 0.75, Count: 11.75, Sum: 130.125,
+			PositiveSpans: spans, PositiveBuckets: []float64{1.25, 2.25, 2.75, 3, 1.75}},
+		{Schema: 3, ZeroThreshold: 1e-128, ZeroCount: 0.875, Count: 12.375, Sum: 131.0625,
+			PositiveSpans: spans, PositiveBuckets: []float64{1.375, 2.25, 2.875, 3.125, 1.875}},
+		{Schema: 3, ZeroThreshold: 1e-128, ZeroCount: 0.875, Count: 12.375, Sum: 131.0625,
+			PositiveSpans: spans, PositiveBuckets: []float64{1.375, 2.25, 2.875, 3.125, 1.875}},
+		// New buckets: the previous samples are recoded with the wider spans.
+		{Schema: 3, ZeroThreshold: 1e-128, ZeroCount: 1, Count: 13.5, Sum: 140.3,
+			PositiveSpans: wider_spans, PositiveBuckets: []float64{1.5, 2.5, 3, 0.25, 3.25, 2}},
+		// Counter reset: a new chunk is cut, with the counter reset header.
+		{Schema: 3, ZeroThreshold: 1e-128, ZeroCount: 0, Count: 2, Sum: 1.5,
+			PositiveSpans: wider_spans, PositiveBuckets: []float64{0, 1, 0, 0, 1, 0}},
+		{Schema: 3, ZeroThreshold: 1e-128, ZeroCount: 0.25, Count: 3.75, Sum: 3.75,
+			PositiveSpans: wider_spans, PositiveBuckets: []float64{0.25, 1.25, 0, 0.25, 1.5, 0.25}},
+	}
+
+	gauge := []*histogram.FloatHistogram{
+		{CounterResetHint: histogram.GaugeType, Schema: -1, ZeroThreshold: 0.5, ZeroCount: 1.5, Count: 6.5, Sum: -7,
+			NegativeSpans: []histogram.Span{{Offset: 0, Length: 3}}, NegativeBuckets: []float64{2, 1, 2}},
+		{CounterResetHint: histogram.GaugeType, Schema: -1, ZeroThreshold: 0.5, ZeroCount: 0.5, Count: 4, Sum: -3.5,
+			NegativeSpans: []histogram.Span{{Offset: 0, Length: 3}}, NegativeBuckets: []float64{1.5, 1, 1}},
+	}
+
+	series := make([]JFloatHistogramSeries, 0)
+	for _, histograms := range [][]*histogram.FloatHistogram{counter, gauge} {
+		jseries := JFloatHistogramSeries{Gauge: histograms[0].CounterResetHint == histogram.GaugeType}
+		var chunk promchunkenc.Chunk = promchunkenc.NewFloatHistogramChunk()
+		app, err := chunk.Appender()
+		if err != nil {
+			log.Fatalf("Failed to create appender: %v", err)
+		}
+		for i, h := range histograms {
+			ts := int64(1234123324 + i*15000)
+			// The appender may modify the histogram, so it is saved first.
+			jseries.Samples = append(jseries.Samples, JFloatHistogramSample{
+				ts, h.Schema, h.ZeroThreshold, h.ZeroCount, h.Count, h.Sum,
+				to_jspans(h.PositiveSpans), to_jspans(h.NegativeSpans),
+				append([]float64{}, h.PositiveBuckets...), append([]float64{}, h.NegativeBuckets...),
+			})
+			new_chunk, recoded, new_app, err := app.AppendFloatHistogram(nil, ts, h.Copy(), false)
+			if err != nil {
+				log.Fatalf("Failed to append float histogram: %v", err)
+			}
+			if new_chunk != nil {
+				if !recoded {
+					jseries.Chunks = append(jseries.Chunks, copy_bytes(chunk.Bytes()))
+				}
+				chunk = new_chunk
+				app = new_app
+			}
+		}
+		jseries.Chunks = append(jseries.Chunks, copy_bytes(chunk.Bytes()))
+		series = append(series, jseries)
+	}
+	return series
+}
+
 func main() {
 	// read n as first argument
 	n, err := strconv.Atoi(os.Args[1])
@@ -220,12 +428,15 @@ func main() {
 		VarbitUints: make_varbituint_samples(),
 		Uvarints:    make_uvarint_samples(),
 		Chunks:      make_chunk_samples(n),
+
+		HistogramSeries:      make_histogram_series(),
+		FloatHistogramSeries: make_float_histogram_series(),
 	}
 
-	_, err = json.MarshalIndent(samples, "", "  ")
+	jsonData, err := json.MarshalIndent(samples, "", "  ")
 	if err != nil {
 		log.Fatalf("Failed to marshal JSON: %v", err)
 	}
 
-	//fmt.Println(string(jsonData))
+	fmt.Println(string(jsonData))
 }
@@ -0,0 +1,134 @@
+use nom::{bytes::complete::take, number::complete::u8 as read_u8, IResult};
+
+use crate::{
+    chunk::ChunkWithBlockChunkRef,
+    uvarint::read_uvarint,
+    xor::{read_xor_chunk_data, XORChunk},
+};
+
+/// The aggregates of a Thanos aggregate chunk, in the order of the chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AggrType {
+    Count = 0,
+    Sum = 1,
+    Min = 2,
+    Max = 3,
+    Counter = 4,
+}
+
+impl AggrType {
+    /// All the aggregates, in the order of the chunk.
+    pub const ALL: [AggrType; 5] = [
+        AggrType::Count,
+        AggrType::Sum,
+        AggrType::Min,
+        AggrType::Max,
+        AggrType::Counter,
+    ];
+}
+
+/// A Thanos aggregate chunk, used in downsampled blocks.
+///
+/// It packs one XOR chunk per aggregate: the count, sum, minimum and maximum
+/// of the samples of each downsampling window, and the counter, that has the
+/// counter resets applied. Not all the aggregates must be present.
+#[derive(Debug, Default)]
+pub struct AggrChunk {
+    // Boxed, to keep the chunk enum small.
+    chunks: Box<[Option<XORChunk>; 5]>,
+    block_chunk_ref: Option<u64>,
+    addr: Option<*const u8>,
+}
+
+impl ChunkWithBlockChunkRef for AggrChunk {
+    fn block_chunk_ref(&self) -> Option<u64> {
+        self.block_chunk_ref
+    }
+    fn compute_block_chunk_ref(&mut self, file_index: u64, chunks_addr: *const u8) {
+        match self.addr {
+            Some(addr) => {
+                self.block_chunk_ref =
+                    Some((file_index << 32) | (addr as u64 - chunks_addr as u64));
+            }
+            None => self.block_chunk_ref = None,
+        }
+    }
+}
+
+impl PartialEq for AggrChunk {
+    fn eq(&self, other: &Self) -> bool {
+        self.chunks == other.chunks
+    }
+}
+
+impl AggrChunk {
+    /// Creates a new aggregate chunk, with the chunks in the order of [`AggrType::ALL`].
+    pub fn new(chunks: [Option<XORChunk>; 5]) -> Self {
+        Self {
+            chunks: Box::new(chunks),
+            block_chunk_ref: None,
+            addr: None,
+        }
+    }
+
+    /// Sets the memory address of the chunk.
+    pub(crate) fn set_addr(&mut self, addr: *const u8) {
+        self.addr = Some(addr);
+    }
+
+    /// Returns the chunk of the aggregate, if present.
+    pub fn get(&self, aggr_type: AggrType) -> Option<&XORChunk> {
+        self.chunks[aggr_type as usize].as_ref()
+    }
+
+    /// Returns the chunks, in the order of [`AggrType::ALL`].
+    pub fn chunks(&self) -> &[Option<XORChunk>; 5] {
+        &self.chunks
+    }
+
+    /// Returns the chunks, in the order of [`AggrType::ALL`].
+    pub fn into_chunks(self) -> [Option<XORChunk>; 5] {
+        *self.chunks
+    }
+}
+
+fn read_aggregate(input: &[u8]) -> IResult<&[u8], Option<XORChunk>> {
+    let (remaining_input, length) = read_uvarint(input)?;
+    // Missing aggregates have a zero length.
+    if length == 0 {
+        return Ok((remaining_input, None));
+    }
+    let (remaining_input, encoding) = read_u8(remaining_input)?;
+    // Thanos only writes XOR chunks in aggregate chunks.
+    if encoding != 1 {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Tag,
+        )));
+    }
+    let length = usize::try_from(length).map_err(|_| {
+        nom::Err::Error(nom::error::Error::new(
+            remaining_input,
+            nom::error::ErrorKind::TooLarge,
+        ))
+    })?;
+    let (remaining_input, chunk_data) = take(length)(remaining_input)?;
+    let (_, xor_chunk) = read_xor_chunk_data(chunk_data)?;
+    Ok((remaining_input, Some(xor_chunk)))
+}
+
+/// Reads a Thanos aggregate chunk from the input data.
+///
+/// Like [`crate::xor::read_xor_chunk_data`], it doesn't read the chunk header,
+/// use the `read_chunk` function for chunks with a header.
+pub fn read_aggr_chunk_data(input: &[u8]) -> IResult<&[u8], AggrChunk> {
+    let (remaining_input, count) = read_aggregate(input)?;
+    let (remaining_input, sum) = read_aggregate(remaining_input)?;
+    let (remaining_input, min) = read_aggregate(remaining_input)?;
+    let (remaining_input, max) = read_aggregate(remaining_input)?;
+    let (remaining_input, counter) = read_aggregate(remaining_input)?;
+    Ok((
+        remaining_input,
+        AggrChunk::new([count, sum, min, max, counter]),
+    ))
+}
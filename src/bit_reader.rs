@@ -0,0 +1,205 @@
+use nom::{error::ErrorKind, IResult};
+
+use crate::NomBitInput;
+
+/// A buffered bit reader, like Prometheus' `bstreamReader`.
+///
+/// It loads the input 64 bits at a time, so most reads are a shift and a mask.
+/// It decodes XOR chunks much faster than the nom bit parsers, that remain
+/// available on top of it.
+///
+/// Reading past the end of the input fails with [`ErrorKind::Eof`].
+#[derive(Debug, Clone)]
+pub struct BitReader<'a> {
+    input: &'a [u8],
+    /// The number of bytes of the input loaded in the buffer so far.
+    offset: usize,
+    /// The unread bits are the `valid` lowest bits of the buffer.
+    buffer: u64,
+    valid: u8,
+}
+
+#[inline]
+fn lowest_bits(value: u64, num_bits: u8) -> u64 {
+    if num_bits >= 64 {
+        value
+    } else {
+        value & ((1 << num_bits) - 1)
+    }
+}
+
+impl<'a> BitReader<'a> {
+    /// Creates a bit reader, starting at the first bit of the input.
+    pub fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            offset: 0,
+            buffer: 0,
+            valid: 0,
+        }
+    }
+
+    /// Creates a bit reader from a nom bit input.
+    pub(crate) fn from_nom_input((input, bit_offset): NomBitInput<'a>) -> Self {
+        let mut reader = Self::new(input);
+        if bit_offset > 0 {
+            // Nom bit inputs always have a byte at a non-zero offset.
+            let _ = reader.read_bits(bit_offset as u8);
+        }
+        reader
+    }
+
+    /// Returns the position of the reader as a nom bit input.
+    pub(crate) fn nom_input(&self) -> NomBitInput<'a> {
+        let position = self.bit_position();
+        (&self.input[position / 8..], position % 8)
+    }
+
+    /// Returns the number of bits read so far.
+    pub fn bit_position(&self) -> usize {
+        self.offset * 8 - self.valid as usize
+    }
+
+    /// Returns the input following the last byte read, even partially.
+    pub fn remaining_bytes(&self) -> &'a [u8] {
+        &self.input[self.bit_position().div_ceil(8)..]
+    }
+
+    /// Loads the next bytes of the input in the empty buffer.
+    #[inline]
+    fn refill(&mut self) -> Result<(), ErrorKind> {
+        let remaining_input = &self.input[self.offset..];
+        if let Some(bytes) = remaining_input.first_chunk::<8>() {
+            self.buffer = u64::from_be_bytes(*bytes);
+            self.valid = 64;
+            self.offset += 8;
+        } else if remaining_input.is_empty() {
+            return Err(ErrorKind::Eof);
+        } else {
+            self.buffer = remaining_input
+                .iter()
+                .fold(0, |buffer, &byte| (buffer << 8) | byte as u64);
+            self.valid = remaining_input.len() as u8 * 8;
+            self.offset += remaining_input.len();
+        }
+        Ok(())
+    }
+
+    /// Reads one bit.
+    #[inline]
+    pub fn read_bit(&mut self) -> Result<bool, ErrorKind> {
+        if self.valid == 0 {
+            self.refill()?;
+        }
+        self.valid -= 1;
+        Ok((self.buffer >> self.valid) & 1 == 1)
+    }
+
+    /// Reads up to 64 bits, most significant bit first.
+    #[inline]
+    pub fn read_bits(&mut self, num_bits: u8) -> Result<u64, ErrorKind> {
+        if num_bits > 64 {
+            return Err(ErrorKind::TooLarge);
+        }
+        if num_bits == 0 {
+            return Ok(0);
+        }
+        if num_bits <= self.valid {
+            self.valid -= num_bits;
+            return Ok(lowest_bits(self.buffer >> self.valid, num_bits));
+        }
+
+        // The bits are split between the buffer and the next bytes.
+        let high_num_bits = self.valid;
+        let high_bits = lowest_bits(self.buffer, high_num_bits);
+        let low_num_bits = num_bits - high_num_bits;
+        self.valid = 0;
+        self.refill()?;
+        if low_num_bits > self.valid {
+            self.valid = 0;
+            return Err(ErrorKind::Eof);
+        }
+        self.valid -= low_num_bits;
+        let low_bits = lowest_bits(self.buffer >> self.valid, low_num_bits);
+        if high_num_bits == 0 {
+            Ok(low_bits)
+        } else {
+            Ok((high_bits << low_num_bits) | low_bits)
+        }
+    }
+}
+
+/// Runs a bit reader parser on a nom bit input.
+///
+/// The errors are reported at the start of the input.
+pub(crate) fn parse_bits<'a, T>(
+    input: NomBitInput<'a>,
+    parser: impl FnOnce(&mut BitReader<'a>) -> Result<T, ErrorKind>,
+) -> IResult<NomBitInput<'a>, T> {
+    let mut reader = BitReader::from_nom_input(input);
+    match parser(&mut reader) {
+        Ok(value) => Ok((reader.nom_input(), value)),
+        Err(kind) => Err(nom::Err::Error(nom::error::Error::new(input, kind))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nom::bits::complete::take;
+
+    use super::*;
+
+    #[test]
+    fn test_read_bits() {
+        let input = (0..40u8)
+            .map(|i| i.wrapping_mul(73) ^ 0x5a)
+            .collect::<Vec<_>>();
+        let sizes = [1u8, 7, 64, 3, 13, 0, 64, 1, 33, 17, 5, 64, 2];
+
+        let mut reader = BitReader::new(&input);
+        let mut nom_input: NomBitInput = (&input, 0);
+        for &size in sizes.iter().cycle().take(30) {
+            let expected: Result<(NomBitInput, u64), nom::Err<nom::error::Error<NomBitInput>>> =
+                take(size)(nom_input);
+            match expected {
+                Ok((remaining_input, value)) => {
+                    assert_eq!(reader.read_bits(size), Ok(value));
+                    nom_input = remaining_input;
+                    assert_eq!(reader.nom_input(), nom_input);
+                }
+                Err(_) => {
+                    assert_eq!(reader.read_bits(size), Err(ErrorKind::Eof));
+                    break;
+                }
+            }
+        }
+
+        let mut reader = BitReader::new(&[0b1010_0000]);
+        assert_eq!(reader.read_bit(), Ok(true));
+        assert_eq!(reader.read_bit(), Ok(false));
+        assert_eq!(reader.read_bits(3), Ok(0b100));
+        assert_eq!(reader.remaining_bytes(), []);
+        assert_eq!(reader.read_bits(4), Err(ErrorKind::Eof));
+        assert_eq!(BitReader::new(&[]).read_bit(), Err(ErrorKind::Eof));
+        assert_eq!(
+            BitReader::new(&[0; 9]).read_bits(65),
+            Err(ErrorKind::TooLarge)
+        );
+    }
+
+    #[test]
+    fn test_nom_input() {
+        let input = [0x12, 0x34, 0x56];
+        let mut reader = BitReader::from_nom_input((&input[1..], 4));
+        assert_eq!(reader.read_bits(8), Ok(0x45));
+        assert_eq!(reader.nom_input(), (&input[2..], 4));
+        assert_eq!(reader.remaining_bytes(), []);
+
+        let result: IResult<NomBitInput, u64> =
+            parse_bits((&input, 0), |reader| reader.read_bits(12));
+        assert_eq!(result, Ok(((&input[1..], 4), 0x123)));
+        let result: IResult<NomBitInput, u64> =
+            parse_bits((&input, 0), |reader| reader.read_bits(25));
+        assert!(result.is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::num::NonZeroUsize;
+use std::{num::NonZeroUsize, ops::Range};
 
 use nom::{
     branch::alt,
@@ -9,11 +9,13 @@ use nom::{
 };
 
 use crate::{
+    aggr_chunk::{read_aggr_chunk_data, AggrChunk, AggrType},
     crc32c::{assert_crc32c_on_data, read_crc32c},
     histogram::{
-        read_float_histogram_chunk_data, read_histogram_chunk_data, FloatHistogramChunk,
-        HistogramChunk,
+        read_float_histogram_chunk_data, read_histogram_chunk_data, CounterResetHeader,
+        FloatHistogramChunk, HistogramChunk,
     },
+    sample_iterator::ChunkSampleIterator,
     uvarint::read_uvarint,
     xor::{read_xor_chunk_data, XORChunk, XORSample},
 };
@@ -24,6 +26,7 @@ pub(crate) enum ChunkType {
     XOR,
     Histogram,
     FloatHistogram,
+    Aggr,
 }
 
 struct ChunkHeader {
@@ -38,14 +41,14 @@ pub(crate) trait ChunkWithBlockChunkRef {
 
 /// A Prometheus chunk.
 ///
-/// It can be a XOR chunk, a histogram chunk, or a float histogram chunk.
-///
-/// For now, only the XOR chunk type is fully implemented.
+/// It can be a XOR chunk, a histogram chunk, a float histogram chunk,
+/// or a Thanos aggregate chunk.
 #[derive(Debug, PartialEq)]
 pub enum Chunk {
     XOR(XORChunk),
     Histogram(HistogramChunk),
     FloatHistogram(FloatHistogramChunk),
+    Aggr(AggrChunk),
 }
 
 impl Chunk {
@@ -62,12 +65,178 @@ impl Chunk {
         }
     }
 
+    /// Returns the histogram chunk if it's a histogram chunk.
+    pub fn as_histogram(self) -> Option<HistogramChunk> {
+        match self {
+            Chunk::Histogram(histogram_chunk) => Some(histogram_chunk),
+            _ => None,
+        }
+    }
+
+    /// Returns the float histogram chunk if it's a float histogram chunk.
+    pub fn as_float_histogram(self) -> Option<FloatHistogramChunk> {
+        match self {
+            Chunk::FloatHistogram(float_histogram_chunk) => Some(float_histogram_chunk),
+            _ => None,
+        }
+    }
+
+    /// Returns the aggregate chunk if it's a Thanos aggregate chunk.
+    pub fn as_aggr(self) -> Option<AggrChunk> {
+        match self {
+            Chunk::Aggr(aggr_chunk) => Some(aggr_chunk),
+            _ => None,
+        }
+    }
+
+    /// Returns the counter reset header of histogram and float histogram chunks.
+    ///
+    /// XOR and aggregate chunks have no counter reset header.
+    pub fn counter_reset_header(&self) -> Option<CounterResetHeader> {
+        match self {
+            Chunk::XOR(_) | Chunk::Aggr(_) => None,
+            Chunk::Histogram(histogram_chunk) => Some(histogram_chunk.counter_reset_header()),
+            Chunk::FloatHistogram(float_histogram_chunk) => {
+                Some(float_histogram_chunk.counter_reset_header())
+            }
+        }
+    }
+
+    /// Sets the counter reset header of histogram and float histogram chunks.
+    ///
+    /// Returns false for XOR and aggregate chunks, as they have no counter reset header.
+    pub fn set_counter_reset_header(&mut self, counter_reset_header: CounterResetHeader) -> bool {
+        match self {
+            Chunk::XOR(_) | Chunk::Aggr(_) => false,
+            Chunk::Histogram(histogram_chunk) => {
+                histogram_chunk.set_counter_reset_header(counter_reset_header);
+                true
+            }
+            Chunk::FloatHistogram(float_histogram_chunk) => {
+                float_histogram_chunk.set_counter_reset_header(counter_reset_header);
+                true
+            }
+        }
+    }
+
+    /// Returns the timestamps of the first and last samples, or None if the chunk is empty.
+    pub fn time_range(&self) -> Option<(i64, i64)> {
+        match self {
+            Chunk::XOR(xor_chunk) => {
+                let samples = xor_chunk.samples();
+                Some((samples.first()?.timestamp, samples.last()?.timestamp))
+            }
+            Chunk::Histogram(histogram_chunk) => {
+                let samples = histogram_chunk.samples();
+                Some((samples.first()?.timestamp, samples.last()?.timestamp))
+            }
+            Chunk::FloatHistogram(float_histogram_chunk) => {
+                let samples = float_histogram_chunk.samples();
+                Some((samples.first()?.timestamp, samples.last()?.timestamp))
+            }
+            Chunk::Aggr(aggr_chunk) => aggr_chunk
+                .chunks()
+                .iter()
+                .flatten()
+                .filter_map(|xor_chunk| {
+                    let samples = xor_chunk.samples();
+                    Some((samples.first()?.timestamp, samples.last()?.timestamp))
+                })
+                .reduce(|(min, max), (first, last)| (min.min(first), max.max(last))),
+        }
+    }
+
+    /// Returns a chunk with only the samples between `mint` and `maxt`, both inclusive.
+    ///
+    /// The chunk is returned as it is when all its samples are in the range,
+    /// and None is returned when none of them are.
+    ///
+    /// When the first samples of a histogram chunk are dropped, the counter reset header
+    /// becomes `NotCounterReset`, or stays `Gauge`, like in Prometheus.
+    /// The chunks of aggregate chunks are sliced one by one.
+    pub fn slice(self, mint: i64, maxt: i64) -> Option<Chunk> {
+        let (first_timestamp, last_timestamp) = self.time_range()?;
+        if mint <= first_timestamp && last_timestamp <= maxt {
+            return Some(self);
+        }
+        if maxt < first_timestamp || last_timestamp < mint {
+            return None;
+        }
+
+        match self {
+            Chunk::XOR(xor_chunk) => {
+                let samples = xor_chunk.samples();
+                let range = samples_in_range(samples, |sample| sample.timestamp, mint, maxt);
+                if range.is_empty() {
+                    return None;
+                }
+                Some(Chunk::new_xor(samples[range].to_vec()))
+            }
+            Chunk::Histogram(histogram_chunk) => {
+                let samples = histogram_chunk.samples();
+                let range = samples_in_range(samples, |sample| sample.timestamp, mint, maxt);
+                if range.is_empty() {
+                    return None;
+                }
+                let counter_reset_header =
+                    sliced_counter_reset_header(histogram_chunk.counter_reset_header(), &range);
+                Some(Chunk::Histogram(HistogramChunk::new(
+                    samples[range].to_vec(),
+                    counter_reset_header,
+                )))
+            }
+            Chunk::FloatHistogram(float_histogram_chunk) => {
+                let samples = float_histogram_chunk.samples();
+                let range = samples_in_range(samples, |sample| sample.timestamp, mint, maxt);
+                if range.is_empty() {
+                    return None;
+                }
+                let counter_reset_header = sliced_counter_reset_header(
+                    float_histogram_chunk.counter_reset_header(),
+                    &range,
+                );
+                Some(Chunk::FloatHistogram(FloatHistogramChunk::new(
+                    samples[range].to_vec(),
+                    counter_reset_header,
+                )))
+            }
+            Chunk::Aggr(aggr_chunk) => {
+                let chunks = aggr_chunk.into_chunks().map(|xor_chunk| {
+                    Chunk::XOR(xor_chunk?)
+                        .slice(mint, maxt)
+                        .and_then(Chunk::as_xor)
+                });
+                Some(Chunk::Aggr(AggrChunk::new(chunks)))
+            }
+        }
+    }
+
+    /// Returns a [`SampleIterator`] over the samples of the chunk.
+    ///
+    /// Aggregate chunks have one series per aggregate, so the iterator goes over
+    /// the chosen aggregate, and is empty when it's missing. Other chunks ignore it.
+    pub fn iterator(&self, aggr_type: AggrType) -> ChunkSampleIterator<'_> {
+        match self {
+            Chunk::XOR(xor_chunk) => ChunkSampleIterator::new_float(xor_chunk.samples()),
+            Chunk::Histogram(histogram_chunk) => {
+                ChunkSampleIterator::new_histogram(histogram_chunk.samples())
+            }
+            Chunk::FloatHistogram(float_histogram_chunk) => {
+                ChunkSampleIterator::new_float_histogram(float_histogram_chunk.samples())
+            }
+            Chunk::Aggr(aggr_chunk) => aggr_chunk
+                .iterator(aggr_type)
+                .unwrap_or_else(|| ChunkSampleIterator::new_float(&[])),
+        }
+    }
+
     /// Retuns the block chunk reference.
     pub fn block_chunk_ref(&self) -> Option<u64> {
         match self {
             Chunk::XOR(xor_chunk) => xor_chunk.block_chunk_ref(),
             Chunk::Histogram(histogram_chunk) => histogram_chunk.block_chunk_ref(),
             Chunk::FloatHistogram(float_histogram_chunk) => float_histogram_chunk.block_chunk_ref(),
+            Chunk::Aggr(aggr_chunk) => aggr_chunk.block_chunk_ref(),
         }
     }
 
@@ -82,15 +251,52 @@ impl Chunk {
             Chunk::FloatHistogram(float_histogram_chunk) => {
                 float_histogram_chunk.compute_block_chunk_ref(file_index, chunks_addr);
             }
+            Chunk::Aggr(aggr_chunk) => {
+                aggr_chunk.compute_block_chunk_ref(file_index, chunks_addr);
+            }
         }
     }
 }
 
+/// Returns the chunks with only the samples between `mint` and `maxt`, both inclusive.
+///
+/// See [`Chunk::slice`]. The chunks without samples in the range are dropped.
+pub fn slice_chunks(chunks: Vec<Chunk>, mint: i64, maxt: i64) -> Vec<Chunk> {
+    chunks
+        .into_iter()
+        .filter_map(|chunk| chunk.slice(mint, maxt))
+        .collect()
+}
+
+/// Returns the range of the sorted samples between `mint` and `maxt`, both inclusive.
+fn samples_in_range<T>(
+    samples: &[T],
+    timestamp: impl Fn(&T) -> i64,
+    mint: i64,
+    maxt: i64,
+) -> Range<usize> {
+    let start = samples.partition_point(|sample| timestamp(sample) < mint);
+    let end = samples.partition_point(|sample| timestamp(sample) <= maxt);
+    start..end.max(start)
+}
+
+fn sliced_counter_reset_header(
+    counter_reset_header: CounterResetHeader,
+    range: &Range<usize>,
+) -> CounterResetHeader {
+    match counter_reset_header {
+        _ if range.start == 0 => counter_reset_header,
+        CounterResetHeader::Gauge => CounterResetHeader::Gauge,
+        _ => CounterResetHeader::NotCounterReset,
+    }
+}
+
 fn read_chunk_type(input: &[u8]) -> IResult<&[u8], ChunkType> {
     alt((
         value(ChunkType::XOR, tag([1u8])),
         value(ChunkType::Histogram, tag([2u8])),
         value(ChunkType::FloatHistogram, tag([3u8])),
+        value(ChunkType::Aggr, tag([0xffu8])),
     ))(input)
 }
 
@@ -119,26 +325,29 @@ fn parse_chunk_data(
             Ok((remaining_input, Chunk::XOR(xor_chunk)))
         }
         ChunkType::Histogram => {
-            let (remaining_input, histogram_chunk) = read_histogram_chunk_data(chunk_data)?;
+            let (remaining_input, mut histogram_chunk) = read_histogram_chunk_data(chunk_data)?;
+            histogram_chunk.set_addr(addr);
             Ok((remaining_input, Chunk::Histogram(histogram_chunk)))
         }
         ChunkType::FloatHistogram => {
-            let (remaining_input, float_histogram_chunk) =
+            let (remaining_input, mut float_histogram_chunk) =
                 read_float_histogram_chunk_data(chunk_data)?;
+            float_histogram_chunk.set_addr(addr);
             Ok((
                 remaining_input,
                 Chunk::FloatHistogram(float_histogram_chunk),
             ))
         }
+        ChunkType::Aggr => {
+            let (remaining_input, mut aggr_chunk) = read_aggr_chunk_data(chunk_data)?;
+            aggr_chunk.set_addr(addr);
+            Ok((remaining_input, Chunk::Aggr(aggr_chunk)))
+        }
     }
 }
 
-/// Reads a chunk from the input data.
-///
-/// Returns the remaining input data and the chunk.
-pub fn read_chunk(input: &[u8]) -> IResult<&[u8], Chunk> {
-    let addr = input.as_ptr();
-
+/// Reads the header, the data and the checksum of a chunk, without parsing the data.
+pub(crate) fn read_raw_chunk(input: &[u8]) -> IResult<&[u8], (ChunkType, &[u8])> {
     let (remaining_input, (consumed_header_bytes, chunk_header)) =
         consumed(read_chunk_header)(input)?;
 
@@ -169,9 +378,19 @@ pub fn read_chunk(input: &[u8]) -> IResult<&[u8], Chunk> {
         chunk_crc32c,
     )?;
 
+    Ok((remaining_input, (chunk_header.chunk_type, chunk_data)))
+}
+
+/// Reads a chunk from the input data.
+///
+/// Returns the remaining input data and the chunk.
+pub fn read_chunk(input: &[u8]) -> IResult<&[u8], Chunk> {
+    let addr = input.as_ptr();
+
+    let (remaining_input, (chunk_type, chunk_data)) = read_raw_chunk(input)?;
+
     // Finaly, we can parse the chunk data
-    let (remaining_chunk_data_input, chunk) =
-        parse_chunk_data(addr, chunk_header.chunk_type, chunk_data)?;
+    let (remaining_chunk_data_input, chunk) = parse_chunk_data(addr, chunk_type, chunk_data)?;
 
     // https://github.com/prometheus/prometheus/pull/14854
     if !remaining_chunk_data_input.is_empty() {
@@ -185,3 +404,158 @@ pub fn read_chunk(input: &[u8]) -> IResult<&[u8], Chunk> {
 
     Ok((remaining_input, chunk))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        histogram::{FloatHistogramSample, HistogramSample},
+        native_histogram::Histogram,
+    };
+
+    fn xor_chunk(timestamps: &[i64]) -> Chunk {
+        Chunk::new_xor(
+            timestamps
+                .iter()
+                .map(|&timestamp| XORSample {
+                    timestamp,
+                    value: timestamp as f64,
+                })
+                .collect(),
+        )
+    }
+
+    fn timestamps(chunk: &Chunk) -> Vec<i64> {
+        match chunk {
+            Chunk::XOR(xor_chunk) => xor_chunk.samples().iter().map(|s| s.timestamp).collect(),
+            Chunk::Histogram(histogram_chunk) => histogram_chunk
+                .samples()
+                .iter()
+                .map(|s| s.timestamp)
+                .collect(),
+            Chunk::FloatHistogram(float_histogram_chunk) => float_histogram_chunk
+                .samples()
+                .iter()
+                .map(|s| s.timestamp)
+                .collect(),
+            Chunk::Aggr(aggr_chunk) => aggr_chunk.chunks()[0]
+                .iter()
+                .flat_map(|xor_chunk| xor_chunk.samples().iter().map(|s| s.timestamp))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_slice() {
+        let chunk = xor_chunk(&[10, 20, 30, 40]);
+        assert_eq!(chunk.time_range(), Some((10, 40)));
+        assert_eq!(xor_chunk(&[]).time_range(), None);
+
+        assert_eq!(
+            timestamps(&xor_chunk(&[10, 20, 30, 40]).slice(0, 100).unwrap()),
+            [10, 20, 30, 40]
+        );
+        assert_eq!(
+            timestamps(&xor_chunk(&[10, 20, 30, 40]).slice(20, 30).unwrap()),
+            [20, 30]
+        );
+        assert_eq!(
+            timestamps(&xor_chunk(&[10, 20, 30, 40]).slice(15, 35).unwrap()),
+            [20, 30]
+        );
+        assert_eq!(
+            timestamps(&xor_chunk(&[10, 20, 30, 40]).slice(40, 40).unwrap()),
+            [40]
+        );
+        assert!(xor_chunk(&[10, 20, 30, 40]).slice(21, 29).is_none());
+        assert!(xor_chunk(&[10, 20, 30, 40]).slice(41, 100).is_none());
+        assert!(xor_chunk(&[10, 20, 30, 40]).slice(30, 20).is_none());
+        assert!(xor_chunk(&[]).slice(0, 100).is_none());
+
+        // The sliced chunk can be written
+        let mut buffer: Vec<u8> = Vec::new();
+        let sliced = xor_chunk(&[10, 20, 30, 40]).slice(15, 100).unwrap();
+        sliced.write(&mut buffer).unwrap();
+        let (_, parsed) = read_chunk(&buffer).unwrap();
+        assert_eq!(parsed, sliced);
+
+        let chunks = slice_chunks(
+            vec![
+                xor_chunk(&[10, 20]),
+                xor_chunk(&[30, 40]),
+                xor_chunk(&[50, 60]),
+            ],
+            20,
+            45,
+        );
+        assert_eq!(
+            chunks.iter().map(timestamps).collect::<Vec<_>>(),
+            [vec![20], vec![30, 40]]
+        );
+
+        let aggr_chunk = Chunk::Aggr(AggrChunk::new([
+            xor_chunk(&[10, 20, 30]).as_xor(),
+            None,
+            None,
+            None,
+            xor_chunk(&[5, 10, 20, 30, 30]).as_xor(),
+        ]));
+        assert_eq!(aggr_chunk.time_range(), Some((5, 30)));
+        let sliced = aggr_chunk.slice(10, 25).unwrap();
+        assert_eq!(sliced.time_range(), Some((10, 20)));
+        assert_eq!(timestamps(&sliced), [10, 20]);
+    }
+
+    #[test]
+    fn test_slice_histograms() {
+        let samples = (0..4)
+            .map(|i| HistogramSample {
+                timestamp: i * 10,
+                histogram: Histogram {
+                    count: i as u64,
+                    zero_count: i as u64,
+                    ..Default::default()
+                },
+            })
+            .collect::<Vec<_>>();
+
+        let chunk = Chunk::Histogram(HistogramChunk::new(
+            samples.clone(),
+            CounterResetHeader::CounterReset,
+        ));
+        let sliced = chunk.slice(0, 15).unwrap();
+        assert_eq!(timestamps(&sliced), [0, 10]);
+        assert_eq!(
+            sliced.counter_reset_header(),
+            Some(CounterResetHeader::CounterReset)
+        );
+
+        let chunk = Chunk::Histogram(HistogramChunk::new(
+            samples.clone(),
+            CounterResetHeader::CounterReset,
+        ));
+        let sliced = chunk.slice(5, 25).unwrap();
+        assert_eq!(timestamps(&sliced), [10, 20]);
+        assert_eq!(
+            sliced.counter_reset_header(),
+            Some(CounterResetHeader::NotCounterReset)
+        );
+
+        let chunk = Chunk::FloatHistogram(FloatHistogramChunk::new(
+            samples
+                .iter()
+                .map(|sample| FloatHistogramSample {
+                    timestamp: sample.timestamp,
+                    histogram: sample.histogram.to_float(),
+                })
+                .collect(),
+            CounterResetHeader::Gauge,
+        ));
+        let sliced = chunk.slice(25, 100).unwrap();
+        assert_eq!(timestamps(&sliced), [30]);
+        assert_eq!(
+            sliced.counter_reset_header(),
+            Some(CounterResetHeader::Gauge)
+        );
+    }
+}
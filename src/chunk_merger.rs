@@ -0,0 +1,154 @@
+use crate::{
+    chunk::Chunk,
+    errors::RustyChunkEncError,
+    series_chunker::SeriesChunker,
+    xor::{XORChunk, XORSample},
+};
+
+/// What to keep when several samples have the same timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Keep the sample of the first chunk.
+    #[default]
+    KeepFirst,
+    /// Keep the sample of the last chunk.
+    KeepLast,
+    /// Fail when the samples have different values.
+    ///
+    /// Samples with the same value, bitwise, are still deduplicated.
+    Error,
+}
+
+/// Merges the samples of overlapping XOR chunks of one series.
+///
+/// The samples are sorted by timestamp, and the samples with the same timestamp
+/// are deduplicated according to the policy. The order of the chunks decides
+/// which sample is the first one.
+pub fn merge_xor_samples(
+    chunks: &[XORChunk],
+    policy: DuplicatePolicy,
+) -> Result<Vec<XORSample>, RustyChunkEncError> {
+    let mut samples = chunks
+        .iter()
+        .flat_map(|chunk| chunk.samples().iter().cloned())
+        .collect::<Vec<_>>();
+    // The sort is stable, so the samples with the same timestamp stay in the chunks order.
+    samples.sort_by_key(|sample| sample.timestamp);
+
+    let mut merged: Vec<XORSample> = Vec::with_capacity(samples.len());
+    for sample in samples {
+        match merged.last_mut() {
+            Some(last) if last.timestamp == sample.timestamp => match policy {
+                DuplicatePolicy::KeepFirst => {}
+                DuplicatePolicy::KeepLast => *last = sample,
+                DuplicatePolicy::Error => {
+                    if *last != sample {
+                        return Err(RustyChunkEncError::ConflictingSamples(sample.timestamp));
+                    }
+                }
+            },
+            _ => merged.push(sample),
+        }
+    }
+    Ok(merged)
+}
+
+/// Merges overlapping XOR chunks of one series into new chunks.
+///
+/// Like Prometheus' vertical compaction, the samples are merged with
+/// [`merge_xor_samples`], and cut again into chunks by the chunker.
+pub fn merge_xor_chunks(
+    chunks: &[XORChunk],
+    policy: DuplicatePolicy,
+    chunker: SeriesChunker,
+) -> Result<Vec<Chunk>, RustyChunkEncError> {
+    chunker.chunks(merge_xor_samples(chunks, policy)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(samples: &[(i64, f64)]) -> XORChunk {
+        XORChunk::new(
+            samples
+                .iter()
+                .map(|&(timestamp, value)| XORSample { timestamp, value })
+                .collect(),
+        )
+    }
+
+    fn pairs(samples: &[XORSample]) -> Vec<(i64, f64)> {
+        samples
+            .iter()
+            .map(|sample| (sample.timestamp, sample.value))
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_xor_samples() {
+        let chunks = [
+            chunk(&[(1, 1.0), (3, 3.0), (5, 5.0)]),
+            chunk(&[(2, 2.0), (3, 30.0), (4, 4.0), (6, 6.0)]),
+            chunk(&[(5, 5.0), (7, 7.0)]),
+        ];
+
+        let merged = merge_xor_samples(&chunks, DuplicatePolicy::KeepFirst).unwrap();
+        assert_eq!(
+            pairs(&merged),
+            [
+                (1, 1.0),
+                (2, 2.0),
+                (3, 3.0),
+                (4, 4.0),
+                (5, 5.0),
+                (6, 6.0),
+                (7, 7.0)
+            ]
+        );
+
+        let merged = merge_xor_samples(&chunks, DuplicatePolicy::KeepLast).unwrap();
+        assert_eq!(pairs(&merged[2..3]), [(3, 30.0)]);
+        assert_eq!(merged.len(), 7);
+
+        match merge_xor_samples(&chunks, DuplicatePolicy::Error) {
+            Err(RustyChunkEncError::ConflictingSamples(3)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Identical samples, including staleness markers, aren't conflicts.
+        let chunks = [
+            XORChunk::new(vec![XORSample::stale(1), XORSample::stale(2)]),
+            XORChunk::new(vec![XORSample::stale(2)]),
+        ];
+        let merged = merge_xor_samples(&chunks, DuplicatePolicy::Error).unwrap();
+        assert_eq!(merged, [XORSample::stale(1), XORSample::stale(2)]);
+
+        assert!(merge_xor_samples(&[], DuplicatePolicy::Error)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_merge_xor_chunks() {
+        // Two copies of the same 4 hours of data, shifted by 30 minutes.
+        let samples = |start: i64| {
+            (0..960)
+                .map(|i| (start + i * 15000, i as f64))
+                .collect::<Vec<_>>()
+        };
+        let chunks = [chunk(&samples(0)), chunk(&samples(1800000))];
+
+        let merged =
+            merge_xor_chunks(&chunks, DuplicatePolicy::KeepLast, SeriesChunker::new()).unwrap();
+        let merged_samples = merged
+            .into_iter()
+            .flat_map(|chunk| chunk.as_xor().unwrap().samples().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(merged_samples.len(), 960 + 120);
+        assert!(merged_samples
+            .windows(2)
+            .all(|pair| pair[0].timestamp < pair[1].timestamp));
+        assert_eq!(merged_samples[120].value, 0.0);
+    }
+}
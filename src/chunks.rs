@@ -68,37 +68,35 @@ impl PartialEq for ChunksDiskFormat {
     }
 }
 
-fn read_chunks_disk_format(input: &[u8]) -> IResult<&[u8], ChunksDiskFormat> {
-    let (remaining_input, (_, mut chunks_disk_format)) = tuple((
+/// Reads the magic number, the version and the padding of the chunks disk format.
+pub(crate) fn read_chunks_disk_format_header(input: &[u8]) -> IResult<&[u8], ()> {
+    let (remaining_input, _) = tuple((
         // Chunks on disk start with 0x85BD40DD
         tag([0x85, 0xBD, 0x40, 0xDD]),
-        read_version_one,
-    ))(input)?;
-
-    chunks_disk_format.set_addr(input.as_ptr());
-
-    Ok((remaining_input, chunks_disk_format))
-}
-
-fn read_version_one(input: &[u8]) -> IResult<&[u8], ChunksDiskFormat> {
-    let (remaining_input, (_, _, chunks)) = tuple((
         // Read the version byte, that is 1
         tag([1u8]),
         // 3 bytes of 0 for padding
         tag([0u8; 3]),
+    ))(input)?;
+    Ok((remaining_input, ()))
+}
+
+fn read_chunks_disk_format(input: &[u8]) -> IResult<&[u8], ChunksDiskFormat> {
+    let (remaining_input, (_, chunks)) = tuple((
+        read_chunks_disk_format_header,
         // Chunks follow each other
         many1(read_chunk),
     ))(input)?;
 
-    Ok((
-        remaining_input,
-        ChunksDiskFormat {
-            version: 1,
-            chunks,
-            file_index: None,
-            addr: None,
-        },
-    ))
+    let mut chunks_disk_format = ChunksDiskFormat {
+        version: 1,
+        chunks,
+        file_index: None,
+        addr: None,
+    };
+    chunks_disk_format.set_addr(input.as_ptr());
+
+    Ok((remaining_input, chunks_disk_format))
 }
 
 /// Reads the chunks disk format from the input data.
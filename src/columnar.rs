@@ -0,0 +1,228 @@
+use crate::{
+    chunk::{read_raw_chunk, ChunkType},
+    chunks::read_chunks_disk_format_header,
+    errors::RustyChunkEncError,
+    xor::XORIterator,
+};
+
+/// Decodes XOR chunk data, and appends its timestamps and values to the buffers.
+///
+/// It takes the same input as [`crate::xor::read_xor_chunk_data`].
+/// The buffers are only grown when they are too small, so they can be
+/// cleared and reused between chunks without new allocations.
+///
+/// Returns the number of samples appended. On errors, the buffers are left unchanged.
+pub fn decode_xor_chunk_data(
+    input: &[u8],
+    timestamps: &mut Vec<i64>,
+    values: &mut Vec<f64>,
+) -> Result<usize, RustyChunkEncError> {
+    let previous_len = timestamps.len();
+    if let Some(&[high, low]) = input.get(..2) {
+        let num_samples = u16::from_be_bytes([high, low]) as usize;
+        timestamps.reserve(num_samples);
+        values.reserve(num_samples);
+    }
+
+    let mut iterator = XORIterator::new(input);
+    for (timestamp, value) in &mut iterator {
+        timestamps.push(timestamp);
+        values.push(value);
+    }
+    if let Some(error) = iterator.error.take() {
+        timestamps.truncate(previous_len);
+        values.truncate(previous_len);
+        return Err(error);
+    }
+    Ok(timestamps.len() - previous_len)
+}
+
+/// Decodes a XOR chunk, with its header and checksum, and appends its timestamps
+/// and values to the buffers.
+///
+/// It takes the same input as [`crate::chunk::read_chunk`], and returns the
+/// remaining input and the number of samples appended.
+/// Other chunk types are errors. On errors, the buffers are left unchanged.
+pub fn decode_chunk<'a>(
+    input: &'a [u8],
+    timestamps: &mut Vec<i64>,
+    values: &mut Vec<f64>,
+) -> Result<(&'a [u8], usize), RustyChunkEncError> {
+    let (remaining_input, (chunk_type, chunk_data)) = read_raw_chunk(input)?;
+    if chunk_type != ChunkType::XOR {
+        return Err(RustyChunkEncError::ParsingError(format!(
+            "expected a XOR chunk, got {:?}",
+            chunk_type
+        )));
+    }
+    let num_samples = decode_xor_chunk_data(chunk_data, timestamps, values)?;
+    Ok((remaining_input, num_samples))
+}
+
+/// Decodes the XOR chunks of a chunks disk format, and appends their timestamps
+/// and values to the buffers.
+///
+/// It takes the same input as [`crate::chunks::read_chunks`]. The end of each
+/// chunk in the buffers is appended to `chunk_ends`, as chunks often belong to
+/// different series. The other chunk types are skipped.
+///
+/// Returns the number of samples appended. On errors, the buffers are left unchanged.
+pub fn decode_chunks(
+    input: &[u8],
+    timestamps: &mut Vec<i64>,
+    values: &mut Vec<f64>,
+    chunk_ends: &mut Vec<usize>,
+) -> Result<usize, RustyChunkEncError> {
+    let previous_len = timestamps.len();
+    let previous_chunk_ends_len = chunk_ends.len();
+
+    let result = decode_chunks_without_rollback(input, timestamps, values, chunk_ends);
+    if result.is_err() {
+        timestamps.truncate(previous_len);
+        values.truncate(previous_len);
+        chunk_ends.truncate(previous_chunk_ends_len);
+    }
+    result.map(|_| timestamps.len() - previous_len)
+}
+
+fn decode_chunks_without_rollback(
+    input: &[u8],
+    timestamps: &mut Vec<i64>,
+    values: &mut Vec<f64>,
+    chunk_ends: &mut Vec<usize>,
+) -> Result<(), RustyChunkEncError> {
+    let (mut remaining_input, _) = read_chunks_disk_format_header(input)?;
+    let mut num_chunks = 0;
+    loop {
+        let (new_remaining_input, (chunk_type, chunk_data)) = match read_raw_chunk(remaining_input)
+        {
+            Ok(result) => result,
+            // Like `read_chunks`, the chunks stop at the first invalid chunk.
+            Err(nom::Err::Error(_)) if num_chunks > 0 => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+        remaining_input = new_remaining_input;
+        num_chunks += 1;
+
+        if chunk_type == ChunkType::XOR {
+            decode_xor_chunk_data(chunk_data, timestamps, values)?;
+            chunk_ends.push(timestamps.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk::Chunk,
+        chunks::{read_chunks, ChunksDiskFormat},
+        histogram::{CounterResetHeader, HistogramChunk, HistogramSample},
+        native_histogram::Histogram,
+        xor::{XORChunk, XORSample},
+    };
+
+    fn samples(start: i64, count: i64) -> Vec<XORSample> {
+        (0..count)
+            .map(|i| XORSample {
+                timestamp: start + i * 1000,
+                value: (i * i) as f64 / 3.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_xor_chunk_data() {
+        let mut buffer: Vec<u8> = Vec::new();
+        XORChunk::new(samples(0, 100)).write(&mut buffer).unwrap();
+
+        let mut timestamps = Vec::new();
+        let mut values = Vec::new();
+        assert_eq!(
+            decode_xor_chunk_data(&buffer, &mut timestamps, &mut values).unwrap(),
+            100
+        );
+        assert_eq!(timestamps, (0..100).map(|i| i * 1000).collect::<Vec<_>>());
+        assert_eq!(values[99], 9801.0 / 3.0);
+
+        // Appends, then reuses the buffers without allocating.
+        decode_xor_chunk_data(&buffer, &mut timestamps, &mut values).unwrap();
+        assert_eq!(timestamps.len(), 200);
+        let capacity = (timestamps.capacity(), values.capacity());
+        timestamps.clear();
+        values.clear();
+        decode_xor_chunk_data(&buffer, &mut timestamps, &mut values).unwrap();
+        assert_eq!(capacity, (timestamps.capacity(), values.capacity()));
+
+        // Errors leave the buffers unchanged.
+        assert!(
+            decode_xor_chunk_data(&buffer[..buffer.len() / 2], &mut timestamps, &mut values)
+                .is_err()
+        );
+        assert_eq!(timestamps.len(), 100);
+        assert_eq!(values.len(), 100);
+    }
+
+    #[test]
+    fn test_decode_chunks() {
+        let chunks = ChunksDiskFormat::new(
+            vec![
+                Chunk::new_xor(samples(0, 10)),
+                Chunk::Histogram(HistogramChunk::new(
+                    vec![HistogramSample {
+                        timestamp: 0,
+                        histogram: Histogram::default(),
+                    }],
+                    CounterResetHeader::Unknown,
+                )),
+                Chunk::new_xor(samples(5000, 20)),
+            ],
+            None,
+        );
+        let mut buffer: Vec<u8> = Vec::new();
+        chunks.write(&mut buffer).unwrap();
+
+        let mut timestamps = Vec::new();
+        let mut values = Vec::new();
+        let mut chunk_ends = Vec::new();
+        assert_eq!(
+            decode_chunks(&buffer, &mut timestamps, &mut values, &mut chunk_ends).unwrap(),
+            30
+        );
+        assert_eq!(chunk_ends, [10, 30]);
+
+        let (_, parsed) = read_chunks(&buffer, None).unwrap();
+        let expected = parsed
+            .chunks()
+            .iter()
+            .filter_map(|chunk| match chunk {
+                Chunk::XOR(xor_chunk) => Some(xor_chunk.samples()),
+                _ => None,
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            timestamps,
+            expected.iter().map(|s| s.timestamp).collect::<Vec<_>>()
+        );
+        assert_eq!(values, expected.iter().map(|s| s.value).collect::<Vec<_>>());
+
+        // A single chunk
+        let mut chunk_buffer: Vec<u8> = Vec::new();
+        chunks.chunks()[2].write(&mut chunk_buffer).unwrap();
+        let (remaining, count) = decode_chunk(&chunk_buffer, &mut timestamps, &mut values).unwrap();
+        assert!(remaining.is_empty());
+        assert_eq!(count, 20);
+
+        let mut chunk_buffer: Vec<u8> = Vec::new();
+        chunks.chunks()[1].write(&mut chunk_buffer).unwrap();
+        assert!(decode_chunk(&chunk_buffer, &mut timestamps, &mut values).is_err());
+
+        // Errors leave the buffers unchanged.
+        let len = timestamps.len();
+        buffer[20] ^= 0xff;
+        assert!(decode_chunks(&buffer, &mut timestamps, &mut values, &mut chunk_ends).is_err());
+        assert_eq!(timestamps.len(), len);
+        assert_eq!(chunk_ends, [10, 30]);
+    }
+}
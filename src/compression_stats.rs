@@ -0,0 +1,294 @@
+use std::ops::AddAssign;
+
+use crate::{chunk::Chunk, chunks::ChunksDiskFormat, errors::RustyChunkEncError, xor::XORIterator};
+
+/// The number of bits of the delta of delta of each `varbit_ts` bucket.
+pub const VARBIT_TS_BUCKETS: [u8; 5] = [0, 14, 17, 20, 64];
+
+/// Compression statistics of XOR chunks.
+///
+/// The bits of the chunks are split between the header (the number of samples),
+/// the timestamps, the values, and the padding at the end of the chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompressionStats {
+    pub num_chunks: usize,
+    pub num_samples: usize,
+    pub total_bits: usize,
+    pub header_bits: usize,
+    pub timestamp_bits: usize,
+    pub value_bits: usize,
+    pub padding_bits: usize,
+    /// How often each of the [`VARBIT_TS_BUCKETS`] is used, from the third sample of each chunk.
+    pub timestamp_buckets: [usize; 5],
+    /// Values identical to the previous value, encoded in a single bit.
+    pub identical_values: usize,
+    /// Values encoded within the leading and trailing bits window of the previous value.
+    pub reused_windows: usize,
+    /// Values encoded with a new leading and trailing bits window.
+    pub new_windows: usize,
+}
+
+impl CompressionStats {
+    /// Returns the average number of bits per sample, including the headers and padding.
+    pub fn bits_per_sample(&self) -> f64 {
+        ratio(self.total_bits, self.num_samples)
+    }
+
+    /// Returns the average number of timestamp bits per sample.
+    pub fn timestamp_bits_per_sample(&self) -> f64 {
+        ratio(self.timestamp_bits, self.num_samples)
+    }
+
+    /// Returns the average number of value bits per sample.
+    pub fn value_bits_per_sample(&self) -> f64 {
+        ratio(self.value_bits, self.num_samples)
+    }
+
+    /// Returns the share of values identical to the previous value in the chunk.
+    pub fn identical_values_ratio(&self) -> f64 {
+        ratio(
+            self.identical_values,
+            self.identical_values + self.reused_windows + self.new_windows,
+        )
+    }
+
+    /// Returns the share of changed values that reuse the previous leading and trailing bits window.
+    pub fn reused_windows_ratio(&self) -> f64 {
+        ratio(self.reused_windows, self.reused_windows + self.new_windows)
+    }
+
+    /// Counts the bits of a XOR sample after the first one.
+    ///
+    /// The `varbit_xor` branch is found from the number of bits of the value
+    /// and its window: one bit for identical values, then 2 bits for a reused
+    /// window, or 13 bits for a new window, before the significant bits.
+    pub(crate) fn add_xor_sample(
+        &mut self,
+        timestamp_bits: usize,
+        is_varbit_ts: bool,
+        value_bits: usize,
+        leading_bits_count: u8,
+        trailing_bits_count: u8,
+    ) {
+        self.timestamp_bits += timestamp_bits;
+        if is_varbit_ts {
+            let bucket = match timestamp_bits {
+                1 => 0,
+                16 => 1,
+                20 => 2,
+                24 => 3,
+                _ => 4,
+            };
+            self.timestamp_buckets[bucket] += 1;
+        }
+
+        self.value_bits += value_bits;
+        let significant_bits = 64 - leading_bits_count as usize - trailing_bits_count as usize;
+        if value_bits == 1 {
+            self.identical_values += 1;
+        } else if value_bits == 2 + significant_bits {
+            self.reused_windows += 1;
+        } else {
+            self.new_windows += 1;
+        }
+    }
+}
+
+impl AddAssign for CompressionStats {
+    fn add_assign(&mut self, other: Self) {
+        self.num_chunks += other.num_chunks;
+        self.num_samples += other.num_samples;
+        self.total_bits += other.total_bits;
+        self.header_bits += other.header_bits;
+        self.timestamp_bits += other.timestamp_bits;
+        self.value_bits += other.value_bits;
+        self.padding_bits += other.padding_bits;
+        for (bucket, other_bucket) in self
+            .timestamp_buckets
+            .iter_mut()
+            .zip(other.timestamp_buckets)
+        {
+            *bucket += other_bucket;
+        }
+        self.identical_values += other.identical_values;
+        self.reused_windows += other.reused_windows;
+        self.new_windows += other.new_windows;
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+/// Computes the compression statistics of XOR chunk data.
+///
+/// It takes the same input as [`crate::xor::read_xor_chunk_data`],
+/// and decodes the chunk with a [`XORIterator`] that counts the bits of each part.
+pub fn xor_chunk_data_stats(input: &[u8]) -> Result<CompressionStats, RustyChunkEncError> {
+    let mut iterator = XORIterator::new(input).with_stats();
+    for _ in &mut iterator {}
+    if let Some(error) = iterator.error {
+        return Err(error);
+    }
+
+    let mut stats = iterator.stats.map(|stats| *stats).unwrap_or_default();
+    // The samples after the first one are padded to a byte boundary.
+    stats.padding_bits = (8 - iterator.reader.bit_position() % 8) % 8;
+    stats.total_bits =
+        stats.header_bits + stats.timestamp_bits + stats.value_bits + stats.padding_bits;
+    Ok(stats)
+}
+
+/// Computes the compression statistics of a chunk.
+///
+/// The statistics of an aggregate chunk are the sum of the statistics of its chunks.
+/// Returns None for histogram and float histogram chunks.
+pub fn chunk_compression_stats(
+    chunk: &Chunk,
+) -> Result<Option<CompressionStats>, RustyChunkEncError> {
+    match chunk {
+        Chunk::XOR(xor_chunk) => {
+            let mut buffer: Vec<u8> = Vec::new();
+            xor_chunk.write(&mut buffer)?;
+            Ok(Some(xor_chunk_data_stats(&buffer)?))
+        }
+        Chunk::Aggr(aggr_chunk) => {
+            let mut stats = CompressionStats::default();
+            for xor_chunk in aggr_chunk.chunks().iter().flatten() {
+                let mut buffer: Vec<u8> = Vec::new();
+                xor_chunk.write(&mut buffer)?;
+                stats += xor_chunk_data_stats(&buffer)?;
+            }
+            Ok(Some(stats))
+        }
+        Chunk::Histogram(_) | Chunk::FloatHistogram(_) => Ok(None),
+    }
+}
+
+/// Computes the compression statistics of all the XOR chunks of a chunks disk format.
+pub fn chunks_compression_stats(
+    chunks_disk_format: &ChunksDiskFormat,
+) -> Result<CompressionStats, RustyChunkEncError> {
+    let mut stats = CompressionStats::default();
+    for chunk in chunks_disk_format.chunks() {
+        if let Some(chunk_stats) = chunk_compression_stats(chunk)? {
+            stats += chunk_stats;
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        histogram::{CounterResetHeader, HistogramChunk, HistogramSample},
+        native_histogram::Histogram,
+        xor::{XORChunk, XORSample},
+    };
+
+    fn samples(samples: &[(i64, f64)]) -> Vec<XORSample> {
+        samples
+            .iter()
+            .map(|&(timestamp, value)| XORSample { timestamp, value })
+            .collect()
+    }
+
+    #[test]
+    fn test_xor_chunk_data_stats() {
+        let chunk = XORChunk::new(samples(&[
+            (1000, 1.0),
+            (2000, 1.0),
+            (3000, 2.0),
+            (4000, 2.0),
+            (5001, 3.0),
+            (6001, 3.0),
+            (106001, 4.0),
+            (206001, 5.0),
+        ]));
+        let mut buffer: Vec<u8> = Vec::new();
+        chunk.write(&mut buffer).unwrap();
+
+        let stats = xor_chunk_data_stats(&buffer).unwrap();
+        assert_eq!(stats.num_chunks, 1);
+        assert_eq!(stats.num_samples, 8);
+        assert_eq!(stats.total_bits, buffer.len() * 8);
+        assert_eq!(stats.header_bits, 16);
+        // A 2 bytes varint, and a 2 bytes uvarint
+        // then 3 zeros, two 14 bits buckets, and one 20 bits bucket.
+        assert_eq!(stats.timestamp_buckets, [3, 2, 0, 1, 0]);
+        assert_eq!(stats.timestamp_bits, 16 + 16 + 3 + 2 * 16 + 24);
+        assert_eq!(stats.identical_values, 3);
+        assert_eq!(stats.reused_windows + stats.new_windows, 4);
+        assert!(stats.padding_bits < 8);
+        assert_eq!(stats.bits_per_sample(), (buffer.len() * 8) as f64 / 8.0);
+        assert_eq!(stats.identical_values_ratio(), 3.0 / 7.0);
+
+        // Empty and single sample chunks
+        let stats = xor_chunk_data_stats(&[0x00, 0x00]).unwrap();
+        assert_eq!(stats.total_bits, 16);
+        assert_eq!(stats.bits_per_sample(), 0.0);
+        let mut buffer: Vec<u8> = Vec::new();
+        XORChunk::new(samples(&[(1, 1.0)]))
+            .write(&mut buffer)
+            .unwrap();
+        let stats = xor_chunk_data_stats(&buffer).unwrap();
+        assert_eq!(stats.timestamp_bits, 8);
+        assert_eq!(stats.value_bits, 64);
+        assert_eq!(stats.total_bits, buffer.len() * 8);
+
+        // 1 to 2 needs a new window, 2 to 3 too, and 3 to 2 reuses it.
+        let mut buffer: Vec<u8> = Vec::new();
+        XORChunk::new(samples(&[(1, 1.0), (2, 2.0), (3, 3.0), (4, 2.0)]))
+            .write(&mut buffer)
+            .unwrap();
+        let stats = xor_chunk_data_stats(&buffer).unwrap();
+        assert_eq!(stats.new_windows, 2);
+        assert_eq!(stats.reused_windows, 1);
+        assert_eq!(stats.total_bits, buffer.len() * 8);
+
+        assert!(xor_chunk_data_stats(&[0x00, 0x02, 0x02]).is_err());
+    }
+
+    #[test]
+    fn test_chunks_compression_stats() {
+        let series = (0..100)
+            .map(|i| (i * 15000, (i / 10) as f64))
+            .collect::<Vec<_>>();
+        let chunks = ChunksDiskFormat::new(
+            vec![
+                Chunk::new_xor(samples(&series[..50])),
+                Chunk::new_xor(samples(&series[50..])),
+                Chunk::Histogram(HistogramChunk::new(
+                    vec![HistogramSample {
+                        timestamp: 0,
+                        histogram: Histogram::default(),
+                    }],
+                    CounterResetHeader::Unknown,
+                )),
+            ],
+            None,
+        );
+
+        assert!(chunk_compression_stats(&chunks.chunks()[2])
+            .unwrap()
+            .is_none());
+        let first = chunk_compression_stats(&chunks.chunks()[0])
+            .unwrap()
+            .unwrap();
+        let stats = chunks_compression_stats(&chunks).unwrap();
+        assert_eq!(stats.num_chunks, 2);
+        assert_eq!(stats.num_samples, 100);
+        assert_eq!(stats.timestamp_buckets, [96, 0, 0, 0, 0]);
+        assert_eq!(stats.identical_values, 90);
+        let second = chunk_compression_stats(&chunks.chunks()[1])
+            .unwrap()
+            .unwrap();
+        assert_eq!(stats.total_bits, first.total_bits + second.total_bits);
+        assert_eq!(stats.bits_per_sample(), stats.total_bits as f64 / 100.0);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::{
+    aggr_chunk::AggrChunk,
+    value::is_stale_nan,
+    xor::{XORChunk, XORSample},
+};
+
+/// Thanos' first downsampling resolution, 5 minutes in milliseconds.
+pub const RESOLUTION_5M: i64 = 5 * 60 * 1000;
+
+/// Thanos' second downsampling resolution, 1 hour in milliseconds.
+pub const RESOLUTION_1H: i64 = 60 * 60 * 1000;
+
+/// The number of aggregated samples Thanos aims for in each chunk.
+const SAMPLES_PER_CHUNK: usize = 120;
+
+/// Aggregates the samples of a downsampling window.
+#[derive(Debug)]
+struct Aggregator {
+    /// Samples of the batch.
+    total: usize,
+    /// Samples of the window.
+    count: usize,
+    sum: f64,
+    min: f64,
+    max: f64,
+    /// The counter has the counter resets applied.
+    counter: f64,
+    last: f64,
+}
+
+impl Aggregator {
+    fn new() -> Self {
+        let mut aggregator = Self {
+            total: 0,
+            count: 0,
+            sum: 0.0,
+            min: 0.0,
+            max: 0.0,
+            counter: 0.0,
+            last: 0.0,
+        };
+        aggregator.reset();
+        aggregator
+    }
+
+    fn reset(&mut self) {
+        self.count = 0;
+        self.sum = 0.0;
+        self.min = f64::MAX;
+        self.max = -f64::MAX;
+    }
+
+    fn add(&mut self, value: f64) {
+        if self.total > 0 {
+            if value < self.last {
+                // Counter reset, the counter starts again from 0.
+                self.counter += value;
+            } else {
+                self.counter += value - self.last;
+            }
+        } else {
+            self.counter = value;
+        }
+        self.last = value;
+
+        self.count += 1;
+        self.total += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+}
+
+/// The samples of the aggregates of an aggregate chunk.
+#[derive(Debug, Default)]
+struct AggrChunkBuilder {
+    count: Vec<XORSample>,
+    sum: Vec<XORSample>,
+    min: Vec<XORSample>,
+    max: Vec<XORSample>,
+    counter: Vec<XORSample>,
+}
+
+impl AggrChunkBuilder {
+    fn add(&mut self, timestamp: i64, aggregator: &Aggregator) {
+        let sample = |value: f64| XORSample { timestamp, value };
+        self.count.push(sample(aggregator.count as f64));
+        self.sum.push(sample(aggregator.sum));
+        self.min.push(sample(aggregator.min));
+        self.max.push(sample(aggregator.max));
+        self.counter.push(sample(aggregator.counter));
+    }
+
+    fn build(self) -> AggrChunk {
+        AggrChunk::new([
+            Some(XORChunk::new(self.count)),
+            Some(XORChunk::new(self.sum)),
+            Some(XORChunk::new(self.min)),
+            Some(XORChunk::new(self.max)),
+            Some(XORChunk::new(self.counter)),
+        ])
+    }
+}
+
+/// Returns the last timestamp of the downsampling window containing the timestamp.
+fn current_window(timestamp: i64, resolution: i64) -> i64 {
+    timestamp - timestamp % resolution + resolution - 1
+}
+
+/// Returns the number of chunks to produce, for chunks of about 120 aggregated samples.
+///
+/// Like Thanos, it assumes a raw resolution of one minute, adjusted by the actual
+/// number of samples.
+fn target_chunk_count(min_time: i64, max_time: i64, resolution: i64, count: usize) -> usize {
+    const RAW_RESOLUTION: i64 = 60 * 1000;
+    let max_samples = ((max_time - min_time) / resolution) as f64;
+    let expected_samples = ((max_time - min_time) / RAW_RESOLUTION) as f64 + 1.0;
+    let samples = ((max_samples / expected_samples) * count as f64) as usize;
+    samples.div_ceil(SAMPLES_PER_CHUNK)
+}
+
+/// Aggregates a batch of samples, and returns the timestamp of the last window.
+fn downsample_batch(samples: &[XORSample], resolution: i64, builder: &mut AggrChunkBuilder) -> i64 {
+    let mut aggregator = Aggregator::new();
+    let mut next_timestamp: Option<i64> = None;
+    let last_timestamp = samples[samples.len() - 1].timestamp;
+
+    for sample in samples {
+        match next_timestamp {
+            Some(next_timestamp) if sample.timestamp <= next_timestamp => {}
+            _ => {
+                if let Some(next_timestamp) = next_timestamp {
+                    builder.add(next_timestamp, &aggregator);
+                }
+                aggregator.reset();
+                // The window ends with the batch at the latest,
+                // so the chunks don't overlap.
+                next_timestamp =
+                    Some(current_window(sample.timestamp, resolution).min(last_timestamp));
+            }
+        }
+        aggregator.add(sample.value);
+    }
+
+    let next_timestamp = next_timestamp.unwrap_or(last_timestamp);
+    builder.add(next_timestamp, &aggregator);
+    next_timestamp
+}
+
+/// Downsamples raw XOR chunks of one series into Thanos aggregate chunks.
+///
+/// Like Thanos, the samples are aggregated per window of `resolution` milliseconds,
+/// and each aggregated sample has the last timestamp of its window.
+/// The counter aggregate also starts with the first raw sample and ends with
+/// the last raw sample of each chunk, so rates can be computed precisely.
+///
+/// The staleness markers and the samples that aren't sorted are skipped.
+pub fn downsample_raw(chunks: &[XORChunk], resolution: i64) -> Vec<AggrChunk> {
+    assert!(resolution > 0, "the resolution must be positive");
+
+    let mut samples: Vec<XORSample> = Vec::new();
+    for sample in chunks.iter().flat_map(|chunk| chunk.samples()) {
+        if is_stale_nan(sample.value) {
+            continue;
+        }
+        if let Some(last) = samples.last() {
+            if sample.timestamp < last.timestamp {
+                continue;
+            }
+        }
+        samples.push(sample.clone());
+    }
+    if samples.is_empty() {
+        return Vec::new();
+    }
+
+    let min_time = samples[0].timestamp;
+    let max_time = samples[samples.len() - 1].timestamp;
+    let num_chunks = target_chunk_count(min_time, max_time, resolution, samples.len()).max(1);
+    let batch_size = samples.len() / num_chunks + 1;
+
+    let mut aggr_chunks = Vec::with_capacity(num_chunks);
+    let mut samples: &[XORSample] = &samples;
+    while !samples.is_empty() {
+        let mut end = batch_size.min(samples.len());
+        // The batch must end with a complete window.
+        let window = current_window(samples[end - 1].timestamp, resolution);
+        while end < samples.len() && samples[end].timestamp <= window {
+            end += 1;
+        }
+        let (batch, remaining_samples) = samples.split_at(end);
+        samples = remaining_samples;
+
+        let mut builder = AggrChunkBuilder::default();
+        builder.counter.push(batch[0].clone());
+        let last_timestamp = downsample_batch(batch, resolution, &mut builder);
+        builder.counter.push(XORSample {
+            timestamp: last_timestamp,
+            value: batch[batch.len() - 1].value,
+        });
+        aggr_chunks.push(builder.build());
+    }
+    aggr_chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aggr_chunk::AggrType,
+        chunk::{read_chunk, Chunk},
+    };
+
+    fn values(aggr_chunk: &AggrChunk, aggr_type: AggrType) -> Vec<(i64, f64)> {
+        aggr_chunk
+            .get(aggr_type)
+            .unwrap()
+            .samples()
+            .iter()
+            .map(|sample| (sample.timestamp, sample.value))
+            .collect()
+    }
+
+    #[test]
+    fn test_downsample_raw() {
+        // A counter every minute for 15 minutes, reset after 10 minutes.
+        let mut samples = (0..15)
+            .map(|i| XORSample {
+                timestamp: i * 60000,
+                value: if i < 10 { 10.0 * i as f64 } else { i as f64 },
+            })
+            .collect::<Vec<_>>();
+        samples.insert(3, XORSample::stale(150000));
+        let chunks = [
+            XORChunk::new(samples[..8].to_vec()),
+            XORChunk::new(samples[8..].to_vec()),
+        ];
+
+        let aggr_chunks = downsample_raw(&chunks, RESOLUTION_5M);
+        assert_eq!(aggr_chunks.len(), 1);
+        let aggr_chunk = &aggr_chunks[0];
+
+        assert_eq!(
+            values(aggr_chunk, AggrType::Count),
+            [(299999, 5.0), (599999, 5.0), (840000, 5.0)]
+        );
+        assert_eq!(
+            values(aggr_chunk, AggrType::Sum),
+            [(299999, 100.0), (599999, 350.0), (840000, 60.0)]
+        );
+        assert_eq!(
+            values(aggr_chunk, AggrType::Min),
+            [(299999, 0.0), (599999, 50.0), (840000, 10.0)]
+        );
+        assert_eq!(
+            values(aggr_chunk, AggrType::Max),
+            [(299999, 40.0), (599999, 90.0), (840000, 14.0)]
+        );
+        assert_eq!(
+            values(aggr_chunk, AggrType::Counter),
+            [
+                (0, 0.0),
+                (299999, 40.0),
+                (599999, 90.0),
+                (840000, 104.0),
+                (840000, 14.0)
+            ]
+        );
+
+        // Written and read back as a chunk.
+        let chunk = Chunk::Aggr(downsample_raw(&chunks, RESOLUTION_5M).remove(0));
+        let mut buffer: Vec<u8> = Vec::new();
+        chunk.write(&mut buffer).unwrap();
+        let (_, parsed) = read_chunk(&buffer).unwrap();
+        assert!(matches!(parsed, Chunk::Aggr(_)));
+        assert_eq!(parsed, chunk);
+        assert_eq!(parsed.time_range(), Some((0, 840000)));
+
+        assert!(downsample_raw(&[], RESOLUTION_1H).is_empty());
+    }
+
+    #[test]
+    fn test_downsample_raw_chunks() {
+        // 10 days of samples every 15 seconds. They are 4 times denser than the
+        // assumed raw resolution, so the 240 windows are split in 8 chunks.
+        let samples = (0..57600)
+            .map(|i| XORSample {
+                timestamp: i * 15000,
+                value: i as f64,
+            })
+            .collect::<Vec<_>>();
+        let aggr_chunks = downsample_raw(&[XORChunk::new(samples)], RESOLUTION_1H);
+        assert_eq!(aggr_chunks.len(), 8);
+
+        let mut previous_max_time = i64::MIN;
+        let mut total = 0.0;
+        for aggr_chunk in &aggr_chunks {
+            let counts = values(aggr_chunk, AggrType::Count);
+            assert!(counts.len() <= 2 * SAMPLES_PER_CHUNK);
+            assert!(counts[0].0 > previous_max_time);
+            previous_max_time = counts[counts.len() - 1].0;
+            total += counts.iter().map(|(_, count)| count).sum::<f64>();
+        }
+        assert_eq!(total, 57600.0);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::aggr_chunk::AggrChunk;
+
+use super::uvarint_encoder::write_uvarint;
+
+impl AggrChunk {
+    /// Writes the aggregate chunk to the writer, like Thanos' `EncodeAggrChunk`.
+    pub fn write<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut buffer: Vec<u8> = Vec::new();
+        for chunk in self.chunks() {
+            match chunk {
+                // Missing aggregates have a zero length.
+                None => write_uvarint(0, writer)?,
+                Some(xor_chunk) => {
+                    buffer.clear();
+                    xor_chunk.write(&mut buffer)?;
+                    write_uvarint(buffer.len() as u64, writer)?;
+                    // The encoding of the aggregate, always XOR.
+                    writer.write_all(&[1u8])?;
+                    writer.write_all(&buffer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aggr_chunk::{read_aggr_chunk_data, AggrType},
+        xor::{XORChunk, XORSample},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_write_aggr_chunk() {
+        let xor_chunk = |value: f64| {
+            XORChunk::new(vec![
+                XORSample {
+                    timestamp: 299999,
+                    value,
+                },
+                XORSample {
+                    timestamp: 599999,
+                    value: value + 1.0,
+                },
+            ])
+        };
+        let chunk = AggrChunk::new([
+            Some(xor_chunk(10.0)),
+            Some(xor_chunk(100.0)),
+            None,
+            Some(xor_chunk(12.0)),
+            None,
+        ]);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        chunk.write(&mut buffer).unwrap();
+        let (remaining, parsed) = read_aggr_chunk_data(&buffer).unwrap();
+        assert!(remaining.is_empty());
+        assert_eq!(parsed, chunk);
+        assert!(parsed.get(AggrType::Min).is_none());
+        assert_eq!(parsed.get(AggrType::Max).unwrap().samples()[1].value, 13.0);
+
+        // The empty chunk is five zero lengths.
+        let mut buffer: Vec<u8> = Vec::new();
+        AggrChunk::default().write(&mut buffer).unwrap();
+        assert_eq!(buffer, [0, 0, 0, 0, 0]);
+
+        // Errors on unknown encodings and truncated data.
+        assert!(read_aggr_chunk_data(&[0x02, 0x02, 0x00, 0x00]).is_err());
+        assert!(read_aggr_chunk_data(&[0, 0, 0, 0]).is_err());
+    }
+}
@@ -19,6 +19,9 @@ fn write_chunk_type<W: std::io::Write>(
         ChunkType::FloatHistogram => {
             writer.write_all(&[3u8])?;
         }
+        ChunkType::Aggr => {
+            writer.write_all(&[0xffu8])?;
+        }
     }
     Ok(())
 }
@@ -45,6 +48,10 @@ impl Chunk {
                 write_chunk_type(ChunkType::FloatHistogram, &mut buffer)?;
                 float_histogram_chunk.write(&mut buffer)?;
             }
+            Chunk::Aggr(aggr_chunk) => {
+                write_chunk_type(ChunkType::Aggr, &mut buffer)?;
+                aggr_chunk.write(&mut buffer)?;
+            }
         }
 
         let chunk_len = buffer.len() as u64 - 1;
@@ -62,6 +69,11 @@ mod tests {
 
     use crate::{
         chunk::read_chunk,
+        histogram::{
+            CounterResetHeader, FloatHistogramChunk, FloatHistogramSample, HistogramChunk,
+            HistogramSample,
+        },
+        native_histogram::{FloatHistogram, Histogram, Span, CUSTOM_BUCKETS_SCHEMA},
         xor::{XORChunk, XORSample},
     };
 
@@ -135,4 +147,101 @@ mod tests {
         let error = read_chunk(&buffer).unwrap_err();
         assert!(error.to_string().contains("Verify"));
     }
+
+    #[test]
+    fn test_write_histogram_chunks() {
+        let spans = vec![
+            Span {
+                offset: -1,
+                length: 2,
+            },
+            Span {
+                offset: 3,
+                length: 1,
+            },
+        ];
+        let histograms = (0..100)
+            .map(|i| HistogramSample {
+                timestamp: 1234567890 + i * 15000,
+                histogram: Histogram {
+                    schema: 3,
+                    zero_threshold: 2f64.powi(-128),
+                    zero_count: i as u64,
+                    count: 7 * i as u64,
+                    sum: 0.25 * i as f64,
+                    positive_spans: spans.clone(),
+                    negative_spans: vec![],
+                    positive_buckets: vec![i, 2 * i, -i],
+                    negative_buckets: vec![],
+                    custom_values: vec![],
+                },
+            })
+            .collect::<Vec<_>>();
+        let float_histograms = histograms
+            .iter()
+            .map(|sample| FloatHistogramSample {
+                timestamp: sample.timestamp,
+                histogram: FloatHistogram {
+                    schema: 3,
+                    zero_threshold: 2f64.powi(-128),
+                    zero_count: sample.histogram.zero_count as f64,
+                    count: sample.histogram.count as f64,
+                    sum: sample.histogram.sum,
+                    positive_spans: spans.clone(),
+                    negative_spans: vec![],
+                    positive_buckets: vec![1.0, 3.0, 2.0]
+                        .into_iter()
+                        .map(|bucket| bucket * sample.histogram.zero_count as f64)
+                        .collect(),
+                    negative_buckets: vec![],
+                    custom_values: vec![],
+                },
+            })
+            .collect::<Vec<_>>();
+
+        let mut test_cases = generate_random_test_data(42, 2);
+        test_cases.insert(
+            1,
+            Chunk::Histogram(HistogramChunk::new(
+                histograms,
+                CounterResetHeader::NotCounterReset,
+            )),
+        );
+        test_cases.push(Chunk::FloatHistogram(FloatHistogramChunk::new(
+            float_histograms,
+            CounterResetHeader::Unknown,
+        )));
+        // Native histograms with custom buckets
+        test_cases.push(Chunk::Histogram(HistogramChunk::new(
+            vec![HistogramSample {
+                timestamp: 1234567890,
+                histogram: Histogram {
+                    schema: CUSTOM_BUCKETS_SCHEMA,
+                    count: 3,
+                    sum: 4.2,
+                    positive_spans: vec![Span {
+                        offset: 0,
+                        length: 3,
+                    }],
+                    positive_buckets: vec![1, 0, 0],
+                    custom_values: vec![0.1, 0.5, 1.5],
+                    ..Default::default()
+                },
+            }],
+            CounterResetHeader::Unknown,
+        )));
+
+        let mut buffer: Vec<u8> = Vec::new();
+        for test_case in &test_cases {
+            test_case.write(&mut buffer).unwrap();
+        }
+
+        let mut cursor: &[u8] = &buffer;
+        for test_case in test_cases {
+            let (new_cursor, parsed_chunk) = read_chunk(cursor).unwrap();
+            assert_eq!(parsed_chunk, test_case);
+            cursor = new_cursor;
+        }
+        assert!(cursor.is_empty());
+    }
 }
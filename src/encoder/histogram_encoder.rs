@@ -1,13 +1,1103 @@
-use crate::histogram::{FloatHistogramChunk, HistogramChunk};
+use bitstream_io::{BigEndian, BitWrite, BitWriter};
+
+use crate::{
+    histogram::{
+        count_spans, CounterResetHeader, FloatHistogramChunk, FloatHistogramSample, HistogramChunk,
+        HistogramChunkLayout, HistogramSample,
+    },
+    native_histogram::{
+        is_custom_buckets_schema, same_custom_values, FloatHistogram, Histogram, Span,
+    },
+    value::is_stale_nan,
+};
+
+use super::{
+    varbit_encoder::{write_varbit_int, write_varbit_uint},
+    varbit_xor_encoder::write_varbit_xor,
+};
+
+impl CounterResetHeader {
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            CounterResetHeader::Unknown => 0b0000_0000,
+            CounterResetHeader::CounterReset => 0b1000_0000,
+            CounterResetHeader::NotCounterReset => 0b0100_0000,
+            CounterResetHeader::Gauge => 0b1100_0000,
+        }
+    }
+}
+
+/// Writes the zero threshold of a histogram chunk layout.
+///
+/// Zero is a single 0 byte, and the powers of two between 2^-243 and 2^10
+/// are a single byte containing the exponent. Anything else is a 255 byte
+/// followed by the 64 bits float.
+fn write_zero_threshold<W: BitWrite>(zero_threshold: f64, writer: &mut W) -> std::io::Result<()> {
+    if zero_threshold == 0.0 {
+        return writer.write_out::<8, u8>(0);
+    }
+
+    // A positive power of two has no sign bit and an empty mantissa.
+    let bits = zero_threshold.to_bits();
+    let biased_exponent = (bits >> 52) as i64;
+    let is_power_of_two = bits & ((1 << 52) - 1) == 0 && biased_exponent != 0;
+    // This is the exponent returned by Golang's math.Frexp.
+    let exponent = biased_exponent - 1022;
+
+    if !is_power_of_two || !(-242..=11).contains(&exponent) {
+        writer.write_out::<8, u8>(255)?;
+        return writer.write_out::<64, u64>(bits);
+    }
+
+    writer.write_out::<8, u8>((exponent + 243) as u8)
+}
+
+fn write_spans<W: BitWrite>(spans: &[Span], writer: &mut W) -> std::io::Result<()> {
+    write_varbit_uint(spans.len() as u64, writer)?;
+    for span in spans {
+        write_varbit_uint(span.length as u64, writer)?;
+        write_varbit_int(span.offset as i64, writer)?;
+    }
+    Ok(())
+}
+
+/// Writes a custom bucket bound.
+///
+/// Bounds with at most 3 decimals, between 0 and 33554.430, are written
+/// as a varbit uint of the bound times 1000, plus one, so they fit in 4 bytes.
+/// Anything else is a 0 bit followed by the 64 bits float.
+fn write_custom_bound<W: BitWrite>(bound: f64, writer: &mut W) -> std::io::Result<()> {
+    let thousandths = bound * 1000.0;
+    // Also true for NaN.
+    if !(0.0..=33554430.0).contains(&thousandths) || thousandths.floor() != thousandths {
+        writer.write_bit(false)?;
+        return writer.write_out::<64, u64>(bound.to_bits());
+    }
+    write_varbit_uint(thousandths.round() as u64 + 1, writer)
+}
+
+fn write_custom_bounds<W: BitWrite>(bounds: &[f64], writer: &mut W) -> std::io::Result<()> {
+    write_varbit_uint(bounds.len() as u64, writer)?;
+    for bound in bounds {
+        write_custom_bound(*bound, writer)?;
+    }
+    Ok(())
+}
+
+/// Writes the layout of a histogram chunk, before the first sample.
+///
+/// Histograms with custom buckets also have their bounds in the layout.
+pub(crate) fn write_histogram_chunk_layout<W: BitWrite>(
+    layout: &HistogramChunkLayout,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    write_zero_threshold(layout.zero_threshold, writer)?;
+    write_varbit_int(layout.schema as i64, writer)?;
+    write_spans(&layout.positive_spans, writer)?;
+    write_spans(&layout.negative_spans, writer)?;
+    if is_custom_buckets_schema(layout.schema) {
+        write_custom_bounds(&layout.custom_values, writer)?;
+    }
+    Ok(())
+}
+
+fn check_same_layout(
+    schema: i32,
+    zero_threshold: f64,
+    positive_spans: &[Span],
+    negative_spans: &[Span],
+    custom_values: &[f64],
+    layout: &HistogramChunkLayout,
+) -> std::io::Result<()> {
+    if schema != layout.schema
+        || zero_threshold.to_bits() != layout.zero_threshold.to_bits()
+        || positive_spans != layout.positive_spans
+        || negative_spans != layout.negative_spans
+        || !same_custom_values(custom_values, &layout.custom_values)
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "histogram layout changes within the chunk",
+        ));
+    }
+    Ok(())
+}
+
+fn check_buckets(
+    positive_spans: &[Span],
+    num_positive_buckets: usize,
+    negative_spans: &[Span],
+    num_negative_buckets: usize,
+) -> std::io::Result<()> {
+    if num_positive_buckets != count_spans(positive_spans)
+        || num_negative_buckets != count_spans(negative_spans)
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "number of buckets doesn't match the spans",
+        ));
+    }
+    Ok(())
+}
+
+fn unsorted_error() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "samples aren't sorted by timestamp ascending",
+    )
+}
+
+/// Returns the delta between two timestamps, that must be sorted and not too far apart.
+fn timestamp_delta(previous_timestamp: i64, timestamp: i64) -> std::io::Result<i64> {
+    let timestamp_delta = timestamp.checked_sub(previous_timestamp).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "timestamps are too far apart",
+        )
+    })?;
+    if timestamp_delta < 0 {
+        return Err(unsorted_error());
+    }
+    Ok(timestamp_delta)
+}
+
+/// Writes the chunk header: the number of samples and the counter reset header.
+///
+/// Nothing is written when the number of samples is invalid.
+pub(crate) fn write_histogram_chunk_header<W: std::io::Write>(
+    num_samples: usize,
+    counter_reset_header: CounterResetHeader,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    let num_samples_u16 = u16::try_from(num_samples).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "too many samples for one chunk",
+        )
+    })?;
+    if num_samples == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "at least one sample is required",
+        ));
+    }
+
+    writer.write_all(&num_samples_u16.to_be_bytes())?;
+    writer.write_all(&[counter_reset_header.to_byte()])?;
+    Ok(())
+}
+
+#[derive(Debug)]
+struct HistogramWriteIterator {
+    timestamp: i64,
+    timestamp_delta: i64,
+    count: u64,
+    count_delta: i64,
+    zero_count: u64,
+    zero_count_delta: i64,
+    sum: f64,
+    leading_bits_count: u8,
+    trailing_bits_count: u8,
+    positive_buckets: Vec<i64>,
+    positive_buckets_delta: Vec<i64>,
+    negative_buckets: Vec<i64>,
+    negative_buckets_delta: Vec<i64>,
+}
+
+/// Stale markers are written without layout nor buckets.
+fn without_stale_buckets(histogram: &Histogram) -> std::borrow::Cow<'_, Histogram> {
+    if is_stale_nan(histogram.sum) {
+        std::borrow::Cow::Owned(Histogram {
+            sum: histogram.sum,
+            ..Default::default()
+        })
+    } else {
+        std::borrow::Cow::Borrowed(histogram)
+    }
+}
+
+fn check_histogram_buckets(histogram: &Histogram) -> std::io::Result<()> {
+    check_buckets(
+        &histogram.positive_spans,
+        histogram.positive_buckets.len(),
+        &histogram.negative_spans,
+        histogram.negative_buckets.len(),
+    )
+}
+
+fn write_first_histogram_sample<W: BitWrite>(
+    sample: &HistogramSample,
+    writer: &mut W,
+) -> std::io::Result<(HistogramChunkLayout, HistogramWriteIterator)> {
+    let histogram = without_stale_buckets(&sample.histogram);
+    check_histogram_buckets(&histogram)?;
+
+    let layout = HistogramChunkLayout {
+        schema: histogram.schema,
+        zero_threshold: histogram.zero_threshold,
+        positive_spans: histogram.positive_spans.clone(),
+        negative_spans: histogram.negative_spans.clone(),
+        custom_values: histogram.custom_values.clone(),
+    };
+    write_histogram_chunk_layout(&layout, writer)?;
+
+    write_varbit_int(sample.timestamp, writer)?;
+    write_varbit_uint(histogram.count, writer)?;
+    write_varbit_uint(histogram.zero_count, writer)?;
+    writer.write_out::<64, u64>(histogram.sum.to_bits())?;
+    for bucket in histogram
+        .positive_buckets
+        .iter()
+        .chain(histogram.negative_buckets.iter())
+    {
+        write_varbit_int(*bucket, writer)?;
+    }
+
+    let iterator = HistogramWriteIterator {
+        timestamp: sample.timestamp,
+        timestamp_delta: 0,
+        count: histogram.count,
+        count_delta: 0,
+        zero_count: histogram.zero_count,
+        zero_count_delta: 0,
+        sum: histogram.sum,
+        leading_bits_count: 0xff,
+        trailing_bits_count: 0,
+        positive_buckets: histogram.positive_buckets.clone(),
+        positive_buckets_delta: vec![0; histogram.positive_buckets.len()],
+        negative_buckets: histogram.negative_buckets.clone(),
+        negative_buckets_delta: vec![0; histogram.negative_buckets.len()],
+    };
+
+    Ok((layout, iterator))
+}
+
+fn write_buckets_delta_of_delta<W: BitWrite>(
+    new_buckets: &[i64],
+    buckets: &mut [i64],
+    buckets_delta: &mut [i64],
+    writer: &mut W,
+) -> std::io::Result<()> {
+    for ((new_bucket, bucket), bucket_delta) in new_buckets
+        .iter()
+        .zip(buckets.iter_mut())
+        .zip(buckets_delta.iter_mut())
+    {
+        let delta = new_bucket.wrapping_sub(*bucket);
+        write_varbit_int(delta.wrapping_sub(*bucket_delta), writer)?;
+        *bucket_delta = delta;
+        *bucket = *new_bucket;
+    }
+    Ok(())
+}
+
+/// Writes the following samples of a histogram chunk.
+///
+/// Prometheus writes single deltas for the second sample, which are the same
+/// as delta of deltas since the previous deltas are 0.
+fn write_n_histogram_sample<W: BitWrite>(
+    iterator: &mut HistogramWriteIterator,
+    sample: &HistogramSample,
+    layout: &HistogramChunkLayout,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    let histogram = without_stale_buckets(&sample.histogram);
+    let is_stale = is_stale_nan(histogram.sum);
+    if !is_stale {
+        check_histogram_buckets(&histogram)?;
+        check_same_layout(
+            histogram.schema,
+            histogram.zero_threshold,
+            &histogram.positive_spans,
+            &histogram.negative_spans,
+            &histogram.custom_values,
+            layout,
+        )?;
+    }
+
+    let timestamp_delta = timestamp_delta(iterator.timestamp, sample.timestamp)?;
+    let count_delta = (histogram.count as i64).wrapping_sub(iterator.count as i64);
+    let zero_count_delta = (histogram.zero_count as i64).wrapping_sub(iterator.zero_count as i64);
+
+    write_varbit_int(
+        timestamp_delta.wrapping_sub(iterator.timestamp_delta),
+        writer,
+    )?;
+    write_varbit_int(count_delta.wrapping_sub(iterator.count_delta), writer)?;
+    write_varbit_int(
+        zero_count_delta.wrapping_sub(iterator.zero_count_delta),
+        writer,
+    )?;
+
+    let (leading_bits_count, trailing_bits_count) = write_varbit_xor(
+        histogram.sum,
+        iterator.sum,
+        iterator.leading_bits_count,
+        iterator.trailing_bits_count,
+        writer,
+    )?;
+
+    if !is_stale {
+        write_buckets_delta_of_delta(
+            &histogram.positive_buckets,
+            &mut iterator.positive_buckets,
+            &mut iterator.positive_buckets_delta,
+            writer,
+        )?;
+        write_buckets_delta_of_delta(
+            &histogram.negative_buckets,
+            &mut iterator.negative_buckets,
+            &mut iterator.negative_buckets_delta,
+            writer,
+        )?;
+    }
+
+    iterator.timestamp = sample.timestamp;
+    iterator.timestamp_delta = timestamp_delta;
+    iterator.count = histogram.count;
+    iterator.count_delta = count_delta;
+    iterator.zero_count = histogram.zero_count;
+    iterator.zero_count_delta = zero_count_delta;
+    iterator.sum = histogram.sum;
+    iterator.leading_bits_count = leading_bits_count;
+    iterator.trailing_bits_count = trailing_bits_count;
+
+    Ok(())
+}
 
 impl HistogramChunk {
-    pub fn write<W: std::io::Write>(&self, _writer: &mut W) -> std::io::Result<()> {
-        unimplemented!()
+    /// Writes the histogram chunk to the writer.
+    ///
+    /// All the samples must share the schema, the zero threshold,
+    /// the spans, and the custom bounds of the first sample.
+    /// Stale markers are the exception.
+    pub fn write<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let samples = self.samples();
+        write_histogram_chunk_header(samples.len(), self.counter_reset_header(), writer)?;
+
+        let mut bit_writer = BitWriter::endian(writer, BigEndian);
+
+        let (layout, mut iterator) = write_first_histogram_sample(&samples[0], &mut bit_writer)?;
+
+        for sample in &samples[1..] {
+            write_n_histogram_sample(&mut iterator, sample, &layout, &mut bit_writer)?;
+        }
+
+        // Add 0 bits padding
+        bit_writer.byte_align()?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct XORWriteValue {
+    value: f64,
+    leading_bits_count: u8,
+    trailing_bits_count: u8,
+}
+
+impl XORWriteValue {
+    fn new(value: f64) -> Self {
+        Self {
+            value,
+            leading_bits_count: 0xff,
+            trailing_bits_count: 0,
+        }
     }
+
+    fn write<W: BitWrite>(&mut self, value: f64, writer: &mut W) -> std::io::Result<()> {
+        let (leading_bits_count, trailing_bits_count) = write_varbit_xor(
+            value,
+            self.value,
+            self.leading_bits_count,
+            self.trailing_bits_count,
+            writer,
+        )?;
+        self.value = value;
+        self.leading_bits_count = leading_bits_count;
+        self.trailing_bits_count = trailing_bits_count;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct FloatHistogramWriteIterator {
+    timestamp: i64,
+    timestamp_delta: i64,
+    count: XORWriteValue,
+    zero_count: XORWriteValue,
+    sum: XORWriteValue,
+    positive_buckets: Vec<XORWriteValue>,
+    negative_buckets: Vec<XORWriteValue>,
+}
+
+/// Stale markers are written without layout nor buckets.
+fn without_stale_float_buckets(histogram: &FloatHistogram) -> std::borrow::Cow<'_, FloatHistogram> {
+    if is_stale_nan(histogram.sum) {
+        std::borrow::Cow::Owned(FloatHistogram {
+            sum: histogram.sum,
+            ..Default::default()
+        })
+    } else {
+        std::borrow::Cow::Borrowed(histogram)
+    }
+}
+
+fn check_float_histogram_buckets(histogram: &FloatHistogram) -> std::io::Result<()> {
+    check_buckets(
+        &histogram.positive_spans,
+        histogram.positive_buckets.len(),
+        &histogram.negative_spans,
+        histogram.negative_buckets.len(),
+    )
+}
+
+fn write_first_float_histogram_sample<W: BitWrite>(
+    sample: &FloatHistogramSample,
+    writer: &mut W,
+) -> std::io::Result<(HistogramChunkLayout, FloatHistogramWriteIterator)> {
+    let histogram = without_stale_float_buckets(&sample.histogram);
+    check_float_histogram_buckets(&histogram)?;
+
+    let layout = HistogramChunkLayout {
+        schema: histogram.schema,
+        zero_threshold: histogram.zero_threshold,
+        positive_spans: histogram.positive_spans.clone(),
+        negative_spans: histogram.negative_spans.clone(),
+        custom_values: histogram.custom_values.clone(),
+    };
+    write_histogram_chunk_layout(&layout, writer)?;
+
+    // Unlike integer histograms, the counts are written as raw 64 bits floats.
+    write_varbit_int(sample.timestamp, writer)?;
+    writer.write_out::<64, u64>(histogram.count.to_bits())?;
+    writer.write_out::<64, u64>(histogram.zero_count.to_bits())?;
+    writer.write_out::<64, u64>(histogram.sum.to_bits())?;
+    for bucket in histogram
+        .positive_buckets
+        .iter()
+        .chain(histogram.negative_buckets.iter())
+    {
+        writer.write_out::<64, u64>(bucket.to_bits())?;
+    }
+
+    let iterator = FloatHistogramWriteIterator {
+        timestamp: sample.timestamp,
+        timestamp_delta: 0,
+        count: XORWriteValue::new(histogram.count),
+        zero_count: XORWriteValue::new(histogram.zero_count),
+        sum: XORWriteValue::new(histogram.sum),
+        positive_buckets: histogram
+            .positive_buckets
+            .iter()
+            .map(|bucket| XORWriteValue::new(*bucket))
+            .collect(),
+        negative_buckets: histogram
+            .negative_buckets
+            .iter()
+            .map(|bucket| XORWriteValue::new(*bucket))
+            .collect(),
+    };
+
+    Ok((layout, iterator))
+}
+
+/// Writes the following samples of a float histogram chunk.
+///
+/// Each count, the sum, and each bucket has its own XOR state.
+fn write_n_float_histogram_sample<W: BitWrite>(
+    iterator: &mut FloatHistogramWriteIterator,
+    sample: &FloatHistogramSample,
+    layout: &HistogramChunkLayout,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    let histogram = without_stale_float_buckets(&sample.histogram);
+    let is_stale = is_stale_nan(histogram.sum);
+    if !is_stale {
+        check_float_histogram_buckets(&histogram)?;
+        check_same_layout(
+            histogram.schema,
+            histogram.zero_threshold,
+            &histogram.positive_spans,
+            &histogram.negative_spans,
+            &histogram.custom_values,
+            layout,
+        )?;
+    }
+
+    let timestamp_delta = timestamp_delta(iterator.timestamp, sample.timestamp)?;
+    write_varbit_int(
+        timestamp_delta.wrapping_sub(iterator.timestamp_delta),
+        writer,
+    )?;
+
+    iterator.count.write(histogram.count, writer)?;
+    iterator.zero_count.write(histogram.zero_count, writer)?;
+    iterator.sum.write(histogram.sum, writer)?;
+
+    if !is_stale {
+        for (bucket, value) in iterator
+            .positive_buckets
+            .iter_mut()
+            .zip(histogram.positive_buckets.iter())
+            .chain(
+                iterator
+                    .negative_buckets
+                    .iter_mut()
+                    .zip(histogram.negative_buckets.iter()),
+            )
+        {
+            bucket.write(*value, writer)?;
+        }
+    }
+
+    iterator.timestamp = sample.timestamp;
+    iterator.timestamp_delta = timestamp_delta;
+
+    Ok(())
 }
 
 impl FloatHistogramChunk {
-    pub fn write<W: std::io::Write>(&self, _writer: &mut W) -> std::io::Result<()> {
-        unimplemented!()
+    /// Writes the float histogram chunk to the writer.
+    ///
+    /// All the samples must share the schema, the zero threshold,
+    /// the spans, and the custom bounds of the first sample.
+    /// Stale markers are the exception.
+    pub fn write<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let samples = self.samples();
+        write_histogram_chunk_header(samples.len(), self.counter_reset_header(), writer)?;
+
+        let mut bit_writer = BitWriter::endian(writer, BigEndian);
+
+        let (layout, mut iterator) =
+            write_first_float_histogram_sample(&samples[0], &mut bit_writer)?;
+
+        for sample in &samples[1..] {
+            write_n_float_histogram_sample(&mut iterator, sample, &layout, &mut bit_writer)?;
+        }
+
+        // Add 0 bits padding
+        bit_writer.byte_align()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        histogram::{read_float_histogram_chunk_data, read_histogram_chunk_data},
+        native_histogram::CUSTOM_BUCKETS_SCHEMA,
+    };
+
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    fn generate_random_test_data(seed: u64) -> Vec<Vec<HistogramSample>> {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+
+        let mut test_cases = Vec::with_capacity(64);
+        for _ in 0..64 {
+            let mut timestamp: i64 = rng.gen_range(1234567890..1357908642);
+            let schema = rng.gen_range(-4..=8);
+            let zero_threshold = if rng.gen_bool(0.5) {
+                2f64.powi(-128)
+            } else {
+                rng.gen()
+            };
+            let positive_spans = vec![
+                Span {
+                    offset: rng.gen_range(-10..10),
+                    length: rng.gen_range(0..5),
+                },
+                Span {
+                    offset: rng.gen_range(0..10),
+                    length: rng.gen_range(1..5),
+                },
+            ];
+            let negative_spans = if rng.gen_bool(0.5) {
+                vec![]
+            } else {
+                vec![Span {
+                    offset: rng.gen_range(-10..10),
+                    length: rng.gen_range(1..5),
+                }]
+            };
+            let mut positive_counts = vec![0i64; count_spans(&positive_spans)];
+            let mut negative_counts = vec![0i64; count_spans(&negative_spans)];
+            let mut zero_count: u64 = 0;
+            let mut sum: f64 = 0.0;
+
+            let vec_size = rng.gen_range(1..129);
+            let mut vec = Vec::with_capacity(vec_size);
+            for _ in 0..vec_size {
+                timestamp += rng.gen_range(1..30000);
+                for count in positive_counts.iter_mut().chain(negative_counts.iter_mut()) {
+                    *count += rng.gen_range(0..100);
+                }
+                zero_count += rng.gen_range(0..3);
+                sum += rng.gen_range(-1000.0..1000.0);
+
+                let to_deltas = |counts: &[i64]| {
+                    let mut previous = 0;
+                    counts
+                        .iter()
+                        .map(|count| {
+                            let delta = count - previous;
+                            previous = *count;
+                            delta
+                        })
+                        .collect::<Vec<i64>>()
+                };
+                vec.push(HistogramSample {
+                    timestamp,
+                    histogram: Histogram {
+                        schema,
+                        zero_threshold,
+                        zero_count,
+                        count: zero_count
+                            + positive_counts.iter().sum::<i64>() as u64
+                            + negative_counts.iter().sum::<i64>() as u64,
+                        sum,
+                        positive_spans: positive_spans.clone(),
+                        negative_spans: negative_spans.clone(),
+                        positive_buckets: to_deltas(&positive_counts),
+                        negative_buckets: to_deltas(&negative_counts),
+                        custom_values: vec![],
+                    },
+                });
+            }
+            test_cases.push(vec);
+        }
+        test_cases
+    }
+
+    #[test]
+    fn test_write_histogram_chunk() {
+        let test_cases = generate_random_test_data(42);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        for test_case in &test_cases {
+            let chunk = HistogramChunk::new(test_case.clone(), CounterResetHeader::NotCounterReset);
+            chunk.write(&mut buffer).unwrap();
+        }
+
+        let mut cursor: &[u8] = &buffer;
+        for test_case in test_cases {
+            let (new_cursor, parsed_chunk) = read_histogram_chunk_data(cursor).unwrap();
+            assert_eq!(parsed_chunk.samples(), test_case);
+            assert_eq!(
+                parsed_chunk.counter_reset_header(),
+                CounterResetHeader::NotCounterReset
+            );
+            cursor = new_cursor;
+        }
+    }
+
+    #[test]
+    fn test_write_histogram_chunk_bytes() {
+        let spans = vec![Span {
+            offset: 0,
+            length: 2,
+        }];
+        let chunk = HistogramChunk::new(
+            vec![
+                HistogramSample {
+                    timestamp: 1000,
+                    histogram: Histogram {
+                        schema: 0,
+                        zero_threshold: 2f64.powi(-128),
+                        zero_count: 1,
+                        count: 5,
+                        sum: 12.5,
+                        positive_spans: spans.clone(),
+                        negative_spans: vec![],
+                        positive_buckets: vec![2, 0],
+                        negative_buckets: vec![],
+                        custom_values: vec![],
+                    },
+                },
+                HistogramSample {
+                    timestamp: 1015,
+                    histogram: Histogram {
+                        schema: 0,
+                        zero_threshold: 2f64.powi(-128),
+                        zero_count: 1,
+                        count: 8,
+                        sum: 20.0,
+                        positive_spans: spans,
+                        negative_spans: vec![],
+                        positive_buckets: vec![3, 1],
+                        negative_buckets: vec![],
+                        custom_values: vec![],
+                    },
+                },
+            ],
+            CounterResetHeader::NotCounterReset,
+        );
+
+        let mut buffer: Vec<u8> = Vec::new();
+        chunk.write(&mut buffer).unwrap();
+
+        // Same bytes as the chunk written by hand in the histogram reader tests.
+        assert_eq!(
+            buffer,
+            [
+                0x00, 0x02, 0x40, 0x74, 0x46, 0x47, 0x8F, 0xA2, 0xB1, 0x40, 0x29, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x93, 0x1F, 0x36, 0xB1, 0x7B, 0x18, 0x80
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_stale_histogram_sample() {
+        let histogram = Histogram {
+            schema: 1,
+            zero_threshold: 0.001,
+            zero_count: 1,
+            count: 3,
+            sum: 4.0,
+            positive_spans: vec![Span {
+                offset: 1,
+                length: 1,
+            }],
+            negative_spans: vec![],
+            positive_buckets: vec![2],
+            negative_buckets: vec![],
+            custom_values: vec![],
+        };
+        let stale_marker = Histogram {
+            sum: f64::from_bits(0x7ff0000000000002),
+            ..Default::default()
+        };
+        let chunk = HistogramChunk::new(
+            vec![
+                HistogramSample {
+                    timestamp: 1,
+                    histogram: histogram.clone(),
+                },
+                HistogramSample {
+                    timestamp: 2,
+                    histogram: stale_marker,
+                },
+                HistogramSample {
+                    timestamp: 3,
+                    histogram: histogram.clone(),
+                },
+            ],
+            CounterResetHeader::Unknown,
+        );
+
+        let mut buffer: Vec<u8> = Vec::new();
+        chunk.write(&mut buffer).unwrap();
+
+        let (_, parsed_chunk) = read_histogram_chunk_data(&buffer).unwrap();
+        let samples = parsed_chunk.samples();
+        assert_eq!(samples[0].histogram, histogram);
+        assert!(is_stale_nan(samples[1].histogram.sum));
+        assert!(samples[1].histogram.positive_buckets.is_empty());
+        assert_eq!(samples[2].histogram, histogram);
+    }
+
+    #[test]
+    fn test_write_histogram_chunk_errors() {
+        let histogram = Histogram {
+            positive_spans: vec![Span {
+                offset: 0,
+                length: 1,
+            }],
+            positive_buckets: vec![1],
+            count: 1,
+            ..Default::default()
+        };
+        let sample = |timestamp, histogram: &Histogram| HistogramSample {
+            timestamp,
+            histogram: histogram.clone(),
+        };
+
+        let test_cases = vec![
+            vec![],
+            // Not sorted
+            vec![sample(10, &histogram), sample(9, &histogram)],
+            // Timestamps too far apart
+            vec![sample(i64::MIN, &histogram), sample(i64::MAX, &histogram)],
+            // Buckets don't match the spans
+            vec![sample(
+                10,
+                &Histogram {
+                    positive_buckets: vec![1, 2],
+                    ..histogram.clone()
+                },
+            )],
+            // Layout changes
+            vec![
+                sample(10, &histogram),
+                sample(
+                    11,
+                    &Histogram {
+                        schema: 1,
+                        ..histogram.clone()
+                    },
+                ),
+            ],
+        ];
+
+        let mut buffer: Vec<u8> = Vec::new();
+        for test_case in test_cases {
+            let chunk = HistogramChunk::new(test_case, CounterResetHeader::Unknown);
+            assert!(chunk.write(&mut buffer).is_err());
+        }
+
+        // Nothing is written for empty chunks.
+        let mut buffer: Vec<u8> = Vec::new();
+        let chunk = HistogramChunk::new(vec![], CounterResetHeader::Unknown);
+        assert!(chunk.write(&mut buffer).is_err());
+        assert!(buffer.is_empty());
+    }
+
+    fn to_float_samples(samples: &[HistogramSample]) -> Vec<FloatHistogramSample> {
+        samples
+            .iter()
+            .map(|sample| {
+                let to_absolute = |buckets: &[i64]| {
+                    let mut count = 0;
+                    buckets
+                        .iter()
+                        .map(|delta| {
+                            count += delta;
+                            count as f64 / 3.0
+                        })
+                        .collect::<Vec<f64>>()
+                };
+                let histogram = &sample.histogram;
+                FloatHistogramSample {
+                    timestamp: sample.timestamp,
+                    histogram: FloatHistogram {
+                        schema: histogram.schema,
+                        zero_threshold: histogram.zero_threshold,
+                        zero_count: histogram.zero_count as f64 / 3.0,
+                        count: histogram.count as f64 / 3.0,
+                        sum: histogram.sum,
+                        custom_values: histogram.custom_values.clone(),
+                        positive_spans: histogram.positive_spans.clone(),
+                        negative_spans: histogram.negative_spans.clone(),
+                        positive_buckets: to_absolute(&histogram.positive_buckets),
+                        negative_buckets: to_absolute(&histogram.negative_buckets),
+                    },
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_write_float_histogram_chunk() {
+        let test_cases = generate_random_test_data(1234)
+            .iter()
+            .map(|samples| to_float_samples(samples))
+            .collect::<Vec<_>>();
+
+        let mut buffer: Vec<u8> = Vec::new();
+        for test_case in &test_cases {
+            let chunk = FloatHistogramChunk::new(test_case.clone(), CounterResetHeader::Gauge);
+            chunk.write(&mut buffer).unwrap();
+        }
+
+        let mut cursor: &[u8] = &buffer;
+        for test_case in test_cases {
+            let (new_cursor, parsed_chunk) = read_float_histogram_chunk_data(cursor).unwrap();
+            assert_eq!(parsed_chunk.samples(), test_case);
+            assert_eq!(
+                parsed_chunk.counter_reset_header(),
+                CounterResetHeader::Gauge
+            );
+            cursor = new_cursor;
+        }
+    }
+
+    #[test]
+    fn test_write_float_histogram_chunk_bytes() {
+        let spans = vec![Span {
+            offset: -2,
+            length: 1,
+        }];
+        let chunk = FloatHistogramChunk::new(
+            vec![
+                FloatHistogramSample {
+                    timestamp: 0,
+                    histogram: FloatHistogram {
+                        schema: -1,
+                        zero_threshold: 0.0,
+                        zero_count: 1.0,
+                        count: 3.0,
+                        custom_values: vec![],
+                        sum: -4.5,
+                        positive_spans: vec![],
+                        negative_spans: spans.clone(),
+                        positive_buckets: vec![],
+                        negative_buckets: vec![2.0],
+                    },
+                },
+                FloatHistogramSample {
+                    timestamp: 4,
+                    histogram: FloatHistogram {
+                        schema: -1,
+                        zero_threshold: 0.0,
+                        zero_count: 1.0,
+                        count: 5.0,
+                        custom_values: vec![],
+                        sum: -9.0,
+                        positive_spans: vec![],
+                        negative_spans: spans,
+                        positive_buckets: vec![],
+                        negative_buckets: vec![4.0],
+                    },
+                },
+                FloatHistogramSample {
+                    timestamp: 8,
+                    histogram: FloatHistogram {
+                        sum: f64::from_bits(0x7ff0000000000002),
+                        ..Default::default()
+                    },
+                },
+            ],
+            CounterResetHeader::Gauge,
+        );
+
+        let mut buffer: Vec<u8> = Vec::new();
+        chunk.write(&mut buffer).unwrap();
+
+        // Same bytes as the chunk written by hand in the histogram reader tests.
+        assert_eq!(
+            buffer,
+            [
+                0x00, 0x03, 0xC0, 0x00, 0xBA, 0x31, 0xB1, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0xFF, 0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x48, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x9A, 0xC3, 0xED,
+                0x41, 0x7A, 0xC1, 0xB0, 0x9B, 0x00, 0x5C, 0x45, 0x7F, 0xF8, 0x3F, 0xBF, 0xD2, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x02
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_float_histogram_chunk_errors() {
+        let histogram = FloatHistogram {
+            negative_spans: vec![Span {
+                offset: 0,
+                length: 1,
+            }],
+            negative_buckets: vec![1.5],
+            count: 1.5,
+            ..Default::default()
+        };
+        let sample = |timestamp, histogram: &FloatHistogram| FloatHistogramSample {
+            timestamp,
+            histogram: histogram.clone(),
+        };
+
+        let test_cases = vec![
+            vec![],
+            // Not sorted
+            vec![sample(10, &histogram), sample(9, &histogram)],
+            // Timestamps too far apart
+            vec![sample(i64::MIN, &histogram), sample(i64::MAX, &histogram)],
+            // Buckets don't match the spans
+            vec![sample(
+                10,
+                &FloatHistogram {
+                    negative_buckets: vec![],
+                    ..histogram.clone()
+                },
+            )],
+            // Layout changes
+            vec![
+                sample(10, &histogram),
+                sample(
+                    11,
+                    &FloatHistogram {
+                        zero_threshold: 0.5,
+                        ..histogram.clone()
+                    },
+                ),
+            ],
+        ];
+
+        let mut buffer: Vec<u8> = Vec::new();
+        for test_case in test_cases {
+            let chunk = FloatHistogramChunk::new(test_case, CounterResetHeader::Unknown);
+            assert!(chunk.write(&mut buffer).is_err());
+        }
+
+        // Nothing is written for empty chunks.
+        let mut buffer: Vec<u8> = Vec::new();
+        let chunk = FloatHistogramChunk::new(vec![], CounterResetHeader::Unknown);
+        assert!(chunk.write(&mut buffer).is_err());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_write_custom_buckets_histogram_chunk() {
+        #[rustfmt::skip]
+        let custom_values = vec![
+            0.0,
+            0.001,
+            0.25,
+            1.0,
+            1.0001,    // Too many decimals
+            33554.431, // Too large
+            1e300,     // Too large
+        ];
+        let samples = (0..10)
+            .map(|i| HistogramSample {
+                timestamp: 1000 + i * 15000,
+                histogram: Histogram {
+                    schema: CUSTOM_BUCKETS_SCHEMA,
+                    count: 3 * i as u64,
+                    sum: 0.5 * i as f64,
+                    positive_spans: vec![
+                        Span {
+                            offset: 1,
+                            length: 2,
+                        },
+                        Span {
+                            offset: 4,
+                            length: 1,
+                        },
+                    ],
+                    positive_buckets: vec![i, 0, i],
+                    custom_values: custom_values.clone(),
+                    ..Default::default()
+                },
+            })
+            .collect::<Vec<_>>();
+
+        let mut buffer: Vec<u8> = Vec::new();
+        HistogramChunk::new(samples.clone(), CounterResetHeader::NotCounterReset)
+            .write(&mut buffer)
+            .unwrap();
+        let (remaining, parsed_chunk) = read_histogram_chunk_data(&buffer).unwrap();
+        assert!(remaining.is_empty());
+        assert_eq!(parsed_chunk.samples(), samples);
+
+        let float_samples = to_float_samples(&samples);
+        let mut buffer: Vec<u8> = Vec::new();
+        FloatHistogramChunk::new(float_samples.clone(), CounterResetHeader::Unknown)
+            .write(&mut buffer)
+            .unwrap();
+        let (remaining, parsed_chunk) = read_float_histogram_chunk_data(&buffer).unwrap();
+        assert!(remaining.is_empty());
+        assert_eq!(parsed_chunk.samples(), float_samples);
+
+        // The custom bounds are part of the layout
+        let mut changed_samples = samples.clone();
+        changed_samples[5].histogram.custom_values[2] = 0.3;
+        let mut buffer: Vec<u8> = Vec::new();
+        assert!(
+            HistogramChunk::new(changed_samples, CounterResetHeader::NotCounterReset)
+                .write(&mut buffer)
+                .is_err()
+        );
     }
 }
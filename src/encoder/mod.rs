@@ -1,7 +1,9 @@
+pub mod aggr_encoder;
 pub mod chunk_encoder;
 pub mod chunks_encoder;
 pub mod histogram_encoder;
 pub mod uvarint_encoder;
+pub mod varbit_encoder;
 pub mod varbit_ts_encoder;
 pub mod varbit_xor_encoder;
 pub mod varint_encoder;
@@ -10,6 +10,12 @@ pub fn write_uvarint<W: std::io::Write>(value: u64, writer: &mut W) -> std::io::
     Ok(())
 }
 
+/// Returns the number of bytes of a u64 written as a Golang uvarint.
+pub fn uvarint_len(value: u64) -> usize {
+    // 7 bits per byte, and at least one byte.
+    (64 - (value | 1).leading_zeros() as usize).div_ceil(7)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::uvarint::read_uvarint;
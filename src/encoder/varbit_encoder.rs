@@ -0,0 +1,206 @@
+/// Writes a i64 as a Prometheus varbit-encoded integer.
+pub fn write_varbit_int<W: bitstream_io::BitWrite>(
+    value: i64,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    match value {
+        0 => writer.write_bit(false)?,
+        // 3 bits
+        -3..=4 => {
+            writer.write_out::<2, u8>(0b10)?;
+            writer.write_out::<3, u64>(value as u64 & 0x7)?;
+        }
+        // 6 bits
+        -31..=32 => {
+            writer.write_out::<3, u8>(0b110)?;
+            writer.write_out::<6, u64>(value as u64 & 0x3F)?;
+        }
+        // 9 bits
+        -255..=256 => {
+            writer.write_out::<4, u8>(0b1110)?;
+            writer.write_out::<9, u64>(value as u64 & 0x1FF)?;
+        }
+        // 12 bits
+        -2047..=2048 => {
+            writer.write_out::<5, u8>(0b11110)?;
+            writer.write_out::<12, u64>(value as u64 & 0xFFF)?;
+        }
+        // 18 bits
+        -131071..=131072 => {
+            writer.write_out::<6, u8>(0b111110)?;
+            writer.write_out::<18, u64>(value as u64 & 0x3FFFF)?;
+        }
+        // 25 bits
+        -16777215..=16777216 => {
+            writer.write_out::<7, u8>(0b1111110)?;
+            writer.write_out::<25, u64>(value as u64 & 0x1FFFFFF)?;
+        }
+        // 56 bits
+        -36028797018963967..=36028797018963968 => {
+            writer.write_out::<8, u8>(0b11111110)?;
+            writer.write_out::<56, u64>(value as u64 & 0xFFFFFFFFFFFFFF)?;
+        }
+        _ => {
+            writer.write_out::<8, u8>(0b11111111)?;
+            writer.write_out::<64, u64>(value as u64)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a u64 as a Prometheus varbit-encoded unsigned integer.
+///
+/// It uses the same buckets as the signed integers.
+pub fn write_varbit_uint<W: bitstream_io::BitWrite>(
+    value: u64,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    match value {
+        0 => writer.write_bit(false)?,
+        1..=0x7 => {
+            writer.write_out::<2, u8>(0b10)?;
+            writer.write_out::<3, u64>(value)?;
+        }
+        0x8..=0x3F => {
+            writer.write_out::<3, u8>(0b110)?;
+            writer.write_out::<6, u64>(value)?;
+        }
+        0x40..=0x1FF => {
+            writer.write_out::<4, u8>(0b1110)?;
+            writer.write_out::<9, u64>(value)?;
+        }
+        0x200..=0xFFF => {
+            writer.write_out::<5, u8>(0b11110)?;
+            writer.write_out::<12, u64>(value)?;
+        }
+        0x1000..=0x3FFFF => {
+            writer.write_out::<6, u8>(0b111110)?;
+            writer.write_out::<18, u64>(value)?;
+        }
+        0x40000..=0x1FFFFFF => {
+            writer.write_out::<7, u8>(0b1111110)?;
+            writer.write_out::<25, u64>(value)?;
+        }
+        0x2000000..=0xFFFFFFFFFFFFFF => {
+            writer.write_out::<8, u8>(0b11111110)?;
+            writer.write_out::<56, u64>(value)?;
+        }
+        _ => {
+            writer.write_out::<8, u8>(0b11111111)?;
+            writer.write_out::<64, u64>(value)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::varbit::{read_varbit_int, read_varbit_uint};
+
+    use super::*;
+    use bitstream_io::{BigEndian, BitWrite, BitWriter};
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_write_varbit_int() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+
+        let mut numbers = vec![
+            i64::MIN,
+            -36028797018963968,
+            -36028797018963967,
+            -16777216,
+            -16777215,
+            -131072,
+            -131071,
+            -2048,
+            -2047,
+            -256,
+            -255,
+            -32,
+            -31,
+            -4,
+            -3,
+            -1,
+            0,
+            1,
+            4,
+            5,
+            32,
+            33,
+            256,
+            257,
+            2048,
+            2049,
+            131072,
+            131073,
+            16777216,
+            16777217,
+            36028797018963968,
+            36028797018963969,
+            i64::MAX,
+        ];
+        for _ in 0..100 {
+            numbers.push(rng.gen());
+            numbers.push(rng.gen_range(-100000..100000));
+        }
+
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut bit_writer = BitWriter::endian(&mut buffer, BigEndian);
+        for number in &numbers {
+            write_varbit_int(*number, &mut bit_writer).unwrap();
+        }
+        bit_writer.byte_align().unwrap();
+
+        let mut cursor: (&[u8], usize) = (&buffer, 0);
+        for number in numbers {
+            let (new_cursor, value) = read_varbit_int(cursor).unwrap();
+            cursor = new_cursor;
+            assert_eq!(value, number);
+        }
+    }
+
+    #[test]
+    fn test_write_varbit_uint() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+
+        let mut numbers = vec![
+            0,
+            1,
+            7,
+            8,
+            63,
+            64,
+            511,
+            512,
+            4095,
+            4096,
+            262143,
+            262144,
+            33554431,
+            33554432,
+            72057594037927935,
+            72057594037927936,
+            u64::MAX,
+        ];
+        for _ in 0..100 {
+            numbers.push(rng.gen());
+            numbers.push(rng.gen_range(0..100000));
+        }
+
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut bit_writer = BitWriter::endian(&mut buffer, BigEndian);
+        for number in &numbers {
+            write_varbit_uint(*number, &mut bit_writer).unwrap();
+        }
+        bit_writer.byte_align().unwrap();
+
+        let mut cursor: (&[u8], usize) = (&buffer, 0);
+        for number in numbers {
+            let (new_cursor, value) = read_varbit_uint(cursor).unwrap();
+            cursor = new_cursor;
+            assert_eq!(value, number);
+        }
+    }
+}
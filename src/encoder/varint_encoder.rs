@@ -1,4 +1,4 @@
-use super::uvarint_encoder::write_uvarint;
+use super::uvarint_encoder::{uvarint_len, write_uvarint};
 
 /// Write a i64 as a Golang varint.
 pub fn write_varint<W: std::io::Write>(value: i64, writer: &mut W) -> std::io::Result<()> {
@@ -10,6 +10,15 @@ pub fn write_varint<W: std::io::Write>(value: i64, writer: &mut W) -> std::io::R
     write_uvarint(ux, writer)
 }
 
+/// Returns the number of bytes of a i64 written as a Golang varint.
+pub fn varint_len(value: i64) -> usize {
+    let mut ux = (value as u64) << 1;
+    if value < 0 {
+        ux = !ux;
+    }
+    uvarint_len(ux)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::varint::read_varint;
@@ -1,14 +1,19 @@
-use bitstream_io::{BigEndian, BitWrite, BitWriter};
+use bitstream_io::{BigEndian, BitCounter, BitWrite, BitWriter};
 use smallvec::SmallVec;
 
-use crate::xor::{XORChunk, XORSample};
+use crate::{
+    quantisation::Quantisation,
+    xor::{XORChunk, XORSample},
+};
 
 use super::{
-    uvarint_encoder::write_uvarint, varbit_ts_encoder::write_varbit_ts,
-    varbit_xor_encoder::write_varbit_xor, varint_encoder::write_varint,
+    uvarint_encoder::write_uvarint,
+    varbit_ts_encoder::write_varbit_ts,
+    varbit_xor_encoder::write_varbit_xor,
+    varint_encoder::{varint_len, write_varint},
 };
 
-fn write_first_sample<W: std::io::Write>(
+pub(crate) fn write_first_sample<W: std::io::Write>(
     first_sample: &XORSample,
     writer: &mut W,
 ) -> std::io::Result<()> {
@@ -22,8 +27,8 @@ fn write_first_sample<W: std::io::Write>(
     Ok(())
 }
 
-#[derive(Debug)]
-struct XORReadIterator {
+#[derive(Debug, Clone)]
+pub(crate) struct XORReadIterator {
     pub timestamp: i64,
     pub value: f64,
     pub leading_bits_count: u8,
@@ -31,7 +36,20 @@ struct XORReadIterator {
     pub timestamp_delta: i64,
 }
 
-fn write_second_sample<W: bitstream_io::BitWrite>(
+impl XORReadIterator {
+    /// Returns the state after the first sample, that has no leading bits count yet.
+    pub(crate) fn from_first_sample(first_sample: &XORSample) -> Self {
+        Self {
+            timestamp: first_sample.timestamp,
+            value: first_sample.value,
+            leading_bits_count: 0xff,
+            trailing_bits_count: 0,
+            timestamp_delta: 0,
+        }
+    }
+}
+
+pub(crate) fn write_second_sample<W: bitstream_io::BitWrite>(
     second_sample: &XORSample,
     first_sample: &XORSample,
     writer: &mut W,
@@ -64,7 +82,7 @@ fn write_second_sample<W: bitstream_io::BitWrite>(
     })
 }
 
-fn write_n_sample<W: bitstream_io::BitWrite>(
+pub(crate) fn write_n_sample<W: bitstream_io::BitWrite>(
     previous_iterator: &XORReadIterator,
     sample: &XORSample,
     writer: &mut W,
@@ -100,45 +118,106 @@ fn write_n_sample<W: bitstream_io::BitWrite>(
     })
 }
 
-impl XORChunk {
-    /// Writes the XOR chunk to the writer.
-    pub fn write<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
-        // Write the number of samples first
-        let samples = self.samples();
+/// Returns the number of bits `write_second_sample` or `write_n_sample` would write
+/// after the previous sample, and the new state.
+///
+/// The state of the first sample has 0xff leading bits.
+pub(crate) fn sample_bit_len(
+    previous_iterator: &XORReadIterator,
+    is_second_sample: bool,
+    sample: &XORSample,
+) -> std::io::Result<(usize, XORReadIterator)> {
+    let mut bit_counter = BitCounter::<u64, BigEndian>::new();
+    let iterator = if is_second_sample {
+        let first_sample = XORSample {
+            timestamp: previous_iterator.timestamp,
+            value: previous_iterator.value,
+        };
+        write_second_sample(sample, &first_sample, &mut bit_counter)?
+    } else {
+        write_n_sample(previous_iterator, sample, &mut bit_counter)?
+    };
+    Ok((bit_counter.written() as usize, iterator))
+}
 
-        let num_samples = samples.len();
-        let num_samples_u16 = u16::try_from(num_samples).map_err(|_| {
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "too many samples for one chunk",
-            )
-        })?;
-        writer.write_all(&num_samples_u16.to_be_bytes())?;
-
-        if num_samples == 0 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "at least one sample is required",
-            ));
+/// Returns the number of samples as written in the chunk.
+fn encoded_num_samples(samples: &[XORSample]) -> std::io::Result<u16> {
+    let num_samples = u16::try_from(samples.len()).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "too many samples for one chunk",
+        )
+    })?;
+    if num_samples == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "at least one sample is required",
+        ));
+    }
+    Ok(num_samples)
+}
+
+fn write_samples<W: std::io::Write>(samples: &[XORSample], writer: &mut W) -> std::io::Result<()> {
+    // Write the number of samples first
+    let num_samples = encoded_num_samples(samples)?;
+    writer.write_all(&num_samples.to_be_bytes())?;
+
+    let first_sample = &samples[0];
+    write_first_sample(first_sample, writer)?;
+
+    if num_samples > 1 {
+        let mut bit_writer = BitWriter::endian(writer, BigEndian);
+
+        let second_sample = &samples[1];
+        let mut iterator = write_second_sample(second_sample, first_sample, &mut bit_writer)?;
+
+        for sample in &samples[2..] {
+            iterator = write_n_sample(&iterator, sample, &mut bit_writer)?;
         }
-        let first_sample = &samples[0];
-        write_first_sample(first_sample, writer)?;
 
-        if num_samples > 1 {
-            let mut bit_writer = BitWriter::endian(writer, BigEndian);
+        // Add 0 bits padding
+        bit_writer.byte_align()?;
+    }
 
-            let second_sample = &samples[1];
-            let mut iterator = write_second_sample(second_sample, first_sample, &mut bit_writer)?;
+    Ok(())
+}
 
-            for sample in &samples[2..] {
-                iterator = write_n_sample(&iterator, sample, &mut bit_writer)?;
-            }
+impl XORChunk {
+    /// Writes the XOR chunk to the writer.
+    pub fn write<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write_samples(self.samples(), writer)
+    }
 
-            // Add 0 bits padding
-            bit_writer.byte_align()?;
+    /// Returns the number of bytes [`XORChunk::write`] would write, without writing them.
+    ///
+    /// It fails like [`XORChunk::write`], on empty chunks, too many samples,
+    /// or samples that aren't sorted.
+    pub fn encoded_len(&self) -> std::io::Result<usize> {
+        let samples = self.samples();
+        encoded_num_samples(samples)?;
+        let first_sample = &samples[0];
+        let mut iterator = XORReadIterator::from_first_sample(first_sample);
+        let mut bits = 0;
+        for (i, sample) in samples.iter().enumerate().skip(1) {
+            let (sample_bits, new_iterator) = sample_bit_len(&iterator, i == 1, sample)?;
+            bits += sample_bits;
+            iterator = new_iterator;
         }
+        Ok(2 + varint_len(first_sample.timestamp) + 8 + bits.div_ceil(8))
+    }
 
-        Ok(())
+    /// Writes the XOR chunk to the writer, with quantised values.
+    ///
+    /// It is lossy, see [`Quantisation`]. It returns the maximum absolute
+    /// error introduced on the values.
+    pub fn write_quantised<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+        quantisation: Quantisation,
+    ) -> std::io::Result<f64> {
+        let (samples, max_error) = quantisation.apply_to_samples(self.samples());
+        write_samples(&samples, writer)?;
+        Ok(max_error)
     }
 }
 
@@ -299,4 +378,84 @@ mod tests {
             assert!(chunk.write(&mut buffer).is_err());
         }
     }
+
+    #[test]
+    fn test_write_quantised() {
+        // A noisy temperature sensor.
+        let samples = (0..120)
+            .map(|i| XORSample {
+                timestamp: i * 15000,
+                value: 21.5 + ((i * 7919) % 13) as f64 * 1e-7 + (i / 40) as f64 * 0.1,
+            })
+            .collect::<Vec<_>>();
+        let chunk = XORChunk::new(samples);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        chunk.write(&mut buffer).unwrap();
+        let mut quantised_buffer: Vec<u8> = Vec::new();
+        let max_error = chunk
+            .write_quantised(&mut quantised_buffer, Quantisation::SignificantDigits(4))
+            .unwrap();
+        assert!(quantised_buffer.len() * 4 < buffer.len());
+        assert!(max_error > 0.0 && max_error < 0.005);
+
+        let (_, parsed) = read_xor_chunk_data(&quantised_buffer).unwrap();
+        for (parsed, sample) in parsed.samples().iter().zip(chunk.samples()) {
+            assert_eq!(parsed.timestamp, sample.timestamp);
+            assert!((parsed.value - sample.value).abs() <= max_error);
+        }
+        assert_eq!(parsed.samples()[119].value, 21.7);
+
+        let mut masked_buffer: Vec<u8> = Vec::new();
+        let max_error = chunk
+            .write_quantised(&mut masked_buffer, Quantisation::MantissaBits(32))
+            .unwrap();
+        assert!(masked_buffer.len() < buffer.len());
+        assert!(max_error < 21.5 * f64::EPSILON * 2f64.powi(32));
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        let mut test_cases = generate_random_test_data(43);
+        test_cases.push(vec![
+            XORSample {
+                timestamp: i64::MIN + 1,
+                value: f64::MAX,
+            },
+            XORSample {
+                timestamp: 0,
+                value: 0.0,
+            },
+            XORSample {
+                timestamp: 2,
+                value: f64::MIN,
+            },
+            XORSample {
+                timestamp: 3,
+                value: f64::MAX,
+            },
+            XORSample {
+                timestamp: i64::MAX - 1,
+                value: f64::MIN,
+            },
+        ]);
+
+        for test_case in test_cases {
+            let chunk = XORChunk::new(test_case);
+            let mut buffer: Vec<u8> = Vec::new();
+            chunk.write(&mut buffer).unwrap();
+            assert_eq!(chunk.encoded_len().unwrap(), buffer.len());
+        }
+
+        assert!(XORChunk::new(vec![]).encoded_len().is_err());
+        let too_many_samples = (0..=u16::MAX as i64)
+            .map(|timestamp| XORSample {
+                timestamp,
+                value: 1.0,
+            })
+            .collect::<Vec<_>>();
+        assert!(XORChunk::new(too_many_samples).encoded_len().is_err());
+        let unsorted_samples = vec![XORSample::stale(10), XORSample::stale(9)];
+        assert!(XORChunk::new(unsorted_samples).encoded_len().is_err());
+    }
 }
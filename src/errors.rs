@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::NomBitInput;
+
 #[derive(Error, Debug)]
 pub enum RustyChunkEncError {
     #[error("IO error: {0}")]
@@ -13,6 +15,12 @@ pub enum RustyChunkEncError {
 
     #[error("Invalid file name")]
     InvalidFileName(),
+
+    #[error("Invalid histogram: {0}")]
+    InvalidHistogram(String),
+
+    #[error("Conflicting samples at timestamp {0}")]
+    ConflictingSamples(i64),
 }
 
 impl From<nom::Err<nom::error::Error<&[u8]>>> for RustyChunkEncError {
@@ -20,3 +28,17 @@ impl From<nom::Err<nom::error::Error<&[u8]>>> for RustyChunkEncError {
         RustyChunkEncError::ParsingError(format!("Nom error: {:?}", err))
     }
 }
+
+impl RustyChunkEncError {
+    /// Converts a nom error on bits.
+    ///
+    /// The bits input would be a long slice, the error kind is enough.
+    pub(crate) fn from_bits_error(err: nom::Err<nom::error::Error<NomBitInput>>) -> Self {
+        RustyChunkEncError::ParsingError(format!("Nom error: {:?}", err.map(|e| e.code)))
+    }
+
+    /// Converts a bit reader error, like [`Self::from_bits_error`].
+    pub(crate) fn from_bit_reader_error(kind: nom::error::ErrorKind) -> Self {
+        Self::from_bits_error(nom::Err::Error(nom::error::Error::new((&[], 0), kind)))
+    }
+}
@@ -1,35 +1,1507 @@
-use nom::IResult;
+use nom::{
+    bits,
+    bits::complete::take,
+    number::complete::{be_u16, u8 as read_u8},
+    sequence::tuple,
+    IResult,
+};
 
-use crate::chunk::ChunkWithBlockChunkRef;
+use crate::{
+    chunk::ChunkWithBlockChunkRef,
+    errors::RustyChunkEncError,
+    native_histogram::{is_custom_buckets_schema, FloatHistogram, Histogram, Span},
+    value::{is_stale_nan, STALE_NAN},
+    varbit::{read_varbit_int, read_varbit_uint},
+    varbit_xor::read_varbit_xor,
+    NomBitInput,
+};
 
-#[derive(Debug, PartialEq)]
-pub struct HistogramChunk {}
+/// The counter reset header of a histogram chunk.
+///
+/// It is stored in the two most significant bits of the byte
+/// following the number of samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CounterResetHeader {
+    #[default]
+    Unknown,
+    CounterReset,
+    NotCounterReset,
+    Gauge,
+}
+
+impl CounterResetHeader {
+    pub(crate) fn from_byte(byte: u8) -> Self {
+        match byte & 0b1100_0000 {
+            0b1000_0000 => CounterResetHeader::CounterReset,
+            0b0100_0000 => CounterResetHeader::NotCounterReset,
+            0b1100_0000 => CounterResetHeader::Gauge,
+            _ => CounterResetHeader::Unknown,
+        }
+    }
+}
+
+/// The layout shared by all the samples of a histogram chunk.
+#[derive(Debug)]
+pub(crate) struct HistogramChunkLayout {
+    pub schema: i32,
+    pub zero_threshold: f64,
+    pub positive_spans: Vec<Span>,
+    pub negative_spans: Vec<Span>,
+    pub custom_values: Vec<f64>,
+}
+
+impl HistogramChunkLayout {
+    fn num_positive_buckets(&self) -> usize {
+        count_spans(&self.positive_spans)
+    }
+
+    fn num_negative_buckets(&self) -> usize {
+        count_spans(&self.negative_spans)
+    }
+}
+
+pub(crate) fn count_spans(spans: &[Span]) -> usize {
+    spans.iter().map(|span| span.length as usize).sum()
+}
+
+/// A Prometheus histogram chunk.
+///
+/// All the samples of a chunk share the same schema, zero threshold,
+/// and bucket spans.
+#[derive(Debug)]
+pub struct HistogramChunk {
+    samples: Vec<HistogramSample>,
+    counter_reset_header: CounterResetHeader,
+    block_chunk_ref: Option<u64>,
+    addr: Option<*const u8>,
+}
+
+/// A sample of a Prometheus histogram chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSample {
+    pub timestamp: i64,
+    pub histogram: Histogram,
+}
+
+impl HistogramSample {
+    /// Creates a staleness marker at the given timestamp.
+    pub fn stale(timestamp: i64) -> Self {
+        Self {
+            timestamp,
+            histogram: Histogram {
+                sum: STALE_NAN,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Returns true if the sample is a staleness marker.
+    pub fn is_stale_marker(&self) -> bool {
+        is_stale_nan(self.histogram.sum)
+    }
+}
 
 impl ChunkWithBlockChunkRef for HistogramChunk {
     fn block_chunk_ref(&self) -> Option<u64> {
-        None
+        self.block_chunk_ref
+    }
+
+    fn compute_block_chunk_ref(&mut self, file_index: u64, chunks_addr: *const u8) {
+        match self.addr {
+            Some(addr) => {
+                self.block_chunk_ref =
+                    Some((file_index << 32) | (addr as u64 - chunks_addr as u64));
+            }
+            None => self.block_chunk_ref = None,
+        }
+    }
+}
+
+impl PartialEq for HistogramChunk {
+    fn eq(&self, other: &Self) -> bool {
+        self.samples == other.samples && self.counter_reset_header == other.counter_reset_header
+    }
+}
+
+impl HistogramChunk {
+    /// Creates a new histogram chunk with the given samples.
+    pub fn new(samples: Vec<HistogramSample>, counter_reset_header: CounterResetHeader) -> Self {
+        Self {
+            samples,
+            counter_reset_header,
+            block_chunk_ref: None,
+            addr: None,
+        }
+    }
+
+    /// Sets the memory address of the chunk.
+    pub(crate) fn set_addr(&mut self, addr: *const u8) {
+        self.addr = Some(addr);
+    }
+
+    /// Returns the samples of the chunk.
+    pub fn samples(&self) -> &[HistogramSample] {
+        &self.samples
+    }
+
+    /// Returns the counter reset header of the chunk.
+    pub fn counter_reset_header(&self) -> CounterResetHeader {
+        self.counter_reset_header
+    }
+
+    /// Sets the counter reset header of the chunk.
+    pub fn set_counter_reset_header(&mut self, counter_reset_header: CounterResetHeader) {
+        self.counter_reset_header = counter_reset_header;
+    }
+
+    /// Returns the counter reset header of the chunk that follows this one in a series,
+    /// when the next chunk starts with the given histogram.
+    ///
+    /// Gauge chunks are followed by gauge chunks. Otherwise, like Prometheus
+    /// when it cuts a new chunk, it's a counter reset if the histogram is
+    /// a counter reset compared to the last sample of this chunk.
+    /// It's unknown when their layouts can't be compared.
+    pub fn next_counter_reset_header(&self, first: &Histogram) -> CounterResetHeader {
+        if self.counter_reset_header == CounterResetHeader::Gauge {
+            return CounterResetHeader::Gauge;
+        }
+        match self.samples.last() {
+            Some(last) if !first.has_comparable_layout(&last.histogram) => {
+                CounterResetHeader::Unknown
+            }
+            Some(last) if first.detect_counter_reset(&last.histogram) => {
+                CounterResetHeader::CounterReset
+            }
+            Some(_) => CounterResetHeader::NotCounterReset,
+            None => CounterResetHeader::Unknown,
+        }
+    }
+}
+
+pub(crate) fn read_counter_reset_header(input: &[u8]) -> IResult<&[u8], CounterResetHeader> {
+    let (remaining_input, byte) = read_u8(input)?;
+    Ok((remaining_input, CounterResetHeader::from_byte(byte)))
+}
+
+fn too_large_error(input: NomBitInput) -> nom::Err<nom::error::Error<NomBitInput>> {
+    nom::Err::Error(nom::error::Error::new(
+        input,
+        nom::error::ErrorKind::TooLarge,
+    ))
+}
+
+pub(crate) fn read_f64_bits(input: NomBitInput) -> IResult<NomBitInput, f64> {
+    let (remaining_input, value_bits): (_, u64) = take(64usize)(input)?;
+    Ok((remaining_input, f64::from_bits(value_bits)))
+}
+
+/// Reads the zero threshold of a histogram chunk layout.
+///
+/// Prometheus stores 0 as a 0 byte, and the powers of two
+/// between 2^-243 and 2^10 as a single byte containing the exponent.
+/// Anything else is a 255 byte followed by the 64 bits float.
+fn read_zero_threshold(input: NomBitInput) -> IResult<NomBitInput, f64> {
+    let (remaining_input, byte): (_, u8) = take(8usize)(input)?;
+    match byte {
+        0 => Ok((remaining_input, 0.0)),
+        255 => read_f64_bits(remaining_input),
+        _ => {
+            // 0.5 * 2^(byte - 243), built directly from the IEEE 754 biased exponent.
+            let exponent = byte as u64 + 1023 - 244;
+            Ok((remaining_input, f64::from_bits(exponent << 52)))
+        }
+    }
+}
+
+fn read_spans(input: NomBitInput) -> IResult<NomBitInput, Vec<Span>> {
+    let (mut remaining_input, num_spans) = read_varbit_uint(input)?;
+
+    // No capacity reservation, the number of spans comes from the input.
+    let mut spans = Vec::new();
+    for _ in 0..num_spans {
+        let (tmp_remaining_input, (length, offset)) =
+            tuple((read_varbit_uint, read_varbit_int))(remaining_input)?;
+        remaining_input = tmp_remaining_input;
+
+        spans.push(Span {
+            offset: i32::try_from(offset).map_err(|_| too_large_error(remaining_input))?,
+            length: u32::try_from(length).map_err(|_| too_large_error(remaining_input))?,
+        });
+    }
+
+    Ok((remaining_input, spans))
+}
+
+/// Reads a custom bucket bound.
+///
+/// Bounds with at most 3 decimals, between 0 and 33554.430, are stored
+/// as a varbit uint of the bound times 1000, plus one.
+/// Anything else is a 0 bit followed by the 64 bits float.
+fn read_custom_bound(input: NomBitInput) -> IResult<NomBitInput, f64> {
+    let (remaining_input, value) = read_varbit_uint(input)?;
+    if value == 0 {
+        return read_f64_bits(remaining_input);
+    }
+    Ok((remaining_input, (value - 1) as f64 / 1000.0))
+}
+
+fn read_custom_bounds(input: NomBitInput) -> IResult<NomBitInput, Vec<f64>> {
+    let (mut remaining_input, num_bounds) = read_varbit_uint(input)?;
+
+    // No capacity reservation, the number of bounds comes from the input.
+    let mut bounds = Vec::new();
+    for _ in 0..num_bounds {
+        let (tmp_remaining_input, bound) = read_custom_bound(remaining_input)?;
+        remaining_input = tmp_remaining_input;
+        bounds.push(bound);
+    }
+
+    Ok((remaining_input, bounds))
+}
+
+/// Reads the layout written by the first sample of a histogram chunk.
+///
+/// Histograms with custom buckets also have their bounds in the layout.
+pub(crate) fn read_histogram_chunk_layout(
+    input: NomBitInput,
+) -> IResult<NomBitInput, HistogramChunkLayout> {
+    let (remaining_input, (zero_threshold, schema, positive_spans, negative_spans)) =
+        tuple((read_zero_threshold, read_varbit_int, read_spans, read_spans))(input)?;
+    let schema = i32::try_from(schema).map_err(|_| too_large_error(remaining_input))?;
+
+    let (remaining_input, custom_values) = if is_custom_buckets_schema(schema) {
+        read_custom_bounds(remaining_input)?
+    } else {
+        (remaining_input, Vec::new())
+    };
+
+    Ok((
+        remaining_input,
+        HistogramChunkLayout {
+            schema,
+            zero_threshold,
+            positive_spans,
+            negative_spans,
+            custom_values,
+        },
+    ))
+}
+
+#[derive(Debug)]
+struct HistogramReadIterator {
+    timestamp: i64,
+    timestamp_delta: i64,
+    count: u64,
+    count_delta: i64,
+    zero_count: u64,
+    zero_count_delta: i64,
+    sum: f64,
+    leading_bits_count: u8,
+    trailing_bits_count: u8,
+    positive_buckets: Vec<i64>,
+    positive_buckets_delta: Vec<i64>,
+    negative_buckets: Vec<i64>,
+    negative_buckets_delta: Vec<i64>,
+}
+
+impl HistogramReadIterator {
+    fn histogram(&self, layout: &HistogramChunkLayout) -> Histogram {
+        if is_stale_nan(self.sum) {
+            return Histogram {
+                sum: self.sum,
+                ..Default::default()
+            };
+        }
+        Histogram {
+            schema: layout.schema,
+            zero_threshold: layout.zero_threshold,
+            zero_count: self.zero_count,
+            count: self.count,
+            sum: self.sum,
+            positive_spans: layout.positive_spans.clone(),
+            negative_spans: layout.negative_spans.clone(),
+            positive_buckets: self.positive_buckets.clone(),
+            negative_buckets: self.negative_buckets.clone(),
+            custom_values: layout.custom_values.clone(),
+        }
+    }
+}
+
+fn read_buckets(input: NomBitInput, num_buckets: usize) -> IResult<NomBitInput, Vec<i64>> {
+    let mut remaining_input = input;
+    let mut buckets = Vec::new();
+    for _ in 0..num_buckets {
+        let (tmp_remaining_input, bucket) = read_varbit_int(remaining_input)?;
+        remaining_input = tmp_remaining_input;
+        buckets.push(bucket);
+    }
+    Ok((remaining_input, buckets))
+}
+
+fn read_first_histogram_sample(
+    input: NomBitInput,
+) -> IResult<NomBitInput, (HistogramChunkLayout, HistogramReadIterator)> {
+    let (remaining_input, layout) = read_histogram_chunk_layout(input)?;
+
+    let (remaining_input, (timestamp, count, zero_count, sum)) = tuple((
+        read_varbit_int,
+        read_varbit_uint,
+        read_varbit_uint,
+        read_f64_bits,
+    ))(remaining_input)?;
+
+    let (remaining_input, positive_buckets) =
+        read_buckets(remaining_input, layout.num_positive_buckets())?;
+    let (remaining_input, negative_buckets) =
+        read_buckets(remaining_input, layout.num_negative_buckets())?;
+
+    let iterator = HistogramReadIterator {
+        timestamp,
+        timestamp_delta: 0,
+        count,
+        count_delta: 0,
+        zero_count,
+        zero_count_delta: 0,
+        sum,
+        leading_bits_count: 0,
+        trailing_bits_count: 0,
+        positive_buckets_delta: vec![0; positive_buckets.len()],
+        positive_buckets,
+        negative_buckets_delta: vec![0; negative_buckets.len()],
+        negative_buckets,
+    };
+
+    Ok((remaining_input, (layout, iterator)))
+}
+
+fn read_buckets_delta_of_delta<'a>(
+    input: NomBitInput<'a>,
+    buckets: &mut [i64],
+    buckets_delta: &mut [i64],
+) -> IResult<NomBitInput<'a>, ()> {
+    let mut remaining_input = input;
+    for (bucket, bucket_delta) in buckets.iter_mut().zip(buckets_delta.iter_mut()) {
+        let (tmp_remaining_input, delta_of_delta) = read_varbit_int(remaining_input)?;
+        remaining_input = tmp_remaining_input;
+        *bucket_delta = bucket_delta.wrapping_add(delta_of_delta);
+        *bucket = bucket.wrapping_add(*bucket_delta);
+    }
+    Ok((remaining_input, ()))
+}
+
+/// Reads the following samples of a histogram chunk.
+///
+/// The second sample is stored with single deltas, which is the same as
+/// delta of deltas when the previous deltas are 0, so it needs no special case.
+fn read_n_histogram_sample<'a>(
+    input: NomBitInput<'a>,
+    iterator: &mut HistogramReadIterator,
+) -> IResult<NomBitInput<'a>, ()> {
+    let (
+        remaining_input,
+        (
+            timestamp_delta_of_delta,
+            count_delta_of_delta,
+            zero_count_delta_of_delta,
+            (sum, leading_bits_count, trailing_bits_count),
+        ),
+    ) = tuple((
+        read_varbit_int,
+        read_varbit_int,
+        read_varbit_int,
+        read_varbit_xor(
+            iterator.sum,
+            iterator.leading_bits_count,
+            iterator.trailing_bits_count,
+        ),
+    ))(input)?;
+
+    iterator.timestamp_delta = iterator
+        .timestamp_delta
+        .wrapping_add(timestamp_delta_of_delta);
+    iterator.timestamp = iterator.timestamp.wrapping_add(iterator.timestamp_delta);
+    iterator.count_delta = iterator.count_delta.wrapping_add(count_delta_of_delta);
+    iterator.count = (iterator.count as i64).wrapping_add(iterator.count_delta) as u64;
+    iterator.zero_count_delta = iterator
+        .zero_count_delta
+        .wrapping_add(zero_count_delta_of_delta);
+    iterator.zero_count =
+        (iterator.zero_count as i64).wrapping_add(iterator.zero_count_delta) as u64;
+    iterator.sum = sum;
+    iterator.leading_bits_count = leading_bits_count;
+    iterator.trailing_bits_count = trailing_bits_count;
+
+    // Stale markers have no buckets.
+    if is_stale_nan(sum) {
+        return Ok((remaining_input, ()));
     }
 
-    fn compute_block_chunk_ref(&mut self, _file_index: u64, _chunks_addr: *const u8) {}
+    let (remaining_input, _) = read_buckets_delta_of_delta(
+        remaining_input,
+        &mut iterator.positive_buckets,
+        &mut iterator.positive_buckets_delta,
+    )?;
+    read_buckets_delta_of_delta(
+        remaining_input,
+        &mut iterator.negative_buckets,
+        &mut iterator.negative_buckets_delta,
+    )
+}
+
+fn read_histogram_samples<'a>(
+    num_samples: u16,
+) -> impl Fn(NomBitInput<'a>) -> IResult<NomBitInput<'a>, Vec<HistogramSample>> {
+    move |input: NomBitInput<'a>| {
+        let mut samples = Vec::with_capacity(num_samples as usize);
+        if num_samples == 0 {
+            return Ok((input, samples));
+        }
+
+        let (mut remaining_input, (layout, mut iterator)) = read_first_histogram_sample(input)?;
+        samples.push(HistogramSample {
+            timestamp: iterator.timestamp,
+            histogram: iterator.histogram(&layout),
+        });
+
+        for _ in 1..num_samples {
+            let (tmp_remaining_input, _) = read_n_histogram_sample(remaining_input, &mut iterator)?;
+            remaining_input = tmp_remaining_input;
+            samples.push(HistogramSample {
+                timestamp: iterator.timestamp,
+                histogram: iterator.histogram(&layout),
+            });
+        }
+
+        Ok((remaining_input, samples))
+    }
 }
 
+/// Reads a histogram chunk from the input data.
+///
+/// Please note that this function does not read the chunk header
+/// nor does it check the CRC32C checksum.
+///
+/// Use the `read_chunk` function if your histogram chunk comes with a header
+/// and a CRC32C checksum.
 pub fn read_histogram_chunk_data(input: &[u8]) -> IResult<&[u8], HistogramChunk> {
-    // An exercice left to the reader
-    Ok((input, HistogramChunk {}))
+    let (remaining_input, (num_samples, counter_reset_header)) =
+        tuple((be_u16, read_counter_reset_header))(input)?;
+
+    let (remaining_input, samples) = bits(read_histogram_samples(num_samples))(remaining_input)?;
+
+    Ok((
+        remaining_input,
+        HistogramChunk::new(samples, counter_reset_header),
+    ))
+}
+
+/// A streaming iterator over the samples of histogram chunk data.
+///
+/// Unlike [`read_histogram_chunk_data`], it decodes the samples one at a time.
+/// Seeking skips samples without building their histograms.
+/// Like Prometheus' iterators, it stops on the first decoding error,
+/// that is then available through [`HistogramIterator::err`].
+#[derive(Debug)]
+pub struct HistogramIterator<'a> {
+    input: NomBitInput<'a>,
+    num_samples: u16,
+    num_read: u16,
+    counter_reset_header: CounterResetHeader,
+    layout: Option<HistogramChunkLayout>,
+    state: Option<HistogramReadIterator>,
+    error: Option<RustyChunkEncError>,
+    /// Whether the iteration ended, after the last sample or on an error.
+    exhausted: bool,
+}
+
+impl<'a> HistogramIterator<'a> {
+    /// Creates an iterator over histogram chunk data, without the chunk header.
+    ///
+    /// It takes the same input as [`read_histogram_chunk_data`].
+    pub fn new(input: &'a [u8]) -> Self {
+        let mut iterator = Self {
+            input: (input, 0),
+            num_samples: 0,
+            num_read: 0,
+            counter_reset_header: CounterResetHeader::Unknown,
+            layout: None,
+            state: None,
+            error: None,
+            exhausted: false,
+        };
+        match tuple((be_u16, read_counter_reset_header))(input) {
+            Ok((remaining_input, (num_samples, counter_reset_header))) => {
+                iterator.input = (remaining_input, 0);
+                iterator.num_samples = num_samples;
+                iterator.counter_reset_header = counter_reset_header;
+            }
+            Err(error) => iterator.error = Some(error.into()),
+        }
+        iterator
+    }
+
+    /// Returns the error that stopped the iteration, if any.
+    pub fn err(&self) -> Option<&RustyChunkEncError> {
+        self.error.as_ref()
+    }
+
+    /// Returns the counter reset header of the chunk.
+    pub fn counter_reset_header(&self) -> CounterResetHeader {
+        self.counter_reset_header
+    }
+
+    /// Returns the timestamp of the current sample, none before the first
+    /// sample and once the iteration ended.
+    pub(crate) fn current_timestamp(&self) -> Option<i64> {
+        match &self.state {
+            Some(state) if !self.exhausted => Some(state.timestamp),
+            _ => None,
+        }
+    }
+
+    /// Returns the last sample read, if any.
+    pub fn at(&self) -> Option<HistogramSample> {
+        match (&self.layout, &self.state) {
+            (Some(layout), Some(state)) => Some(HistogramSample {
+                timestamp: state.timestamp,
+                histogram: state.histogram(layout),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Advances to the first sample with a timestamp equal or greater than `t`.
+    ///
+    /// Like Prometheus' `Iterator.Seek`, it does nothing if the current sample
+    /// is already there. It returns false when the iterator is exhausted,
+    /// or on errors.
+    pub fn seek(&mut self, t: i64) -> bool {
+        if self.exhausted {
+            return false;
+        }
+        loop {
+            match &self.state {
+                Some(state) if state.timestamp >= t => return true,
+                _ => {
+                    if !self.advance() {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Decodes the next sample, without building its histogram.
+    pub(crate) fn advance(&mut self) -> bool {
+        if self.error.is_some() || self.num_read >= self.num_samples {
+            self.exhausted = true;
+            return false;
+        }
+        let result = match self.state.as_mut() {
+            None => {
+                read_first_histogram_sample(self.input).map(|(remaining_input, (layout, state))| {
+                    self.layout = Some(layout);
+                    self.state = Some(state);
+                    remaining_input
+                })
+            }
+            Some(state) => read_n_histogram_sample(self.input, state)
+                .map(|(remaining_input, _)| remaining_input),
+        };
+        match result {
+            Ok(remaining_input) => {
+                self.input = remaining_input;
+                self.num_read += 1;
+                true
+            }
+            Err(error) => {
+                self.error = Some(RustyChunkEncError::from_bits_error(error));
+                self.exhausted = true;
+                false
+            }
+        }
+    }
+}
+
+impl Iterator for HistogramIterator<'_> {
+    type Item = HistogramSample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.advance() {
+            self.at()
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some((self.num_samples - self.num_read) as usize))
+    }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct FloatHistogramChunk {}
+/// A Prometheus float histogram chunk.
+///
+/// All the samples of a chunk share the same schema, zero threshold,
+/// and bucket spans.
+#[derive(Debug)]
+pub struct FloatHistogramChunk {
+    samples: Vec<FloatHistogramSample>,
+    counter_reset_header: CounterResetHeader,
+    block_chunk_ref: Option<u64>,
+    addr: Option<*const u8>,
+}
+
+/// A sample of a Prometheus float histogram chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FloatHistogramSample {
+    pub timestamp: i64,
+    pub histogram: FloatHistogram,
+}
+
+impl FloatHistogramSample {
+    /// Creates a staleness marker at the given timestamp.
+    pub fn stale(timestamp: i64) -> Self {
+        Self {
+            timestamp,
+            histogram: FloatHistogram {
+                sum: STALE_NAN,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Returns true if the sample is a staleness marker.
+    pub fn is_stale_marker(&self) -> bool {
+        is_stale_nan(self.histogram.sum)
+    }
+}
 
 impl ChunkWithBlockChunkRef for FloatHistogramChunk {
     fn block_chunk_ref(&self) -> Option<u64> {
-        None
+        self.block_chunk_ref
     }
 
-    fn compute_block_chunk_ref(&mut self, _file_index: u64, _chunks_addr: *const u8) {}
+    fn compute_block_chunk_ref(&mut self, file_index: u64, chunks_addr: *const u8) {
+        match self.addr {
+            Some(addr) => {
+                self.block_chunk_ref =
+                    Some((file_index << 32) | (addr as u64 - chunks_addr as u64));
+            }
+            None => self.block_chunk_ref = None,
+        }
+    }
+}
+
+impl PartialEq for FloatHistogramChunk {
+    fn eq(&self, other: &Self) -> bool {
+        self.samples == other.samples && self.counter_reset_header == other.counter_reset_header
+    }
+}
+
+impl FloatHistogramChunk {
+    /// Creates a new float histogram chunk with the given samples.
+    pub fn new(
+        samples: Vec<FloatHistogramSample>,
+        counter_reset_header: CounterResetHeader,
+    ) -> Self {
+        Self {
+            samples,
+            counter_reset_header,
+            block_chunk_ref: None,
+            addr: None,
+        }
+    }
+
+    /// Sets the memory address of the chunk.
+    pub(crate) fn set_addr(&mut self, addr: *const u8) {
+        self.addr = Some(addr);
+    }
+
+    /// Returns the samples of the chunk.
+    pub fn samples(&self) -> &[FloatHistogramSample] {
+        &self.samples
+    }
+
+    /// Returns the counter reset header of the chunk.
+    pub fn counter_reset_header(&self) -> CounterResetHeader {
+        self.counter_reset_header
+    }
+
+    /// Sets the counter reset header of the chunk.
+    pub fn set_counter_reset_header(&mut self, counter_reset_header: CounterResetHeader) {
+        self.counter_reset_header = counter_reset_header;
+    }
+
+    /// Returns the counter reset header of the chunk that follows this one in a series,
+    /// when the next chunk starts with the given float histogram.
+    ///
+    /// Gauge chunks are followed by gauge chunks. Otherwise, like Prometheus
+    /// when it cuts a new chunk, it's a counter reset if the histogram is
+    /// a counter reset compared to the last sample of this chunk.
+    /// It's unknown when their layouts can't be compared.
+    pub fn next_counter_reset_header(&self, first: &FloatHistogram) -> CounterResetHeader {
+        if self.counter_reset_header == CounterResetHeader::Gauge {
+            return CounterResetHeader::Gauge;
+        }
+        match self.samples.last() {
+            Some(last) if !first.has_comparable_layout(&last.histogram) => {
+                CounterResetHeader::Unknown
+            }
+            Some(last) if first.detect_counter_reset(&last.histogram) => {
+                CounterResetHeader::CounterReset
+            }
+            Some(_) => CounterResetHeader::NotCounterReset,
+            None => CounterResetHeader::Unknown,
+        }
+    }
+}
+
+/// A XOR-compressed float, with its own leading and trailing bits counts.
+#[derive(Debug, Clone, Copy)]
+struct XORValue {
+    value: f64,
+    leading_bits_count: u8,
+    trailing_bits_count: u8,
+}
+
+impl XORValue {
+    fn new(value: f64) -> Self {
+        Self {
+            value,
+            leading_bits_count: 0,
+            trailing_bits_count: 0,
+        }
+    }
+}
+
+fn read_xor_value<'a>(
+    input: NomBitInput<'a>,
+    xor_value: &mut XORValue,
+) -> IResult<NomBitInput<'a>, ()> {
+    let (remaining_input, (value, leading_bits_count, trailing_bits_count)) = read_varbit_xor(
+        xor_value.value,
+        xor_value.leading_bits_count,
+        xor_value.trailing_bits_count,
+    )(input)?;
+    *xor_value = XORValue {
+        value,
+        leading_bits_count,
+        trailing_bits_count,
+    };
+    Ok((remaining_input, ()))
+}
+
+#[derive(Debug)]
+struct FloatHistogramReadIterator {
+    timestamp: i64,
+    timestamp_delta: i64,
+    count: XORValue,
+    zero_count: XORValue,
+    sum: XORValue,
+    positive_buckets: Vec<XORValue>,
+    negative_buckets: Vec<XORValue>,
+}
+
+impl FloatHistogramReadIterator {
+    fn histogram(&self, layout: &HistogramChunkLayout) -> FloatHistogram {
+        if is_stale_nan(self.sum.value) {
+            return FloatHistogram {
+                sum: self.sum.value,
+                ..Default::default()
+            };
+        }
+        FloatHistogram {
+            schema: layout.schema,
+            zero_threshold: layout.zero_threshold,
+            zero_count: self.zero_count.value,
+            count: self.count.value,
+            sum: self.sum.value,
+            positive_spans: layout.positive_spans.clone(),
+            negative_spans: layout.negative_spans.clone(),
+            positive_buckets: self.positive_buckets.iter().map(|b| b.value).collect(),
+            negative_buckets: self.negative_buckets.iter().map(|b| b.value).collect(),
+            custom_values: layout.custom_values.clone(),
+        }
+    }
+}
+
+fn read_float_buckets(
+    input: NomBitInput,
+    num_buckets: usize,
+) -> IResult<NomBitInput, Vec<XORValue>> {
+    let mut remaining_input = input;
+    let mut buckets = Vec::new();
+    for _ in 0..num_buckets {
+        let (tmp_remaining_input, bucket) = read_f64_bits(remaining_input)?;
+        remaining_input = tmp_remaining_input;
+        buckets.push(XORValue::new(bucket));
+    }
+    Ok((remaining_input, buckets))
 }
 
+fn read_first_float_histogram_sample(
+    input: NomBitInput,
+) -> IResult<NomBitInput, (HistogramChunkLayout, FloatHistogramReadIterator)> {
+    let (remaining_input, layout) = read_histogram_chunk_layout(input)?;
+
+    // Unlike integer histograms, the counts are stored as raw 64 bits floats.
+    let (remaining_input, (timestamp, count, zero_count, sum)) =
+        tuple((read_varbit_int, read_f64_bits, read_f64_bits, read_f64_bits))(remaining_input)?;
+
+    let (remaining_input, positive_buckets) =
+        read_float_buckets(remaining_input, layout.num_positive_buckets())?;
+    let (remaining_input, negative_buckets) =
+        read_float_buckets(remaining_input, layout.num_negative_buckets())?;
+
+    let iterator = FloatHistogramReadIterator {
+        timestamp,
+        timestamp_delta: 0,
+        count: XORValue::new(count),
+        zero_count: XORValue::new(zero_count),
+        sum: XORValue::new(sum),
+        positive_buckets,
+        negative_buckets,
+    };
+
+    Ok((remaining_input, (layout, iterator)))
+}
+
+fn read_n_float_histogram_sample<'a>(
+    input: NomBitInput<'a>,
+    iterator: &mut FloatHistogramReadIterator,
+) -> IResult<NomBitInput<'a>, ()> {
+    let (remaining_input, timestamp_delta_of_delta) = read_varbit_int(input)?;
+    iterator.timestamp_delta = iterator
+        .timestamp_delta
+        .wrapping_add(timestamp_delta_of_delta);
+    iterator.timestamp = iterator.timestamp.wrapping_add(iterator.timestamp_delta);
+
+    let (remaining_input, _) = read_xor_value(remaining_input, &mut iterator.count)?;
+    let (remaining_input, _) = read_xor_value(remaining_input, &mut iterator.zero_count)?;
+    let (mut remaining_input, _) = read_xor_value(remaining_input, &mut iterator.sum)?;
+
+    // Stale markers have no buckets.
+    if is_stale_nan(iterator.sum.value) {
+        return Ok((remaining_input, ()));
+    }
+
+    for bucket in iterator
+        .positive_buckets
+        .iter_mut()
+        .chain(iterator.negative_buckets.iter_mut())
+    {
+        let (tmp_remaining_input, _) = read_xor_value(remaining_input, bucket)?;
+        remaining_input = tmp_remaining_input;
+    }
+
+    Ok((remaining_input, ()))
+}
+
+fn read_float_histogram_samples<'a>(
+    num_samples: u16,
+) -> impl Fn(NomBitInput<'a>) -> IResult<NomBitInput<'a>, Vec<FloatHistogramSample>> {
+    move |input: NomBitInput<'a>| {
+        let mut samples = Vec::with_capacity(num_samples as usize);
+        if num_samples == 0 {
+            return Ok((input, samples));
+        }
+
+        let (mut remaining_input, (layout, mut iterator)) =
+            read_first_float_histogram_sample(input)?;
+        samples.push(FloatHistogramSample {
+            timestamp: iterator.timestamp,
+            histogram: iterator.histogram(&layout),
+        });
+
+        for _ in 1..num_samples {
+            let (tmp_remaining_input, _) =
+                read_n_float_histogram_sample(remaining_input, &mut iterator)?;
+            remaining_input = tmp_remaining_input;
+            samples.push(FloatHistogramSample {
+                timestamp: iterator.timestamp,
+                histogram: iterator.histogram(&layout),
+            });
+        }
+
+        Ok((remaining_input, samples))
+    }
+}
+
+/// Reads a float histogram chunk from the input data.
+///
+/// Please note that this function does not read the chunk header
+/// nor does it check the CRC32C checksum.
+///
+/// Use the `read_chunk` function if your float histogram chunk comes
+/// with a header and a CRC32C checksum.
 pub fn read_float_histogram_chunk_data(input: &[u8]) -> IResult<&[u8], FloatHistogramChunk> {
-    // An exercice left to the reader
-    Ok((input, FloatHistogramChunk {}))
+    let (remaining_input, (num_samples, counter_reset_header)) =
+        tuple((be_u16, read_counter_reset_header))(input)?;
+
+    let (remaining_input, samples) =
+        bits(read_float_histogram_samples(num_samples))(remaining_input)?;
+
+    Ok((
+        remaining_input,
+        FloatHistogramChunk::new(samples, counter_reset_header),
+    ))
+}
+
+/// A streaming iterator over the samples of float histogram chunk data.
+///
+/// Unlike [`read_float_histogram_chunk_data`], it decodes the samples one at a time.
+/// Seeking skips samples without building their histograms.
+/// Like Prometheus' iterators, it stops on the first decoding error,
+/// that is then available through [`FloatHistogramIterator::err`].
+#[derive(Debug)]
+pub struct FloatHistogramIterator<'a> {
+    input: NomBitInput<'a>,
+    num_samples: u16,
+    num_read: u16,
+    counter_reset_header: CounterResetHeader,
+    layout: Option<HistogramChunkLayout>,
+    state: Option<FloatHistogramReadIterator>,
+    error: Option<RustyChunkEncError>,
+    /// Whether the iteration ended, after the last sample or on an error.
+    exhausted: bool,
+}
+
+impl<'a> FloatHistogramIterator<'a> {
+    /// Creates an iterator over float histogram chunk data, without the chunk header.
+    ///
+    /// It takes the same input as [`read_float_histogram_chunk_data`].
+    pub fn new(input: &'a [u8]) -> Self {
+        let mut iterator = Self {
+            input: (input, 0),
+            num_samples: 0,
+            num_read: 0,
+            counter_reset_header: CounterResetHeader::Unknown,
+            layout: None,
+            state: None,
+            error: None,
+            exhausted: false,
+        };
+        match tuple((be_u16, read_counter_reset_header))(input) {
+            Ok((remaining_input, (num_samples, counter_reset_header))) => {
+                iterator.input = (remaining_input, 0);
+                iterator.num_samples = num_samples;
+                iterator.counter_reset_header = counter_reset_header;
+            }
+            Err(error) => iterator.error = Some(error.into()),
+        }
+        iterator
+    }
+
+    /// Returns the error that stopped the iteration, if any.
+    pub fn err(&self) -> Option<&RustyChunkEncError> {
+        self.error.as_ref()
+    }
+
+    /// Returns the counter reset header of the chunk.
+    pub fn counter_reset_header(&self) -> CounterResetHeader {
+        self.counter_reset_header
+    }
+
+    /// Returns the timestamp of the current sample, none before the first
+    /// sample and once the iteration ended.
+    pub(crate) fn current_timestamp(&self) -> Option<i64> {
+        match &self.state {
+            Some(state) if !self.exhausted => Some(state.timestamp),
+            _ => None,
+        }
+    }
+
+    /// Returns the last sample read, if any.
+    pub fn at(&self) -> Option<FloatHistogramSample> {
+        match (&self.layout, &self.state) {
+            (Some(layout), Some(state)) => Some(FloatHistogramSample {
+                timestamp: state.timestamp,
+                histogram: state.histogram(layout),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Advances to the first sample with a timestamp equal or greater than `t`.
+    ///
+    /// Like Prometheus' `Iterator.Seek`, it does nothing if the current sample
+    /// is already there. It returns false when the iterator is exhausted,
+    /// or on errors.
+    pub fn seek(&mut self, t: i64) -> bool {
+        if self.exhausted {
+            return false;
+        }
+        loop {
+            match &self.state {
+                Some(state) if state.timestamp >= t => return true,
+                _ => {
+                    if !self.advance() {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Decodes the next sample, without building its histogram.
+    pub(crate) fn advance(&mut self) -> bool {
+        if self.error.is_some() || self.num_read >= self.num_samples {
+            self.exhausted = true;
+            return false;
+        }
+        let result = match self.state.as_mut() {
+            None => read_first_float_histogram_sample(self.input).map(
+                |(remaining_input, (layout, state))| {
+                    self.layout = Some(layout);
+                    self.state = Some(state);
+                    remaining_input
+                },
+            ),
+            Some(state) => read_n_float_histogram_sample(self.input, state)
+                .map(|(remaining_input, _)| remaining_input),
+        };
+        match result {
+            Ok(remaining_input) => {
+                self.input = remaining_input;
+                self.num_read += 1;
+                true
+            }
+            Err(error) => {
+                self.error = Some(RustyChunkEncError::from_bits_error(error));
+                self.exhausted = true;
+                false
+            }
+        }
+    }
+}
+
+impl Iterator for FloatHistogramIterator<'_> {
+    type Item = FloatHistogramSample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.advance() {
+            self.at()
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some((self.num_samples - self.num_read) as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitstream_io::{BigEndian, BitWrite, BitWriter};
+
+    use crate::varbit_xor::write_varbit_xor;
+
+    use super::*;
+
+    #[test]
+    fn test_read_zero_threshold() {
+        // The default zero threshold, 2^-128
+        let (_, value) = read_zero_threshold((&[116], 0)).unwrap();
+        assert_eq!(value, 2f64.powi(-128));
+
+        let (_, value) = read_zero_threshold((&[0], 0)).unwrap();
+        assert_eq!(value, 0.0);
+
+        let (_, value) = read_zero_threshold((&[1], 0)).unwrap();
+        assert_eq!(value, 2f64.powi(-243));
+
+        let (_, value) = read_zero_threshold((&[254], 0)).unwrap();
+        assert_eq!(value, 1024.0);
+
+        let mut input = vec![255];
+        input.extend_from_slice(&0.001f64.to_be_bytes());
+        let (_, value) = read_zero_threshold((&input, 0)).unwrap();
+        assert_eq!(value, 0.001);
+    }
+
+    #[test]
+    fn test_read_histogram_chunk() {
+        // A chunk written by hand, following Prometheus' HistogramAppender
+        let mut buffer: Vec<u8> = vec![0x00, 0x02, 0b0100_0000];
+        let mut bit_writer = BitWriter::endian(&mut buffer, BigEndian);
+
+        // Layout: default zero threshold, schema 0,
+        // one positive span of 2 buckets at offset 0, no negative spans.
+        bit_writer.write(8, 116u8).unwrap();
+        bit_writer.write_bit(false).unwrap();
+        bit_writer.write(5, 0b10_001u8).unwrap();
+        bit_writer.write(5, 0b10_010u8).unwrap();
+        bit_writer.write_bit(false).unwrap();
+        bit_writer.write_bit(false).unwrap();
+
+        // First sample: timestamp 1000, count 5, zero count 1, sum 12.5,
+        // buckets 2 and 2 (delta-encoded as 2 and 0).
+        bit_writer.write(5, 0b11110u8).unwrap();
+        bit_writer.write(12, 1000u16).unwrap();
+        bit_writer.write(5, 0b10_101u8).unwrap();
+        bit_writer.write(5, 0b10_001u8).unwrap();
+        bit_writer.write(64, 12.5f64.to_bits()).unwrap();
+        bit_writer.write(5, 0b10_010u8).unwrap();
+        bit_writer.write_bit(false).unwrap();
+
+        // Second sample: timestamp delta 15, count delta 3, zero count delta 0,
+        // sum 20.0, buckets delta 1 and 1.
+        bit_writer.write(3, 0b110u8).unwrap();
+        bit_writer.write(6, 15u8).unwrap();
+        bit_writer.write(5, 0b10_011u8).unwrap();
+        bit_writer.write_bit(false).unwrap();
+        write_varbit_xor(20.0, 12.5, 0xff, 0, &mut bit_writer).unwrap();
+        bit_writer.write(5, 0b10_001u8).unwrap();
+        bit_writer.write(5, 0b10_001u8).unwrap();
+        bit_writer.byte_align().unwrap();
+
+        let (remaining_input, chunk) = read_histogram_chunk_data(&buffer).unwrap();
+        assert!(remaining_input.is_empty());
+        assert_eq!(
+            chunk.counter_reset_header(),
+            CounterResetHeader::NotCounterReset
+        );
+
+        let spans = vec![Span {
+            offset: 0,
+            length: 2,
+        }];
+        assert_eq!(
+            chunk.samples(),
+            &[
+                HistogramSample {
+                    timestamp: 1000,
+                    histogram: Histogram {
+                        schema: 0,
+                        zero_threshold: 2f64.powi(-128),
+                        zero_count: 1,
+                        count: 5,
+                        sum: 12.5,
+                        positive_spans: spans.clone(),
+                        negative_spans: vec![],
+                        positive_buckets: vec![2, 0],
+                        negative_buckets: vec![],
+                        custom_values: vec![],
+                    },
+                },
+                HistogramSample {
+                    timestamp: 1015,
+                    histogram: Histogram {
+                        schema: 0,
+                        zero_threshold: 2f64.powi(-128),
+                        zero_count: 1,
+                        count: 8,
+                        sum: 20.0,
+                        positive_spans: spans,
+                        negative_spans: vec![],
+                        positive_buckets: vec![3, 1],
+                        negative_buckets: vec![],
+                        custom_values: vec![],
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_empty_histogram_chunk() {
+        let input = [0x00, 0x00, 0b1000_0000];
+        let (remaining_input, chunk) = read_histogram_chunk_data(&input).unwrap();
+        assert!(remaining_input.is_empty());
+        assert!(chunk.samples().is_empty());
+        assert_eq!(
+            chunk.counter_reset_header(),
+            CounterResetHeader::CounterReset
+        );
+    }
+
+    #[test]
+    fn test_read_truncated_histogram_chunk() {
+        let input = [0x00, 0x02, 0x00, 116];
+        assert!(read_histogram_chunk_data(&input).is_err());
+    }
+
+    #[test]
+    fn test_read_float_histogram_chunk() {
+        // A chunk written by hand, following Prometheus' FloatHistogramAppender
+        let mut buffer: Vec<u8> = vec![0x00, 0x03, 0b1100_0000];
+        let mut bit_writer = BitWriter::endian(&mut buffer, BigEndian);
+
+        // Layout: no zero threshold, schema -1, no positive spans,
+        // one negative span of 1 bucket at offset -2.
+        bit_writer.write(8, 0u8).unwrap();
+        bit_writer.write(5, 0b10_111u8).unwrap();
+        bit_writer.write_bit(false).unwrap();
+        bit_writer.write(5, 0b10_001u8).unwrap();
+        bit_writer.write(5, 0b10_001u8).unwrap();
+        bit_writer.write(5, 0b10_110u8).unwrap();
+
+        // First sample: timestamp 0, count 3, zero count 1, sum -4.5, bucket 2
+        bit_writer.write_bit(false).unwrap();
+        bit_writer.write(64, 3.0f64.to_bits()).unwrap();
+        bit_writer.write(64, 1.0f64.to_bits()).unwrap();
+        bit_writer.write(64, (-4.5f64).to_bits()).unwrap();
+        bit_writer.write(64, 2.0f64.to_bits()).unwrap();
+
+        // Second sample: timestamp 4, count 5, zero count 1, sum -9, bucket 4
+        bit_writer.write(5, 0b10_100u8).unwrap();
+        let (count_leading, count_trailing) =
+            write_varbit_xor(5.0, 3.0, 0xff, 0, &mut bit_writer).unwrap();
+        write_varbit_xor(1.0, 1.0, 0xff, 0, &mut bit_writer).unwrap();
+        let (sum_leading, sum_trailing) =
+            write_varbit_xor(-9.0, -4.5, 0xff, 0, &mut bit_writer).unwrap();
+        write_varbit_xor(4.0, 2.0, 0xff, 0, &mut bit_writer).unwrap();
+
+        // Third sample: a stale marker at timestamp 8, without buckets
+        bit_writer.write_bit(false).unwrap();
+        write_varbit_xor(0.0, 5.0, count_leading, count_trailing, &mut bit_writer).unwrap();
+        write_varbit_xor(0.0, 1.0, 0xff, 0, &mut bit_writer).unwrap();
+        write_varbit_xor(STALE_NAN, -9.0, sum_leading, sum_trailing, &mut bit_writer).unwrap();
+        bit_writer.byte_align().unwrap();
+
+        let (remaining_input, chunk) = read_float_histogram_chunk_data(&buffer).unwrap();
+        assert!(remaining_input.is_empty());
+        assert_eq!(chunk.counter_reset_header(), CounterResetHeader::Gauge);
+
+        let spans = vec![Span {
+            offset: -2,
+            length: 1,
+        }];
+        let samples = chunk.samples();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(
+            samples[0..2],
+            [
+                FloatHistogramSample {
+                    timestamp: 0,
+                    histogram: FloatHistogram {
+                        schema: -1,
+                        zero_threshold: 0.0,
+                        zero_count: 1.0,
+                        count: 3.0,
+                        sum: -4.5,
+                        positive_spans: vec![],
+                        negative_spans: spans.clone(),
+                        positive_buckets: vec![],
+                        negative_buckets: vec![2.0],
+                        custom_values: vec![],
+                    },
+                },
+                FloatHistogramSample {
+                    timestamp: 4,
+                    histogram: FloatHistogram {
+                        schema: -1,
+                        zero_threshold: 0.0,
+                        zero_count: 1.0,
+                        count: 5.0,
+                        sum: -9.0,
+                        positive_spans: vec![],
+                        negative_spans: spans,
+                        positive_buckets: vec![],
+                        negative_buckets: vec![4.0],
+                        custom_values: vec![],
+                    },
+                },
+            ]
+        );
+        assert_eq!(samples[2].timestamp, 8);
+        assert!(is_stale_nan(samples[2].histogram.sum));
+        assert!(samples[2].histogram.negative_buckets.is_empty());
+    }
+
+    #[test]
+    fn test_next_counter_reset_header() {
+        let histogram = |count: u64| Histogram {
+            count,
+            zero_count: count,
+            ..Default::default()
+        };
+        let sample = |count: u64| HistogramSample {
+            timestamp: 0,
+            histogram: histogram(count),
+        };
+
+        let chunk = HistogramChunk::new(vec![], CounterResetHeader::Unknown);
+        assert_eq!(
+            chunk.next_counter_reset_header(&histogram(1)),
+            CounterResetHeader::Unknown
+        );
+
+        let mut chunk =
+            HistogramChunk::new(vec![sample(1), sample(5)], CounterResetHeader::Unknown);
+        assert_eq!(
+            chunk.next_counter_reset_header(&histogram(5)),
+            CounterResetHeader::NotCounterReset
+        );
+        assert_eq!(
+            chunk.next_counter_reset_header(&histogram(4)),
+            CounterResetHeader::CounterReset
+        );
+
+        // The buckets can't be compared after a schema change, nor with stale markers.
+        assert_eq!(
+            chunk.next_counter_reset_header(&Histogram {
+                schema: 1,
+                ..histogram(5)
+            }),
+            CounterResetHeader::Unknown
+        );
+        assert_eq!(
+            chunk.next_counter_reset_header(&Histogram {
+                zero_threshold: 0.5,
+                ..histogram(4)
+            }),
+            CounterResetHeader::Unknown
+        );
+        assert_eq!(
+            chunk.next_counter_reset_header(&HistogramSample::stale(10).histogram),
+            CounterResetHeader::Unknown
+        );
+        let stale_chunk = HistogramChunk::new(
+            vec![sample(5), HistogramSample::stale(10)],
+            CounterResetHeader::Unknown,
+        );
+        assert_eq!(
+            stale_chunk.next_counter_reset_header(&histogram(5)),
+            CounterResetHeader::Unknown
+        );
+
+        chunk.set_counter_reset_header(CounterResetHeader::Gauge);
+        assert_eq!(chunk.counter_reset_header(), CounterResetHeader::Gauge);
+        assert_eq!(
+            chunk.next_counter_reset_header(&histogram(4)),
+            CounterResetHeader::Gauge
+        );
+
+        let chunk = FloatHistogramChunk::new(
+            vec![FloatHistogramSample {
+                timestamp: 0,
+                histogram: histogram(5).to_float(),
+            }],
+            CounterResetHeader::CounterReset,
+        );
+        assert_eq!(
+            chunk.next_counter_reset_header(&histogram(4).to_float()),
+            CounterResetHeader::CounterReset
+        );
+        assert_eq!(
+            chunk.next_counter_reset_header(&FloatHistogram {
+                schema: 2,
+                ..histogram(5).to_float()
+            }),
+            CounterResetHeader::Unknown
+        );
+        assert_eq!(
+            chunk.next_counter_reset_header(&FloatHistogramSample::stale(10).histogram),
+            CounterResetHeader::Unknown
+        );
+    }
+
+    fn test_histogram_samples() -> Vec<HistogramSample> {
+        (0..50)
+            .map(|i| HistogramSample {
+                timestamp: i * 1000,
+                histogram: Histogram {
+                    count: 3 * i as u64,
+                    zero_count: i as u64,
+                    sum: i as f64,
+                    positive_spans: vec![Span {
+                        offset: 0,
+                        length: 2,
+                    }],
+                    positive_buckets: vec![i, 0],
+                    ..Default::default()
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_histogram_iterator() {
+        let samples = test_histogram_samples();
+        let mut buffer: Vec<u8> = Vec::new();
+        HistogramChunk::new(samples.clone(), CounterResetHeader::NotCounterReset)
+            .write(&mut buffer)
+            .unwrap();
+
+        let mut iterator = HistogramIterator::new(&buffer);
+        assert!(iterator.at().is_none());
+        assert_eq!(
+            iterator.counter_reset_header(),
+            CounterResetHeader::NotCounterReset
+        );
+        assert_eq!((&mut iterator).collect::<Vec<_>>(), samples);
+        assert!(iterator.err().is_none());
+
+        let mut iterator = HistogramIterator::new(&buffer);
+        assert!(iterator.seek(20500));
+        assert_eq!(iterator.at().unwrap(), samples[21]);
+        assert!(iterator.seek(0));
+        assert_eq!(iterator.next().unwrap(), samples[22]);
+        assert!(!iterator.seek(50000));
+        assert!(iterator.next().is_none());
+
+        let mut iterator = HistogramIterator::new(&buffer[..buffer.len() / 2]);
+        assert!(!iterator.seek(50000));
+        assert!(iterator.err().is_some());
+        assert!(HistogramIterator::new(&[0x00]).err().is_some());
+    }
+
+    #[test]
+    fn test_float_histogram_iterator() {
+        let samples = test_histogram_samples()
+            .into_iter()
+            .map(|sample| FloatHistogramSample {
+                timestamp: sample.timestamp,
+                histogram: sample.histogram.to_float(),
+            })
+            .collect::<Vec<_>>();
+        let mut buffer: Vec<u8> = Vec::new();
+        FloatHistogramChunk::new(samples.clone(), CounterResetHeader::Gauge)
+            .write(&mut buffer)
+            .unwrap();
+
+        let mut iterator = FloatHistogramIterator::new(&buffer);
+        assert_eq!(iterator.counter_reset_header(), CounterResetHeader::Gauge);
+        assert!(iterator.seek(49000));
+        assert_eq!(iterator.at().unwrap(), samples[49]);
+        assert!(iterator.next().is_none());
+        assert!(iterator.err().is_none());
+
+        let iterator = FloatHistogramIterator::new(&buffer);
+        assert_eq!(iterator.collect::<Vec<_>>(), samples);
+    }
+
+    #[test]
+    fn test_stale_histogram_samples() {
+        let samples = vec![
+            test_histogram_samples().remove(1),
+            HistogramSample::stale(2000),
+        ];
+        assert!(!samples[0].is_stale_marker());
+        assert!(samples[1].is_stale_marker());
+
+        let mut buffer: Vec<u8> = Vec::new();
+        HistogramChunk::new(samples, CounterResetHeader::Unknown)
+            .write(&mut buffer)
+            .unwrap();
+        let (_, chunk) = read_histogram_chunk_data(&buffer).unwrap();
+        assert!(chunk.samples()[1].is_stale_marker());
+        assert_eq!(chunk.samples()[1].timestamp, 2000);
+
+        let samples = vec![
+            FloatHistogramSample::stale(1000),
+            FloatHistogramSample::stale(2000),
+        ];
+        let mut buffer: Vec<u8> = Vec::new();
+        FloatHistogramChunk::new(samples, CounterResetHeader::Gauge)
+            .write(&mut buffer)
+            .unwrap();
+        let (_, chunk) = read_float_histogram_chunk_data(&buffer).unwrap();
+        assert!(chunk
+            .samples()
+            .iter()
+            .all(FloatHistogramSample::is_stale_marker));
+    }
 }
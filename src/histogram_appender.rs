@@ -0,0 +1,603 @@
+use crate::{
+    errors::RustyChunkEncError,
+    histogram::{
+        CounterResetHeader, FloatHistogramChunk, FloatHistogramSample, HistogramChunk,
+        HistogramSample,
+    },
+    native_histogram::{merge_spans, same_custom_values, FloatHistogram, Histogram, Span},
+    value::is_stale_nan,
+};
+
+/// The number of samples of a chunk is stored in 16 bits.
+const MAX_SAMPLES_PER_CHUNK: usize = u16::MAX as usize;
+
+/// What to do with a new sample, compared to the last sample of the chunk.
+#[derive(Debug, PartialEq)]
+enum Appendable {
+    /// The sample has the same layout as the chunk.
+    Append,
+    /// The samples of the chunk and the new sample must move to new spans first.
+    Recode {
+        positive_spans: Vec<Span>,
+        negative_spans: Vec<Span>,
+    },
+    /// The sample must start a new chunk.
+    Cut,
+}
+
+/// The parts of the integer and float histograms the appenders look at.
+trait AppendableHistogram {
+    fn sum(&self) -> f64;
+    fn schema(&self) -> i32;
+    fn zero_threshold(&self) -> f64;
+    fn custom_values(&self) -> &[f64];
+    fn positive_spans(&self) -> &[Span];
+    fn negative_spans(&self) -> &[Span];
+    fn detect_counter_reset(&self, previous: &Self) -> bool;
+}
+
+impl AppendableHistogram for Histogram {
+    fn sum(&self) -> f64 {
+        self.sum
+    }
+    fn schema(&self) -> i32 {
+        self.schema
+    }
+    fn zero_threshold(&self) -> f64 {
+        self.zero_threshold
+    }
+    fn custom_values(&self) -> &[f64] {
+        &self.custom_values
+    }
+    fn positive_spans(&self) -> &[Span] {
+        &self.positive_spans
+    }
+    fn negative_spans(&self) -> &[Span] {
+        &self.negative_spans
+    }
+    fn detect_counter_reset(&self, previous: &Self) -> bool {
+        Histogram::detect_counter_reset(self, previous)
+    }
+}
+
+impl AppendableHistogram for FloatHistogram {
+    fn sum(&self) -> f64 {
+        self.sum
+    }
+    fn schema(&self) -> i32 {
+        self.schema
+    }
+    fn zero_threshold(&self) -> f64 {
+        self.zero_threshold
+    }
+    fn custom_values(&self) -> &[f64] {
+        &self.custom_values
+    }
+    fn positive_spans(&self) -> &[Span] {
+        &self.positive_spans
+    }
+    fn negative_spans(&self) -> &[Span] {
+        &self.negative_spans
+    }
+    fn detect_counter_reset(&self, previous: &Self) -> bool {
+        FloatHistogram::detect_counter_reset(self, previous)
+    }
+}
+
+/// Decides how to append a histogram after the last sample of a chunk.
+///
+/// It follows Prometheus' `appendable` and `appendableGauge`:
+/// - Stale markers can always be appended, but only stale markers can follow them.
+/// - A change of schema, zero threshold, or custom bounds cuts a new chunk.
+/// - For counters, a counter reset cuts a new chunk, and so do missing buckets.
+///   New buckets recode the chunk.
+/// - For gauges, both missing and new buckets recode the chunk.
+fn appendable<H: AppendableHistogram>(last: &H, histogram: &H, gauge: bool) -> Appendable {
+    if is_stale_nan(histogram.sum()) {
+        return Appendable::Append;
+    }
+    if is_stale_nan(last.sum()) {
+        return Appendable::Cut;
+    }
+    if histogram.schema() != last.schema()
+        || histogram.zero_threshold().to_bits() != last.zero_threshold().to_bits()
+        || !same_custom_values(histogram.custom_values(), last.custom_values())
+    {
+        return Appendable::Cut;
+    }
+
+    let (positive_spans, negative_spans) = if gauge {
+        (
+            merge_spans(last.positive_spans(), histogram.positive_spans()),
+            merge_spans(last.negative_spans(), histogram.negative_spans()),
+        )
+    } else {
+        // Missing buckets are counter resets, so the new spans cover the previous ones.
+        if histogram.detect_counter_reset(last) {
+            return Appendable::Cut;
+        }
+        (
+            histogram.positive_spans().to_vec(),
+            histogram.negative_spans().to_vec(),
+        )
+    };
+
+    if positive_spans == last.positive_spans()
+        && negative_spans == last.negative_spans()
+        && positive_spans == histogram.positive_spans()
+        && negative_spans == histogram.negative_spans()
+    {
+        Appendable::Append
+    } else {
+        Appendable::Recode {
+            positive_spans,
+            negative_spans,
+        }
+    }
+}
+
+fn unsorted_error(timestamp: i64, last_timestamp: i64) -> RustyChunkEncError {
+    RustyChunkEncError::InvalidHistogram(format!(
+        "timestamp {} is before the last timestamp {}",
+        timestamp, last_timestamp
+    ))
+}
+
+/// Appends histogram samples to chunks, like Prometheus' `HistogramAppender`.
+///
+/// All the samples of a histogram chunk share the same layout. When a sample
+/// brings new buckets, the samples of the open chunk are recoded with the new
+/// spans. A new chunk is only cut when that isn't possible, such as on counter
+/// resets or schema changes, and its counter reset header is set accordingly.
+#[derive(Debug, Default)]
+pub struct HistogramAppender {
+    samples: Vec<HistogramSample>,
+    counter_reset_header: CounterResetHeader,
+    gauge: bool,
+}
+
+impl HistogramAppender {
+    /// Creates an appender for a counter histogram series.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an appender for a gauge histogram series.
+    ///
+    /// Buckets can disappear from gauge histograms without cutting a new chunk,
+    /// and all the chunks have the gauge counter reset header.
+    pub fn new_gauge() -> Self {
+        Self {
+            counter_reset_header: CounterResetHeader::Gauge,
+            gauge: true,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the samples of the open chunk.
+    pub fn samples(&self) -> &[HistogramSample] {
+        &self.samples
+    }
+
+    /// Returns the counter reset header of the open chunk.
+    pub fn counter_reset_header(&self) -> CounterResetHeader {
+        self.counter_reset_header
+    }
+
+    /// Appends a sample.
+    ///
+    /// Returns the previous chunk when the sample had to start a new chunk.
+    pub fn append(
+        &mut self,
+        timestamp: i64,
+        histogram: Histogram,
+    ) -> Result<Option<HistogramChunk>, RustyChunkEncError> {
+        if !is_stale_nan(histogram.sum) {
+            histogram.validate()?;
+        }
+
+        let last = match self.samples.last() {
+            Some(last) => last,
+            None => {
+                self.samples.push(HistogramSample {
+                    timestamp,
+                    histogram,
+                });
+                return Ok(None);
+            }
+        };
+        if timestamp < last.timestamp {
+            return Err(unsorted_error(timestamp, last.timestamp));
+        }
+
+        let decision = if self.samples.len() >= MAX_SAMPLES_PER_CHUNK {
+            Appendable::Cut
+        } else {
+            appendable(&last.histogram, &histogram, self.gauge)
+        };
+
+        let mut histogram = histogram;
+        let mut previous_chunk = None;
+        match decision {
+            Appendable::Append => {}
+            Appendable::Recode {
+                positive_spans,
+                negative_spans,
+            } => {
+                for sample in self.samples.iter_mut() {
+                    if !is_stale_nan(sample.histogram.sum) {
+                        sample.histogram = sample
+                            .histogram
+                            .expand_spans(&positive_spans, &negative_spans);
+                    }
+                }
+                histogram = histogram.expand_spans(&positive_spans, &negative_spans);
+            }
+            Appendable::Cut => {
+                // Only a detected reset is known; a layout change or a stale marker
+                // leaves the new chunk's header unknown.
+                let counter_reset_header = if self.gauge {
+                    CounterResetHeader::Gauge
+                } else if histogram.detect_counter_reset(&last.histogram) {
+                    CounterResetHeader::CounterReset
+                } else {
+                    CounterResetHeader::Unknown
+                };
+                previous_chunk = Some(HistogramChunk::new(
+                    std::mem::take(&mut self.samples),
+                    std::mem::replace(&mut self.counter_reset_header, counter_reset_header),
+                ));
+            }
+        }
+
+        self.samples.push(HistogramSample {
+            timestamp,
+            histogram,
+        });
+        Ok(previous_chunk)
+    }
+
+    /// Returns the open chunk, if it has samples.
+    pub fn finish(self) -> Option<HistogramChunk> {
+        if self.samples.is_empty() {
+            None
+        } else {
+            Some(HistogramChunk::new(self.samples, self.counter_reset_header))
+        }
+    }
+}
+
+/// Appends float histogram samples to chunks, like Prometheus' `FloatHistogramAppender`.
+///
+/// See [`HistogramAppender`].
+#[derive(Debug, Default)]
+pub struct FloatHistogramAppender {
+    samples: Vec<FloatHistogramSample>,
+    counter_reset_header: CounterResetHeader,
+    gauge: bool,
+}
+
+impl FloatHistogramAppender {
+    /// Creates an appender for a counter float histogram series.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an appender for a gauge float histogram series.
+    ///
+    /// Buckets can disappear from gauge histograms without cutting a new chunk,
+    /// and all the chunks have the gauge counter reset header.
+    pub fn new_gauge() -> Self {
+        Self {
+            counter_reset_header: CounterResetHeader::Gauge,
+            gauge: true,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the samples of the open chunk.
+    pub fn samples(&self) -> &[FloatHistogramSample] {
+        &self.samples
+    }
+
+    /// Returns the counter reset header of the open chunk.
+    pub fn counter_reset_header(&self) -> CounterResetHeader {
+        self.counter_reset_header
+    }
+
+    /// Appends a sample.
+    ///
+    /// Returns the previous chunk when the sample had to start a new chunk.
+    pub fn append(
+        &mut self,
+        timestamp: i64,
+        histogram: FloatHistogram,
+    ) -> Result<Option<FloatHistogramChunk>, RustyChunkEncError> {
+        if !is_stale_nan(histogram.sum) {
+            histogram.validate()?;
+        }
+
+        let last = match self.samples.last() {
+            Some(last) => last,
+            None => {
+                self.samples.push(FloatHistogramSample {
+                    timestamp,
+                    histogram,
+                });
+                return Ok(None);
+            }
+        };
+        if timestamp < last.timestamp {
+            return Err(unsorted_error(timestamp, last.timestamp));
+        }
+
+        let decision = if self.samples.len() >= MAX_SAMPLES_PER_CHUNK {
+            Appendable::Cut
+        } else {
+            appendable(&last.histogram, &histogram, self.gauge)
+        };
+
+        let mut histogram = histogram;
+        let mut previous_chunk = None;
+        match decision {
+            Appendable::Append => {}
+            Appendable::Recode {
+                positive_spans,
+                negative_spans,
+            } => {
+                for sample in self.samples.iter_mut() {
+                    if !is_stale_nan(sample.histogram.sum) {
+                        sample.histogram = sample
+                            .histogram
+                            .expand_spans(&positive_spans, &negative_spans);
+                    }
+                }
+                histogram = histogram.expand_spans(&positive_spans, &negative_spans);
+            }
+            Appendable::Cut => {
+                // Only a detected reset is known; a layout change or a stale marker
+                // leaves the new chunk's header unknown.
+                let counter_reset_header = if self.gauge {
+                    CounterResetHeader::Gauge
+                } else if histogram.detect_counter_reset(&last.histogram) {
+                    CounterResetHeader::CounterReset
+                } else {
+                    CounterResetHeader::Unknown
+                };
+                previous_chunk = Some(FloatHistogramChunk::new(
+                    std::mem::take(&mut self.samples),
+                    std::mem::replace(&mut self.counter_reset_header, counter_reset_header),
+                ));
+            }
+        }
+
+        self.samples.push(FloatHistogramSample {
+            timestamp,
+            histogram,
+        });
+        Ok(previous_chunk)
+    }
+
+    /// Returns the open chunk, if it has samples.
+    pub fn finish(self) -> Option<FloatHistogramChunk> {
+        if self.samples.is_empty() {
+            None
+        } else {
+            Some(FloatHistogramChunk::new(
+                self.samples,
+                self.counter_reset_header,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::histogram::{read_float_histogram_chunk_data, read_histogram_chunk_data};
+
+    use super::*;
+    use crate::value::STALE_NAN;
+
+    /// A counter histogram with absolute counts in the buckets at the given indices.
+    fn histogram(buckets: &[(i32, u64)]) -> Histogram {
+        let mut previous = 0i64;
+        let mut positive_spans: Vec<Span> = Vec::new();
+        let mut next_index = 0;
+        for (index, _) in buckets {
+            match positive_spans.last_mut() {
+                Some(span) if *index == next_index => span.length += 1,
+                _ => positive_spans.push(Span {
+                    offset: index - next_index,
+                    length: 1,
+                }),
+            }
+            next_index = index + 1;
+        }
+        Histogram {
+            count: buckets.iter().map(|(_, count)| count).sum(),
+            sum: 1.5,
+            positive_spans,
+            positive_buckets: buckets
+                .iter()
+                .map(|(_, count)| {
+                    let delta = *count as i64 - previous;
+                    previous = *count as i64;
+                    delta
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn counts(histogram: &Histogram) -> Vec<(i32, u64)> {
+        histogram
+            .positive_bucket_iterator()
+            .map(|bucket| (bucket.index, bucket.count))
+            .collect()
+    }
+
+    #[test]
+    fn test_recode_on_new_buckets() {
+        let mut appender = HistogramAppender::new();
+        assert!(appender
+            .append(1000, histogram(&[(0, 1), (1, 2)]))
+            .unwrap()
+            .is_none());
+        assert!(appender
+            .append(2000, histogram(&[(0, 1), (1, 3), (4, 1)]))
+            .unwrap()
+            .is_none());
+        assert!(appender
+            .append(3000, histogram(&[(-1, 2), (0, 1), (1, 3), (4, 1)]))
+            .unwrap()
+            .is_none());
+
+        let samples = appender.samples();
+        assert_eq!(samples.len(), 3);
+        for sample in samples {
+            assert_eq!(
+                sample.histogram.positive_spans,
+                samples[2].histogram.positive_spans
+            );
+        }
+        assert_eq!(
+            counts(&samples[0].histogram),
+            vec![(-1, 0), (0, 1), (1, 2), (4, 0)]
+        );
+
+        let chunk = appender.finish().unwrap();
+        assert_eq!(chunk.counter_reset_header(), CounterResetHeader::Unknown);
+        let mut buffer: Vec<u8> = Vec::new();
+        chunk.write(&mut buffer).unwrap();
+        let (_, parsed_chunk) = read_histogram_chunk_data(&buffer).unwrap();
+        assert_eq!(parsed_chunk, chunk);
+    }
+
+    #[test]
+    fn test_cut_new_chunks() {
+        let mut appender = HistogramAppender::new();
+        appender.append(1000, histogram(&[(0, 1), (1, 2)])).unwrap();
+
+        // A bucket goes down
+        let chunk = appender
+            .append(2000, histogram(&[(0, 1), (1, 1)]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunk.samples().len(), 1);
+        assert_eq!(
+            appender.counter_reset_header(),
+            CounterResetHeader::CounterReset
+        );
+
+        // A bucket disappears
+        let chunk = appender
+            .append(3000, histogram(&[(1, 2)]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            chunk.counter_reset_header(),
+            CounterResetHeader::CounterReset
+        );
+        assert_eq!(
+            appender.counter_reset_header(),
+            CounterResetHeader::CounterReset
+        );
+
+        // The schema changes
+        let chunk = appender
+            .append(
+                4000,
+                Histogram {
+                    schema: 1,
+                    ..histogram(&[(1, 2)])
+                },
+            )
+            .unwrap();
+        assert!(chunk.is_some());
+        assert_eq!(appender.counter_reset_header(), CounterResetHeader::Unknown);
+
+        // Only stale markers can follow stale markers
+        let stale = Histogram {
+            sum: STALE_NAN,
+            ..Default::default()
+        };
+        assert!(appender.append(5000, stale).unwrap().is_none());
+        assert!(appender
+            .append(6000, histogram(&[(1, 2)]))
+            .unwrap()
+            .is_some());
+        assert_eq!(appender.counter_reset_header(), CounterResetHeader::Unknown);
+
+        assert!(appender.append(5000, histogram(&[(1, 2)])).is_err());
+        assert!(appender
+            .append(
+                7000,
+                Histogram {
+                    count: 3,
+                    ..histogram(&[(1, 2)])
+                }
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_gauge_histogram_appender() {
+        let mut appender = HistogramAppender::new_gauge();
+        appender.append(1000, histogram(&[(0, 5), (1, 2)])).unwrap();
+        assert!(appender
+            .append(2000, histogram(&[(1, 1), (2, 3)]))
+            .unwrap()
+            .is_none());
+
+        let samples = appender.samples();
+        assert_eq!(counts(&samples[0].histogram), vec![(0, 5), (1, 2), (2, 0)]);
+        assert_eq!(counts(&samples[1].histogram), vec![(0, 0), (1, 1), (2, 3)]);
+
+        let chunk = appender
+            .append(
+                3000,
+                Histogram {
+                    zero_threshold: 0.5,
+                    ..histogram(&[(1, 1)])
+                },
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunk.counter_reset_header(), CounterResetHeader::Gauge);
+        assert_eq!(appender.counter_reset_header(), CounterResetHeader::Gauge);
+    }
+
+    #[test]
+    fn test_float_histogram_appender() {
+        let mut appender = FloatHistogramAppender::new();
+        assert!(FloatHistogramAppender::new().finish().is_none());
+
+        appender
+            .append(1000, histogram(&[(0, 1), (1, 2)]).to_float())
+            .unwrap();
+        assert!(appender
+            .append(2000, histogram(&[(0, 1), (1, 3), (3, 1)]).to_float())
+            .unwrap()
+            .is_none());
+        let chunk = appender
+            .append(3000, histogram(&[(0, 1), (1, 3)]).to_float())
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunk.samples().len(), 2);
+        assert_eq!(
+            chunk.samples()[0].histogram.positive_buckets,
+            vec![1.0, 2.0, 0.0]
+        );
+
+        let mut buffer: Vec<u8> = Vec::new();
+        chunk.write(&mut buffer).unwrap();
+        let (_, parsed_chunk) = read_float_histogram_chunk_data(&buffer).unwrap();
+        assert_eq!(parsed_chunk, chunk);
+
+        let chunk = appender.finish().unwrap();
+        assert_eq!(
+            chunk.counter_reset_header(),
+            CounterResetHeader::CounterReset
+        );
+    }
+}
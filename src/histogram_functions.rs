@@ -0,0 +1,407 @@
+use crate::native_histogram::{is_custom_buckets_schema, Bucket, FloatHistogram};
+
+/// A cumulative bucket of a classic Prometheus histogram.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClassicBucket {
+    /// The upper bound of the bucket, inclusive.
+    pub le: f64,
+    /// The number of observations less than or equal to the upper bound.
+    pub count: f64,
+}
+
+/// Returns the number of observations of the histogram, like PromQL's `histogram_count`.
+pub fn histogram_count(histogram: &FloatHistogram) -> f64 {
+    histogram.count
+}
+
+/// Returns the sum of the observations of the histogram, like PromQL's `histogram_sum`.
+pub fn histogram_sum(histogram: &FloatHistogram) -> f64 {
+    histogram.sum
+}
+
+/// Returns whether the bucket contains zero, with the histogram's bounds.
+fn is_zero_bucket(bucket: &Bucket<f64>) -> bool {
+    bucket.lower <= 0.0 && bucket.upper >= 0.0
+}
+
+/// Uses 0 as a bound of the zero bucket when the histogram only has observations
+/// on one side, like Prometheus.
+fn trim_zero_bucket(bucket: &mut Bucket<f64>, histogram: &FloatHistogram) {
+    if histogram.negative_buckets.is_empty() && !histogram.positive_buckets.is_empty() {
+        bucket.lower = 0.0;
+    } else if histogram.positive_buckets.is_empty() && !histogram.negative_buckets.is_empty() {
+        bucket.upper = 0.0;
+    }
+}
+
+/// Estimates the q-quantile of the histogram, like PromQL's `histogram_quantile`.
+///
+/// The interpolation follows Prometheus: it's exponential within exponential
+/// buckets, and linear within the zero bucket and the custom buckets.
+/// The first custom bucket is assumed to start at 0 when its upper bound is positive,
+/// and the quantiles falling in the +Inf custom bucket are its lower bound.
+///
+/// A q below 0 gives -Inf, above 1 gives +Inf, and an empty histogram gives NaN.
+pub fn histogram_quantile(q: f64, histogram: &FloatHistogram) -> f64 {
+    if q < 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if q > 1.0 {
+        return f64::INFINITY;
+    }
+    if histogram.count == 0.0 || q.is_nan() {
+        return f64::NAN;
+    }
+
+    // NaN observations are counted but in no bucket, so they need the forward
+    // iteration. Otherwise, the high quantiles are more precise backwards.
+    let forward = histogram.sum.is_nan() || q < 0.5;
+    let (buckets, mut rank): (Box<dyn Iterator<Item = Bucket<f64>>>, f64) = if forward {
+        (
+            Box::new(histogram.all_bucket_iterator()),
+            q * histogram.count,
+        )
+    } else {
+        (
+            Box::new(histogram.all_bucket_iterator().rev()),
+            (1.0 - q) * histogram.count,
+        )
+    };
+
+    let mut bucket = None;
+    let mut count = 0.0;
+    for current in buckets {
+        let current_count = current.count;
+        bucket = Some(current);
+        if current_count == 0.0 {
+            continue;
+        }
+        count += current_count;
+        if count >= rank {
+            break;
+        }
+    }
+    let mut bucket = match bucket {
+        Some(bucket) => bucket,
+        None => return f64::NAN,
+    };
+
+    let custom_buckets = is_custom_buckets_schema(histogram.schema);
+    if custom_buckets {
+        if bucket.lower == f64::NEG_INFINITY {
+            if bucket.upper <= 0.0 {
+                return bucket.upper;
+            }
+            bucket.lower = 0.0;
+        } else if bucket.upper == f64::INFINITY {
+            return bucket.lower;
+        }
+    } else if bucket.lower < 0.0 && bucket.upper > 0.0 {
+        trim_zero_bucket(&mut bucket, histogram);
+    }
+
+    // Due to numerical inaccuracies, the count could be higher than the histogram's count.
+    if count > histogram.count {
+        count = histogram.count;
+    }
+    // The highest bucket can be below the rank when there are NaN observations.
+    if count < rank {
+        return bucket.upper;
+    }
+
+    if forward {
+        rank -= count - bucket.count;
+    } else {
+        rank = count - rank;
+    }
+    let fraction = rank / bucket.count;
+
+    if custom_buckets || is_zero_bucket(&bucket) {
+        return bucket.lower + (bucket.upper - bucket.lower) * fraction;
+    }
+
+    let log_lower = bucket.lower.abs().log2();
+    let log_upper = bucket.upper.abs().log2();
+    if bucket.lower > 0.0 {
+        (log_lower + (log_upper - log_lower) * fraction).exp2()
+    } else {
+        // The negative buckets are mirrored.
+        -(log_upper + (log_lower - log_upper) * (1.0 - fraction)).exp2()
+    }
+}
+
+/// Estimates the fraction of the observations between the lower and upper values,
+/// like PromQL's `histogram_fraction`.
+///
+/// It uses the same interpolation as [`histogram_quantile`].
+/// An empty histogram or a NaN bound gives NaN.
+pub fn histogram_fraction(lower: f64, upper: f64, histogram: &FloatHistogram) -> f64 {
+    if histogram.count == 0.0 || lower.is_nan() || upper.is_nan() {
+        return f64::NAN;
+    }
+    if lower >= upper {
+        return 0.0;
+    }
+
+    let custom_buckets = is_custom_buckets_schema(histogram.schema);
+    let mut rank = 0.0;
+    let mut lower_rank = None;
+    let mut upper_rank = None;
+
+    for mut bucket in histogram.all_bucket_iterator() {
+        let zero_bucket = !custom_buckets && is_zero_bucket(&bucket);
+        if zero_bucket {
+            trim_zero_bucket(&mut bucket, histogram);
+        }
+
+        let interpolate = |value: f64| {
+            if custom_buckets || zero_bucket {
+                // Like histogram_quantile, the infinite custom buckets
+                // have their observations at their finite bound.
+                if bucket.lower == f64::NEG_INFINITY {
+                    if bucket.upper > 0.0 {
+                        return rank + bucket.count * value.max(0.0) / bucket.upper;
+                    }
+                    return rank;
+                }
+                if bucket.upper == f64::INFINITY {
+                    return rank + bucket.count;
+                }
+                return rank
+                    + bucket.count * (value - bucket.lower) / (bucket.upper - bucket.lower);
+            }
+            let log_lower = bucket.lower.abs().log2();
+            let log_upper = bucket.upper.abs().log2();
+            let log_value = value.abs().log2();
+            let fraction = if value > 0.0 {
+                (log_value - log_lower) / (log_upper - log_lower)
+            } else {
+                1.0 - (log_value - log_upper) / (log_lower - log_upper)
+            };
+            rank + bucket.count * fraction
+        };
+
+        if lower_rank.is_none() {
+            if bucket.lower >= lower {
+                lower_rank = Some(rank);
+            } else if bucket.upper > lower {
+                lower_rank = Some(interpolate(lower));
+            }
+        }
+        if upper_rank.is_none() {
+            if bucket.lower >= upper {
+                upper_rank = Some(rank);
+            } else if bucket.upper > upper {
+                upper_rank = Some(interpolate(upper));
+            }
+        }
+        if lower_rank.is_some() && upper_rank.is_some() {
+            break;
+        }
+        rank += bucket.count;
+    }
+
+    let lower_rank = lower_rank.unwrap_or(histogram.count).min(histogram.count);
+    let upper_rank = upper_rank.unwrap_or(histogram.count).min(histogram.count);
+    (upper_rank - lower_rank) / histogram.count
+}
+
+/// Converts the histogram to cumulative classic buckets.
+///
+/// Each bucket of the histogram, including the zero bucket when it has
+/// observations, becomes a classic bucket with its upper bound as `le`.
+/// The last classic bucket is always `+Inf`, with the total count.
+pub fn to_classic_buckets(histogram: &FloatHistogram) -> Vec<ClassicBucket> {
+    let mut classic_buckets = Vec::new();
+    let mut count = 0.0;
+    for bucket in histogram.all_bucket_iterator() {
+        count += bucket.count;
+        if bucket.upper == f64::INFINITY {
+            break;
+        }
+        classic_buckets.push(ClassicBucket {
+            le: bucket.upper,
+            count,
+        });
+    }
+    classic_buckets.push(ClassicBucket {
+        le: f64::INFINITY,
+        count: histogram.count,
+    });
+    classic_buckets
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::native_histogram::{Span, CUSTOM_BUCKETS_SCHEMA};
+
+    use super::*;
+
+    fn assert_near(value: f64, expected: f64) {
+        assert!(
+            (value - expected).abs() < 1e-12,
+            "{} is not {}",
+            value,
+            expected
+        );
+    }
+
+    /// Buckets (0.5, 1], (1, 2], and (2, 4].
+    fn exponential_histogram() -> FloatHistogram {
+        FloatHistogram {
+            zero_threshold: 0.001,
+            count: 4.0,
+            sum: 7.0,
+            positive_spans: vec![Span {
+                offset: 0,
+                length: 3,
+            }],
+            positive_buckets: vec![1.0, 2.0, 1.0],
+            ..Default::default()
+        }
+    }
+
+    /// Buckets (-Inf, 1], (1, 2], and (2, +Inf].
+    fn custom_buckets_histogram() -> FloatHistogram {
+        FloatHistogram {
+            schema: CUSTOM_BUCKETS_SCHEMA,
+            count: 5.0,
+            sum: 8.0,
+            positive_spans: vec![Span {
+                offset: 0,
+                length: 3,
+            }],
+            positive_buckets: vec![2.0, 2.0, 1.0],
+            custom_values: vec![1.0, 2.0],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_histogram_count_and_sum() {
+        assert_eq!(histogram_count(&exponential_histogram()), 4.0);
+        assert_eq!(histogram_sum(&exponential_histogram()), 7.0);
+    }
+
+    #[test]
+    fn test_histogram_quantile() {
+        let histogram = exponential_histogram();
+        assert_eq!(histogram_quantile(-0.1, &histogram), f64::NEG_INFINITY);
+        assert_eq!(histogram_quantile(1.1, &histogram), f64::INFINITY);
+        assert!(histogram_quantile(f64::NAN, &histogram).is_nan());
+        assert!(histogram_quantile(0.5, &FloatHistogram::default()).is_nan());
+
+        assert_near(histogram_quantile(0.0, &histogram), 0.5);
+        assert_near(histogram_quantile(0.25, &histogram), 1.0);
+        // Exponential interpolation within (1, 2]
+        assert_near(histogram_quantile(0.5, &histogram), 2f64.sqrt());
+        assert_near(histogram_quantile(1.0, &histogram), 4.0);
+
+        // NaN observations are in no bucket
+        let histogram = FloatHistogram {
+            count: 5.0,
+            sum: f64::NAN,
+            ..exponential_histogram()
+        };
+        assert_near(histogram_quantile(1.0, &histogram), 4.0);
+
+        // Negative buckets are mirrored
+        let histogram = FloatHistogram {
+            count: 4.0,
+            negative_spans: vec![Span {
+                offset: 1,
+                length: 1,
+            }],
+            negative_buckets: vec![4.0],
+            ..Default::default()
+        };
+        assert_near(histogram_quantile(0.5, &histogram), -(2f64.sqrt()));
+
+        // Linear interpolation within the zero bucket, starting at 0
+        let histogram = FloatHistogram {
+            zero_threshold: 0.5,
+            zero_count: 2.0,
+            count: 4.0,
+            positive_spans: vec![Span {
+                offset: 0,
+                length: 1,
+            }],
+            positive_buckets: vec![2.0],
+            ..Default::default()
+        };
+        assert_near(histogram_quantile(0.25, &histogram), 0.25);
+    }
+
+    #[test]
+    fn test_histogram_quantile_with_custom_buckets() {
+        let histogram = custom_buckets_histogram();
+        // The first bucket starts at 0
+        assert_near(histogram_quantile(0.2, &histogram), 0.5);
+        // Linear interpolation within (1, 2]
+        assert_near(histogram_quantile(0.5, &histogram), 1.25);
+        // The +Inf bucket gives its lower bound
+        assert_near(histogram_quantile(0.99, &histogram), 2.0);
+    }
+
+    #[test]
+    fn test_histogram_fraction() {
+        let histogram = exponential_histogram();
+        assert!(histogram_fraction(0.0, 1.0, &FloatHistogram::default()).is_nan());
+        assert!(histogram_fraction(f64::NAN, 1.0, &histogram).is_nan());
+        assert_eq!(histogram_fraction(2.0, 1.0, &histogram), 0.0);
+
+        assert_near(
+            histogram_fraction(f64::NEG_INFINITY, f64::INFINITY, &histogram),
+            1.0,
+        );
+        assert_near(histogram_fraction(1.0, 2.0, &histogram), 0.5);
+        assert_near(histogram_fraction(0.0, 1.0, &histogram), 0.25);
+        assert_near(histogram_fraction(2f64.sqrt(), 4.0, &histogram), 0.5);
+
+        let histogram = custom_buckets_histogram();
+        assert_near(histogram_fraction(0.0, 1.5, &histogram), 0.6);
+        assert_near(histogram_fraction(2.0, 100.0, &histogram), 0.2);
+    }
+
+    #[test]
+    fn test_to_classic_buckets() {
+        let bucket = |le, count| ClassicBucket { le, count };
+        assert_eq!(
+            to_classic_buckets(&exponential_histogram()),
+            vec![
+                bucket(1.0, 1.0),
+                bucket(2.0, 3.0),
+                bucket(4.0, 4.0),
+                bucket(f64::INFINITY, 4.0)
+            ]
+        );
+        assert_eq!(
+            to_classic_buckets(&custom_buckets_histogram()),
+            vec![
+                bucket(1.0, 2.0),
+                bucket(2.0, 4.0),
+                bucket(f64::INFINITY, 5.0)
+            ]
+        );
+
+        let histogram = FloatHistogram {
+            zero_threshold: 0.25,
+            zero_count: 1.0,
+            count: 3.0,
+            negative_spans: vec![Span {
+                offset: 0,
+                length: 1,
+            }],
+            negative_buckets: vec![2.0],
+            ..Default::default()
+        };
+        assert_eq!(
+            to_classic_buckets(&histogram),
+            vec![
+                bucket(-0.5, 2.0),
+                bucket(0.25, 3.0),
+                bucket(f64::INFINITY, 3.0)
+            ]
+        );
+    }
+}
@@ -4,7 +4,23 @@
 //! ## Features
 //!
 //! - Parse Prometheus XOR-encoded chunks (that are heavily inspired by [Gorilla](https://www.vldb.org/pvldb/vol8/p1816-teller.pdf)).
+//! - Parse Prometheus native histogram and float histogram chunks, including custom buckets.
 //! - Serialise time series to Prometheus XOR-encoded chunks.
+//! - Append samples to new or existing XOR-encoded chunks one at a time, without re-encoding them.
+//! - Cut long series into chunks like the Prometheus head does.
+//! - Merge and deduplicate overlapping chunks of a series.
+//! - Slice chunks to a time range.
+//! - Report how well XOR chunks compress.
+//! - Compute the encoded size of XOR chunks and samples before writing them.
+//! - Quantise noisy values before the XOR encoding, to shrink the chunks within a known error.
+//! - Read, write, and downsample to Thanos aggregate chunks.
+//! - Decode XOR chunks into reusable timestamp and value arrays.
+//! - Iterate over the samples of any chunk encoding with one iterator, like Prometheus' `chunkenc.Iterator`.
+//! - Serialise native histograms to Prometheus histogram and float histogram chunks.
+//! - Append native histograms to chunks, recoding them when new buckets appear, like Prometheus.
+//! - Compute `histogram_quantile`, `histogram_fraction`, and classic `le` buckets from native histograms.
+//! - Add, subtract, and scale native histograms with different schemas.
+//! - Write and detect Prometheus staleness markers.
 //! - Read Prometheus' cold data directly from the disk.
 //! - Also comes with utilities to read and write `varint`, `uvarint`, `varbit`, `varbit_ts`, and `varbit_xor` numbers.
 //!
@@ -90,24 +106,50 @@
 //! println!("parsed_chunk: {:?}", parsed_chunk);
 //! ```
 
+/// Thanos' aggregate chunks, used in downsampled blocks.
+pub mod aggr_chunk;
+/// Buffered bit reader, used to decode XOR chunks.
+pub mod bit_reader;
 /// Single Prometheus chunk.
 pub mod chunk;
+/// Merges overlapping chunks of a series.
+pub mod chunk_merger;
 /// Prometheus chunks disk format.
 pub mod chunks;
+/// Columnar decoding of XOR chunks into timestamp and value arrays.
+pub mod columnar;
+/// Compression statistics of XOR chunks.
+pub mod compression_stats;
 mod crc32c;
+/// Thanos-style downsampling of raw chunks into aggregate chunks.
+pub mod downsample;
 mod encoder;
 mod errors;
 /// WIP: Parse all prometheus data from the prometheus folder.
 pub mod folder;
-/// Histogram and Float Histogram chunks, not implemented yet.
+/// Histogram and Float Histogram chunks.
 pub mod histogram;
+/// Histogram appenders, that recode or cut chunks when the bucket layout changes.
+pub mod histogram_appender;
+/// PromQL functions on native histograms.
+pub mod histogram_functions;
 /// WIP: Prometheus index files
 pub mod index;
+/// Prometheus' native histograms.
+pub mod native_histogram;
+/// Lossy quantisation of values before the XOR encoding.
+pub mod quantisation;
+/// Unified sample iterator across chunk encodings, like Prometheus' `chunkenc.Iterator`.
+pub mod sample_iterator;
 mod series;
+/// Cuts long series into chunks, like the Prometheus head.
+pub mod series_chunker;
 mod symbol_table;
 mod toc;
 /// Golang's uvarint.
 pub mod uvarint;
+/// Prometheus' special values, like the staleness marker.
+pub mod value;
 /// Prometheus's varbit encoding.
 pub mod varbit;
 /// Prometheus's varbit timestamp encoding.
@@ -118,14 +160,30 @@ pub mod varbit_xor;
 pub mod varint;
 /// XOR chunk.
 pub mod xor;
+/// XOR appender, that appends samples to an encoded chunk in place.
+pub mod xor_appender;
 
 type NomBitInput<'a> = (&'a [u8], usize);
 
 // Re-exports
 pub use chunk::read_chunk;
+pub use chunk::slice_chunks;
 pub use chunk::Chunk;
 
 pub use chunks::read_chunks;
 pub use chunks::ChunksDiskFormat;
 
+pub use errors::RustyChunkEncError;
+
+pub use histogram::FloatHistogramIterator;
+pub use histogram::HistogramIterator;
+
+pub use sample_iterator::ChunkSampleIterator;
+pub use sample_iterator::SampleIterator;
+pub use sample_iterator::ValueType;
+
+pub use series_chunker::SeriesChunker;
+
+pub use xor::XORIterator;
 pub use xor::XORSample;
+pub use xor_appender::XORAppender;
@@ -0,0 +1,1963 @@
+use crate::{errors::RustyChunkEncError, value::is_stale_nan};
+
+/// The schema of native histograms with custom buckets (NHCB).
+///
+/// Their buckets have explicit upper bounds, like classic histograms,
+/// and only use the positive side.
+pub const CUSTOM_BUCKETS_SCHEMA: i32 = -53;
+
+/// Returns whether the schema is the custom buckets schema.
+#[inline]
+pub fn is_custom_buckets_schema(schema: i32) -> bool {
+    schema == CUSTOM_BUCKETS_SCHEMA
+}
+
+/// A span of consecutive buckets in a native histogram.
+///
+/// The offset is the gap in buckets since the end of the previous span,
+/// or the index of the first bucket for the first span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub offset: i32,
+    pub length: u32,
+}
+
+/// A Prometheus native histogram with integer counts.
+///
+/// Like in Prometheus, the buckets are delta-encoded: the first bucket
+/// holds an absolute count, and the following buckets hold the difference
+/// with the previous bucket.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Histogram {
+    pub schema: i32,
+    pub zero_threshold: f64,
+    pub zero_count: u64,
+    pub count: u64,
+    pub sum: f64,
+    pub positive_spans: Vec<Span>,
+    pub negative_spans: Vec<Span>,
+    pub positive_buckets: Vec<i64>,
+    pub negative_buckets: Vec<i64>,
+    /// The upper bounds of the custom buckets, when the schema is [`CUSTOM_BUCKETS_SCHEMA`].
+    pub custom_values: Vec<f64>,
+}
+
+/// A Prometheus native histogram with float counts.
+///
+/// Unlike [`Histogram`], the buckets hold absolute counts.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FloatHistogram {
+    pub schema: i32,
+    pub zero_threshold: f64,
+    pub zero_count: f64,
+    pub count: f64,
+    pub sum: f64,
+    pub positive_spans: Vec<Span>,
+    pub negative_spans: Vec<Span>,
+    pub positive_buckets: Vec<f64>,
+    pub negative_buckets: Vec<f64>,
+    /// The upper bounds of the custom buckets, when the schema is [`CUSTOM_BUCKETS_SCHEMA`].
+    pub custom_values: Vec<f64>,
+}
+
+/// A bucket of a native histogram, with its absolute bounds and count.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bucket<T> {
+    pub lower: f64,
+    pub upper: f64,
+    pub lower_inclusive: bool,
+    pub upper_inclusive: bool,
+    pub count: T,
+    pub index: i32,
+}
+
+/// Multiplies by 2^exponent, in two steps to not overflow too early.
+fn ldexp(fraction: f64, exponent: i32) -> f64 {
+    let half = exponent / 2;
+    fraction * 2f64.powi(half) * 2f64.powi(exponent - half)
+}
+
+/// The fractions of the bucket bounds in [0.5, 1) for the schemas 0 to 8, from
+/// Prometheus' `exponentialBounds` table.
+///
+/// Prometheus generated them by multiplying by `2^(2^-schema)` with Go's `math.Exp2`,
+/// so some are one ulp away from the correctly rounded `2^(i/2^schema) / 2`.
+#[rustfmt::skip]
+const EXPONENTIAL_BOUNDS: [&[f64]; 9] = [
+    // Schema 0:
+    &[
+        0.5,
+    ],
+    // Schema 1:
+    &[
+        0.5, 0.7071067811865475,
+    ],
+    // Schema 2:
+    &[
+        0.5, 0.5946035575013605, 0.7071067811865475, 0.8408964152537144,
+    ],
+    // Schema 3:
+    &[
+        0.5, 0.5452538663326288, 0.5946035575013605, 0.6484197773255048,
+        0.7071067811865475, 0.7711054127039704, 0.8408964152537144, 0.9170040432046711,
+    ],
+    // Schema 4:
+    &[
+        0.5, 0.5221368912137069, 0.5452538663326288, 0.5693943173783458,
+        0.5946035575013605, 0.620928906036742, 0.6484197773255048, 0.6771277734684463,
+        0.7071067811865475, 0.7384130729697496, 0.7711054127039704, 0.805245165974627,
+        0.8408964152537144, 0.8781260801866495, 0.9170040432046711, 0.9576032806985735,
+    ],
+    // Schema 5:
+    &[
+        0.5, 0.5109485743270583, 0.5221368912137069, 0.5335702003384117,
+        0.5452538663326288, 0.5571933712979462, 0.5693943173783458, 0.5818624293887887,
+        0.5946035575013605, 0.6076236799902344, 0.620928906036742, 0.6345254785958666,
+        0.6484197773255048, 0.6626183215798706, 0.6771277734684463, 0.6919549409819159,
+        0.7071067811865475, 0.7225904034885232, 0.7384130729697496, 0.7545822137967112,
+        0.7711054127039704, 0.7879904225539431, 0.805245165974627, 0.8228777390769823,
+        0.8408964152537144, 0.8593096490612387, 0.8781260801866495, 0.8973545375015533,
+        0.9170040432046711, 0.9370838170551498, 0.9576032806985735, 0.9785720620876999,
+    ],
+    // Schema 6:
+    &[
+        0.5, 0.5054446430258502, 0.5109485743270583, 0.5165124395106142,
+        0.5221368912137069, 0.5278225891802786, 0.5335702003384117, 0.5393803988785598,
+        0.5452538663326288, 0.5511912916539204, 0.5571933712979462, 0.5632608093041209,
+        0.5693943173783458, 0.5755946149764913, 0.5818624293887887, 0.5881984958251406,
+        0.5946035575013605, 0.6010783657263515, 0.6076236799902344, 0.6142402680534349,
+        0.620928906036742, 0.6276903785123455, 0.6345254785958666, 0.6414350080393891,
+        0.6484197773255048, 0.6554806057623822, 0.6626183215798706, 0.6698337620266515,
+        0.6771277734684463, 0.6845012114872953, 0.6919549409819159, 0.6994898362691555,
+        0.7071067811865475, 0.7148066691959849, 0.7225904034885232, 0.7304588970903234,
+        0.7384130729697496, 0.7464538641456323, 0.7545822137967112, 0.762799075372269,
+        0.7711054127039704, 0.7795022001189185, 0.7879904225539431, 0.7965710756711334,
+        0.805245165974627, 0.8140137109286738, 0.8228777390769823, 0.8318382901633681,
+        0.8408964152537144, 0.8500531768592616, 0.8593096490612387, 0.8686669176368529,
+        0.8781260801866495, 0.8876882462632604, 0.8973545375015533, 0.9071260877501991,
+        0.9170040432046711, 0.9269895625416926, 0.9370838170551498, 0.9472879907934827,
+        0.9576032806985735, 0.9680308967461471, 0.9785720620876999, 0.9892280131939752,
+    ],
+    // Schema 7:
+    &[
+        0.5, 0.5027149505564014, 0.5054446430258502, 0.5081891574554764,
+        0.5109485743270583, 0.5137229745593818, 0.5165124395106142, 0.5193170509806894,
+        0.5221368912137069, 0.5249720429003435, 0.5278225891802786, 0.5306886136446309,
+        0.5335702003384117, 0.5364674337629877, 0.5393803988785598, 0.5423091811066545,
+        0.5452538663326288, 0.5482145409081883, 0.5511912916539204, 0.5541842058618393,
+        0.5571933712979462, 0.5602188762048033, 0.5632608093041209, 0.5663192597993595,
+        0.5693943173783458, 0.572486072215902, 0.5755946149764913, 0.5787200368168754,
+        0.5818624293887887, 0.585021884841625, 0.5881984958251406, 0.5913923554921704,
+        0.5946035575013605, 0.5978321960199137, 0.6010783657263515, 0.6043421618132907,
+        0.6076236799902344, 0.6109230164863786, 0.6142402680534349, 0.6175755319684665,
+        0.620928906036742, 0.6243004885946023, 0.6276903785123455, 0.6310986751971253,
+        0.6345254785958666, 0.637970889198196, 0.6414350080393891, 0.6449179367033329,
+        0.6484197773255048, 0.6519406325959679, 0.6554806057623822, 0.659039800633032,
+        0.6626183215798706, 0.6662162735415805, 0.6698337620266515, 0.6734708931164728,
+        0.6771277734684463, 0.6808045103191123, 0.6845012114872953, 0.688217985377265,
+        0.6919549409819159, 0.6957121878859629, 0.6994898362691555, 0.7032879969095076,
+        0.7071067811865475, 0.7109463010845827, 0.7148066691959849, 0.718687998724491,
+        0.7225904034885232, 0.7265139979245261, 0.7304588970903234, 0.7344252166684908,
+        0.7384130729697496, 0.7424225829363761, 0.7464538641456323, 0.7505070348132126,
+        0.7545822137967112, 0.7586795205991071, 0.762799075372269, 0.7669409989204777,
+        0.7711054127039704, 0.7752924388424999, 0.7795022001189185, 0.7837348199827764,
+        0.7879904225539431, 0.7922691326262467, 0.7965710756711334, 0.8008963778413465,
+        0.805245165974627, 0.8096175675974316, 0.8140137109286738, 0.8184337248834821,
+        0.8228777390769823, 0.8273458838280969, 0.8318382901633681, 0.8363550898207981,
+        0.8408964152537144, 0.8454623996346523, 0.8500531768592616, 0.8546688815502312,
+        0.8593096490612387, 0.8639756154809185, 0.8686669176368529, 0.8733836930995842,
+        0.8781260801866495, 0.8828942179666361, 0.8876882462632604, 0.8925083056594671,
+        0.8973545375015533, 0.9022270839033115, 0.9071260877501991, 0.9120516927035263,
+        0.9170040432046711, 0.9219832844793128, 0.9269895625416926, 0.9320230241988943,
+        0.9370838170551498, 0.9421720895161669, 0.9472879907934827, 0.9524316709088368,
+        0.9576032806985735, 0.9628029718180622, 0.9680308967461471, 0.9732872087896164,
+        0.9785720620876999, 0.9838856116165875, 0.9892280131939752, 0.9945994234836328,
+    ],
+    // Schema 8:
+    &[
+        0.5, 0.5013556375251013, 0.5027149505564014, 0.5040779490592088,
+        0.5054446430258502, 0.5068150424757447, 0.5081891574554764, 0.509566998038869,
+        0.5109485743270583, 0.5123338964485679, 0.5137229745593818, 0.5151158188430205,
+        0.5165124395106142, 0.5179128468009786, 0.5193170509806894, 0.520725062344158,
+        0.5221368912137069, 0.5235525479396449, 0.5249720429003435, 0.526395386502313,
+        0.5278225891802786, 0.5292536613972564, 0.5306886136446309, 0.5321274564422321,
+        0.5335702003384117, 0.5350168559101208, 0.5364674337629877, 0.5379219445313954,
+        0.5393803988785598, 0.5408428074966075, 0.5423091811066545, 0.5437795304588847,
+        0.5452538663326288, 0.5467321995364429, 0.5482145409081883, 0.549700901315111,
+        0.5511912916539204, 0.5526857228508706, 0.5541842058618393, 0.5556867516724088,
+        0.5571933712979462, 0.5587040757836845, 0.5602188762048033, 0.5617377836665098,
+        0.5632608093041209, 0.564787964283144, 0.5663192597993595, 0.5678547070789026,
+        0.5693943173783458, 0.5709381019847808, 0.572486072215902, 0.5740382394200894,
+        0.5755946149764913, 0.5771552102951081, 0.5787200368168754, 0.5802891060137493,
+        0.5818624293887887, 0.5834400184762408, 0.585021884841625, 0.5866080400818185,
+        0.5881984958251406, 0.5897932637314379, 0.5913923554921704, 0.5929957828304968,
+        0.5946035575013605, 0.5962156912915756, 0.5978321960199137, 0.5994530835371903,
+        0.6010783657263515, 0.6027080545025619, 0.6043421618132907, 0.6059806996384005,
+        0.6076236799902344, 0.6092711149137041, 0.6109230164863786, 0.6125793968185725,
+        0.6142402680534349, 0.6159056423670379, 0.6175755319684665, 0.6192499490999082,
+        0.620928906036742, 0.622612415087629, 0.6243004885946023, 0.6259931389331581,
+        0.6276903785123455, 0.6293922197748583, 0.6310986751971253, 0.6328097572894031,
+        0.6345254785958666, 0.6362458516947014, 0.637970889198196, 0.6397006037528346,
+        0.6414350080393891, 0.6431741147730128, 0.6449179367033329, 0.6466664866145447,
+        0.6484197773255048, 0.6501778216898253, 0.6519406325959679, 0.6537082229673385,
+        0.6554806057623822, 0.6572577939746774, 0.659039800633032, 0.6608266388015788,
+        0.6626183215798706, 0.6644148621029772, 0.6662162735415805, 0.6680225691020727,
+        0.6698337620266515, 0.6716498655934177, 0.6734708931164728, 0.6752968579460171,
+        0.6771277734684463, 0.6789636531064505, 0.6808045103191123, 0.6826503586020058,
+        0.6845012114872953, 0.6863570825438342, 0.688217985377265, 0.690083933630119,
+        0.6919549409819159, 0.6938310211492645, 0.6957121878859629, 0.6975984549830999,
+        0.6994898362691555, 0.7013863456101023, 0.7032879969095076, 0.7051948041086352,
+        0.7071067811865475, 0.7090239421602076, 0.7109463010845827, 0.7128738720527471,
+        0.7148066691959849, 0.7167447066838943, 0.718687998724491, 0.7206365595643126,
+        0.7225904034885232, 0.7245495448210174, 0.7265139979245261, 0.7284837772007218,
+        0.7304588970903234, 0.7324393720732029, 0.7344252166684908, 0.7364164454346837,
+        0.7384130729697496, 0.7404151139112358, 0.7424225829363761, 0.7444354947621984,
+        0.7464538641456323, 0.7484777058836176, 0.7505070348132126, 0.7525418658117031,
+        0.7545822137967112, 0.7566280937263048, 0.7586795205991071, 0.7607365094544071,
+        0.762799075372269, 0.7648672334736434, 0.7669409989204777, 0.7690203869158282,
+        0.7711054127039704, 0.7731960915705107, 0.7752924388424999, 0.7773944698885442,
+        0.7795022001189185, 0.7816156449856788, 0.7837348199827764, 0.7858597406461707,
+        0.7879904225539431, 0.7901268813264122, 0.7922691326262467, 0.7944171921585818,
+        0.7965710756711334, 0.7987307989543135, 0.8008963778413465, 0.8030678282083853,
+        0.805245165974627, 0.8074284071024302, 0.8096175675974316, 0.8118126635086642,
+        0.8140137109286738, 0.8162207259936375, 0.8184337248834821, 0.820652723822003,
+        0.8228777390769823, 0.8251087869603088, 0.8273458838280969, 0.8295890460808079,
+        0.8318382901633681, 0.8340936325652911, 0.8363550898207981, 0.8386226785089391,
+        0.8408964152537144, 0.8431763167241966, 0.8454623996346523, 0.8477546807446661,
+        0.8500531768592616, 0.8523579048290255, 0.8546688815502312, 0.8569861239649629,
+        0.8593096490612387, 0.8616394738731368, 0.8639756154809185, 0.8663180910111553,
+        0.8686669176368529, 0.871022112577578, 0.8733836930995842, 0.8757516765159389,
+        0.8781260801866495, 0.8805069215187917, 0.8828942179666361, 0.8852879870317771,
+        0.8876882462632604, 0.890095013257712, 0.8925083056594671, 0.8949281411607002,
+        0.8973545375015533, 0.8997875124702672, 0.9022270839033115, 0.9046732696855155,
+        0.9071260877501991, 0.909585556079304, 0.9120516927035263, 0.9145245157024483,
+        0.9170040432046711, 0.9194902933879467, 0.9219832844793128, 0.9244830347552253,
+        0.9269895625416926, 0.92950288621441, 0.9320230241988943, 0.9345499949706191,
+        0.9370838170551498, 0.93962450902828, 0.9421720895161669, 0.9447265771954693,
+        0.9472879907934827, 0.9498563490882775, 0.9524316709088368, 0.9550139751351947,
+        0.9576032806985735, 0.9601996065815236, 0.9628029718180622, 0.9654133954938133,
+        0.9680308967461471, 0.9706554947643201, 0.9732872087896164, 0.9759260581154889,
+        0.9785720620876999, 0.9812252401044634, 0.9838856116165875, 0.9865531961276168,
+        0.9892280131939752, 0.9919100824251095, 0.9945994234836328, 0.9972960560854698,
+    ],
+];
+
+/// Returns the upper bound of the bucket at the given index.
+///
+/// The last bucket before the overflow bucket returns `f64::MAX`,
+/// like Prometheus.
+pub fn bucket_bound(index: i32, schema: i32) -> f64 {
+    if schema < 0 {
+        let exponent = (index as i64) << -schema;
+        if exponent == 1024 {
+            return f64::MAX;
+        }
+        return ldexp(1.0, exponent.clamp(i32::MIN as i64, i32::MAX as i64) as i32);
+    }
+
+    let num_fractions = 1i64 << schema;
+    let fraction_index = (index as i64) & (num_fractions - 1);
+    let fraction = match EXPONENTIAL_BOUNDS.get(schema as usize) {
+        Some(fractions) => fractions[fraction_index as usize],
+        // Prometheus doesn't support these schemas.
+        None => (fraction_index as f64 / num_fractions as f64).exp2() / 2.0,
+    };
+    let exponent = ((index as i64) >> schema) + 1;
+    if fraction_index == 0 && exponent == 1025 {
+        return f64::MAX;
+    }
+    ldexp(fraction, exponent as i32)
+}
+
+/// Returns the upper bound of the bucket at the given index,
+/// for any schema including custom buckets.
+///
+/// With custom buckets, the index -1 is the lower bound of the first bucket,
+/// and the index after the last custom value is the +Inf bucket.
+fn bound(index: i32, schema: i32, custom_values: &[f64]) -> f64 {
+    if !is_custom_buckets_schema(schema) {
+        return bucket_bound(index, schema);
+    }
+    if index < 0 {
+        return f64::NEG_INFINITY;
+    }
+    custom_values
+        .get(index as usize)
+        .copied()
+        .unwrap_or(f64::INFINITY)
+}
+
+/// Iterates over the buckets described by spans.
+///
+/// The counts are either absolute, or deltas to the previous bucket.
+struct BucketIterator<'a, C> {
+    schema: i32,
+    custom_values: &'a [f64],
+    positive: bool,
+    deltas: bool,
+    spans: &'a [Span],
+    buckets: &'a [C],
+    span_index: usize,
+    index_in_span: u32,
+    bucket_index: usize,
+    current_index: i32,
+    current_count: C,
+}
+
+impl<'a, C: Copy + Default + std::ops::Add<Output = C>> BucketIterator<'a, C> {
+    fn new(
+        schema: i32,
+        custom_values: &'a [f64],
+        positive: bool,
+        deltas: bool,
+        spans: &'a [Span],
+        buckets: &'a [C],
+    ) -> Self {
+        Self {
+            schema,
+            custom_values,
+            positive,
+            deltas,
+            spans,
+            buckets,
+            span_index: 0,
+            index_in_span: 0,
+            bucket_index: 0,
+            current_index: 0,
+            current_count: C::default(),
+        }
+    }
+}
+
+impl<C: Copy + Default + std::ops::Add<Output = C>> Iterator for BucketIterator<'_, C> {
+    type Item = Bucket<C>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut span = self.spans.get(self.span_index)?;
+
+        if self.bucket_index == 0 {
+            self.current_index = span.offset;
+        } else {
+            self.current_index += 1;
+        }
+
+        // Move to the next span once the current one is exhausted,
+        // it also skips the spans of length 0.
+        while self.index_in_span >= span.length {
+            self.index_in_span = 0;
+            self.span_index += 1;
+            span = self.spans.get(self.span_index)?;
+            self.current_index += span.offset;
+        }
+
+        let count = *self.buckets.get(self.bucket_index)?;
+        self.current_count = if self.deltas {
+            self.current_count + count
+        } else {
+            count
+        };
+        self.index_in_span += 1;
+        self.bucket_index += 1;
+
+        let lower = bound(self.current_index - 1, self.schema, self.custom_values);
+        let upper = bound(self.current_index, self.schema, self.custom_values);
+        Some(if is_custom_buckets_schema(self.schema) {
+            // Custom buckets are like classic buckets, only the first one
+            // includes its lower bound, -Inf.
+            Bucket {
+                lower,
+                upper,
+                lower_inclusive: self.current_index == 0,
+                upper_inclusive: true,
+                count: self.current_count,
+                index: self.current_index,
+            }
+        } else if self.positive {
+            Bucket {
+                lower,
+                upper,
+                lower_inclusive: false,
+                upper_inclusive: true,
+                count: self.current_count,
+                index: self.current_index,
+            }
+        } else {
+            Bucket {
+                lower: -upper,
+                upper: -lower,
+                lower_inclusive: true,
+                upper_inclusive: false,
+                count: self.current_count,
+                index: self.current_index,
+            }
+        })
+    }
+}
+
+fn check_spans(spans: &[Span], num_buckets: usize, side: &str) -> Result<(), RustyChunkEncError> {
+    let mut span_buckets: usize = 0;
+    for (n, span) in spans.iter().enumerate() {
+        if n > 0 && span.offset < 0 {
+            return Err(RustyChunkEncError::InvalidHistogram(format!(
+                "{} side: span number {} with offset {} is negative",
+                side,
+                n + 1,
+                span.offset
+            )));
+        }
+        span_buckets += span.length as usize;
+    }
+    if span_buckets != num_buckets {
+        return Err(RustyChunkEncError::InvalidHistogram(format!(
+            "{} side: spans need {} buckets, have {} buckets",
+            side, span_buckets, num_buckets
+        )));
+    }
+    Ok(())
+}
+
+fn check_schema(schema: i32) -> Result<(), RustyChunkEncError> {
+    if !(-4..=8).contains(&schema) && !is_custom_buckets_schema(schema) {
+        return Err(RustyChunkEncError::InvalidHistogram(format!(
+            "schema {} is not between -4 and 8, nor the custom buckets schema",
+            schema
+        )));
+    }
+    Ok(())
+}
+
+fn custom_buckets_error(message: String) -> RustyChunkEncError {
+    RustyChunkEncError::InvalidHistogram(format!("custom buckets: {}", message))
+}
+
+/// Checks the parts of a histogram that depend on the schema.
+///
+/// Custom buckets need increasing finite bounds covering all the positive buckets,
+/// and nothing on the negative side nor in the zero bucket.
+/// Exponential buckets must not have custom bounds.
+#[allow(clippy::too_many_arguments)]
+fn check_layout(
+    schema: i32,
+    custom_values: &[f64],
+    zero_threshold: f64,
+    has_zero_count: bool,
+    positive_spans: &[Span],
+    num_positive_buckets: usize,
+    negative_spans: &[Span],
+    num_negative_buckets: usize,
+) -> Result<(), RustyChunkEncError> {
+    check_schema(schema)?;
+
+    if !is_custom_buckets_schema(schema) {
+        if !custom_values.is_empty() {
+            return Err(RustyChunkEncError::InvalidHistogram(
+                "histogram with exponential schema must not have custom bounds".to_string(),
+            ));
+        }
+        check_spans(negative_spans, num_negative_buckets, "negative")?;
+        return check_spans(positive_spans, num_positive_buckets, "positive");
+    }
+
+    let mut previous = f64::NEG_INFINITY;
+    for value in custom_values {
+        // Also rejects NaN bounds.
+        if value.partial_cmp(&previous) != Some(std::cmp::Ordering::Greater) {
+            return Err(custom_buckets_error(format!(
+                "previous bound is {} and current is {}",
+                previous, value
+            )));
+        }
+        previous = *value;
+    }
+    if previous == f64::INFINITY {
+        return Err(custom_buckets_error(
+            "last +Inf bound must not be explicitly defined".to_string(),
+        ));
+    }
+
+    let mut span_buckets: usize = 0;
+    let mut total_span_length: i64 = 0;
+    for (n, span) in positive_spans.iter().enumerate() {
+        if span.offset < 0 {
+            return Err(custom_buckets_error(format!(
+                "span number {} with offset {} is negative",
+                n + 1,
+                span.offset
+            )));
+        }
+        span_buckets += span.length as usize;
+        total_span_length += span.length as i64 + span.offset as i64;
+    }
+    if span_buckets != num_positive_buckets {
+        return Err(custom_buckets_error(format!(
+            "spans need {} buckets, have {} buckets",
+            span_buckets, num_positive_buckets
+        )));
+    }
+    if (custom_values.len() as i64 + 1) < total_span_length {
+        return Err(custom_buckets_error(format!(
+            "only {} custom bounds defined which is insufficient to cover total span length of {}",
+            custom_values.len(),
+            total_span_length
+        )));
+    }
+
+    if has_zero_count {
+        return Err(custom_buckets_error(
+            "must have zero count of 0".to_string(),
+        ));
+    }
+    if zero_threshold != 0.0 {
+        return Err(custom_buckets_error(
+            "must have zero threshold of 0".to_string(),
+        ));
+    }
+    if !negative_spans.is_empty() {
+        return Err(custom_buckets_error(
+            "must not have negative spans".to_string(),
+        ));
+    }
+    if num_negative_buckets > 0 {
+        return Err(custom_buckets_error(
+            "must not have negative buckets".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn negative_count_error(side: &str, n: usize, count: impl std::fmt::Display) -> RustyChunkEncError {
+    RustyChunkEncError::InvalidHistogram(format!(
+        "{} side: bucket number {} has observation count of {}",
+        side,
+        n + 1,
+        count
+    ))
+}
+
+/// Sums the delta-encoded buckets, and rejects negative counts.
+fn check_delta_buckets(buckets: &[i64], side: &str) -> Result<u64, RustyChunkEncError> {
+    let mut count: u64 = 0;
+    let mut current: i64 = 0;
+    for (n, delta) in buckets.iter().enumerate() {
+        current = current.wrapping_add(*delta);
+        if current < 0 {
+            return Err(negative_count_error(side, n, current));
+        }
+        count = count.wrapping_add(current as u64);
+    }
+    Ok(count)
+}
+
+fn check_float_buckets(buckets: &[f64], side: &str) -> Result<(), RustyChunkEncError> {
+    for (n, count) in buckets.iter().enumerate() {
+        if *count < 0.0 {
+            return Err(negative_count_error(side, n, count));
+        }
+    }
+    Ok(())
+}
+
+/// Converts delta-encoded buckets to absolute counts.
+fn absolute_buckets(buckets: &[i64]) -> Vec<f64> {
+    let mut current: i64 = 0;
+    buckets
+        .iter()
+        .map(|delta| {
+            current = current.wrapping_add(*delta);
+            current as f64
+        })
+        .collect()
+}
+
+/// Converts absolute float counts to delta-encoded integer buckets.
+fn delta_buckets(buckets: &[f64], side: &str) -> Result<Vec<i64>, RustyChunkEncError> {
+    let mut previous: i64 = 0;
+    buckets
+        .iter()
+        .enumerate()
+        .map(|(n, count)| {
+            let current = float_to_count(*count).ok_or_else(|| {
+                RustyChunkEncError::InvalidHistogram(format!(
+                    "{} side: bucket number {} has a non integer count of {}",
+                    side,
+                    n + 1,
+                    count
+                ))
+            })? as i64;
+            let delta = current - previous;
+            previous = current;
+            Ok(delta)
+        })
+        .collect()
+}
+
+/// Returns the float as a count, if it's a positive integer.
+fn float_to_count(value: f64) -> Option<u64> {
+    // 2^63, so the count also fits in an i64.
+    if (0.0..9223372036854775808.0).contains(&value) && value.fract() == 0.0 {
+        Some(value as u64)
+    } else {
+        None
+    }
+}
+
+/// Returns the indices of the buckets described by the spans, in order.
+fn span_indices(spans: &[Span]) -> impl Iterator<Item = i32> + '_ {
+    spans
+        .iter()
+        .scan(0i32, |next_index, span| {
+            let start = *next_index + span.offset;
+            *next_index = start + span.length as i32;
+            Some(start..*next_index)
+        })
+        .flatten()
+}
+
+/// Builds compact spans from increasing bucket indices.
+fn spans_from_indices(indices: impl Iterator<Item = i32>) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    let mut next_index = 0;
+    for index in indices {
+        match spans.last_mut() {
+            Some(span) if index == next_index => span.length += 1,
+            _ => spans.push(Span {
+                offset: index - next_index,
+                length: 1,
+            }),
+        }
+        next_index = index + 1;
+    }
+    spans
+}
+
+/// Returns compact spans covering the buckets of both spans.
+pub(crate) fn merge_spans(a: &[Span], b: &[Span]) -> Vec<Span> {
+    let mut a = span_indices(a).peekable();
+    let mut b = span_indices(b).peekable();
+    let merged = std::iter::from_fn(|| match (a.peek().copied(), b.peek().copied()) {
+        (Some(index_a), Some(index_b)) => {
+            if index_a <= index_b {
+                a.next();
+            }
+            if index_b <= index_a {
+                b.next();
+            }
+            Some(index_a.min(index_b))
+        }
+        (Some(_), None) => a.next(),
+        (None, _) => b.next(),
+    });
+    spans_from_indices(merged)
+}
+
+/// Places the counts of the buckets into new spans, with empty buckets
+/// where the previous spans had none.
+///
+/// The new spans must cover all the non-empty buckets.
+fn expand_buckets<C: Copy + Default + PartialEq>(
+    buckets: impl Iterator<Item = Bucket<C>>,
+    spans: &[Span],
+) -> Vec<C> {
+    let mut buckets = buckets
+        .filter(|bucket| bucket.count != C::default())
+        .peekable();
+    let expanded = span_indices(spans)
+        .map(|index| match buckets.peek() {
+            Some(bucket) if bucket.index == index => {
+                let count = bucket.count;
+                buckets.next();
+                count
+            }
+            _ => C::default(),
+        })
+        .collect();
+    debug_assert!(buckets.next().is_none(), "the spans miss non-empty buckets");
+    expanded
+}
+
+/// Pairs the bucket indices with their counts.
+fn indexed_buckets<'a, C: Copy>(
+    spans: &'a [Span],
+    buckets: &'a [C],
+) -> impl Iterator<Item = (i32, C)> + 'a {
+    span_indices(spans).zip(buckets.iter().copied())
+}
+
+/// Builds compact spans and buckets from increasing bucket indices and their counts.
+fn spans_and_buckets(buckets: Vec<(i32, f64)>) -> (Vec<Span>, Vec<f64>) {
+    let spans = spans_from_indices(buckets.iter().map(|(index, _)| *index));
+    let counts = buckets.into_iter().map(|(_, count)| count).collect();
+    (spans, counts)
+}
+
+/// Returns the index of the bucket containing the given bucket, with a lower schema.
+///
+/// The bucket upper bounds are powers of 2^(2^-schema), so every 2^(origin - target)
+/// buckets of the origin schema merge into one bucket of the target schema.
+fn target_index(index: i32, origin_schema: i32, target_schema: i32) -> i32 {
+    ((index - 1) >> (origin_schema - target_schema)) + 1
+}
+
+/// Merges the buckets into the buckets of a lower schema.
+fn reduce_resolution(
+    spans: &[Span],
+    buckets: &[f64],
+    origin_schema: i32,
+    target_schema: i32,
+) -> (Vec<Span>, Vec<f64>) {
+    let mut reduced: Vec<(i32, f64)> = Vec::new();
+    for (index, count) in indexed_buckets(spans, buckets) {
+        let index = target_index(index, origin_schema, target_schema);
+        match reduced.last_mut() {
+            Some((last_index, last_count)) if *last_index == index => *last_count += count,
+            _ => reduced.push((index, count)),
+        }
+    }
+    spans_and_buckets(reduced)
+}
+
+/// Adds or subtracts buckets with the same schema, over the union of their spans.
+fn add_buckets(
+    spans: &[Span],
+    buckets: &[f64],
+    other_spans: &[Span],
+    other_buckets: &[f64],
+    subtract: bool,
+) -> (Vec<Span>, Vec<f64>) {
+    let sign = if subtract { -1.0 } else { 1.0 };
+    let mut a = indexed_buckets(spans, buckets).peekable();
+    let mut b = indexed_buckets(other_spans, other_buckets).peekable();
+    let mut sum = Vec::with_capacity(buckets.len().max(other_buckets.len()));
+    loop {
+        let bucket = match (a.peek().copied(), b.peek().copied()) {
+            (Some((index_a, count_a)), Some((index_b, count_b))) => {
+                if index_a < index_b {
+                    a.next();
+                    (index_a, count_a)
+                } else if index_b < index_a {
+                    b.next();
+                    (index_b, sign * count_b)
+                } else {
+                    a.next();
+                    b.next();
+                    (index_a, count_a + sign * count_b)
+                }
+            }
+            (Some(bucket), None) => {
+                a.next();
+                bucket
+            }
+            (None, Some((index_b, count_b))) => {
+                b.next();
+                (index_b, sign * count_b)
+            }
+            (None, None) => break,
+        };
+        sum.push(bucket);
+    }
+    spans_and_buckets(sum)
+}
+
+/// Compares custom bounds bit by bit.
+pub(crate) fn same_custom_values(a: &[f64], b: &[f64]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.to_bits() == b.to_bits())
+}
+
+/// Returns whether any bucket count decreased between two histograms,
+/// or disappeared from the spans.
+///
+/// Like Prometheus' `counterResetInAnyBucket`, the buckets are matched
+/// by their index, so new buckets aren't counter resets.
+fn counter_reset_in_any_bucket<T: PartialOrd>(
+    previous_buckets: impl Iterator<Item = Bucket<T>>,
+    buckets: impl Iterator<Item = Bucket<T>>,
+) -> bool {
+    let mut buckets = buckets.peekable();
+    for previous_bucket in previous_buckets {
+        // Skip the new buckets.
+        while buckets
+            .peek()
+            .is_some_and(|bucket| bucket.index < previous_bucket.index)
+        {
+            buckets.next();
+        }
+        match buckets.next() {
+            Some(bucket) if bucket.index == previous_bucket.index => {
+                if bucket.count < previous_bucket.count {
+                    return true;
+                }
+            }
+            // The bucket is missing.
+            _ => return true,
+        }
+    }
+    false
+}
+
+impl Histogram {
+    /// Iterates over the positive buckets, with absolute counts.
+    pub fn positive_bucket_iterator(&self) -> impl Iterator<Item = Bucket<u64>> + '_ {
+        BucketIterator::new(
+            self.schema,
+            &self.custom_values,
+            true,
+            true,
+            &self.positive_spans,
+            &self.positive_buckets,
+        )
+        .map(to_unsigned_bucket)
+    }
+
+    /// Iterates over the negative buckets, with absolute counts.
+    ///
+    /// The buckets are ordered by increasing absolute values.
+    pub fn negative_bucket_iterator(&self) -> impl Iterator<Item = Bucket<u64>> + '_ {
+        BucketIterator::new(
+            self.schema,
+            &self.custom_values,
+            false,
+            true,
+            &self.negative_spans,
+            &self.negative_buckets,
+        )
+        .map(to_unsigned_bucket)
+    }
+
+    /// Checks that the spans match the buckets, that no bucket is negative,
+    /// that the custom bounds are consistent with the schema,
+    /// and that the count matches the buckets.
+    ///
+    /// Like Prometheus, the count can be larger than the buckets when the sum is NaN.
+    pub fn validate(&self) -> Result<(), RustyChunkEncError> {
+        check_layout(
+            self.schema,
+            &self.custom_values,
+            self.zero_threshold,
+            self.zero_count != 0,
+            &self.positive_spans,
+            self.positive_buckets.len(),
+            &self.negative_spans,
+            self.negative_buckets.len(),
+        )?;
+        let negative_count = check_delta_buckets(&self.negative_buckets, "negative")?;
+        let positive_count = check_delta_buckets(&self.positive_buckets, "positive")?;
+
+        let sum_of_buckets = negative_count
+            .wrapping_add(positive_count)
+            .wrapping_add(self.zero_count);
+        if self.sum.is_nan() {
+            if sum_of_buckets > self.count {
+                return Err(RustyChunkEncError::InvalidHistogram(format!(
+                    "{} observations found in buckets, but the count is {}",
+                    sum_of_buckets, self.count
+                )));
+            }
+        } else if sum_of_buckets != self.count {
+            return Err(RustyChunkEncError::InvalidHistogram(format!(
+                "{} observations found in buckets, but the count is {}",
+                sum_of_buckets, self.count
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns whether the buckets can be compared with the previous histogram
+    /// to detect counter resets.
+    ///
+    /// They can't when either is a stale marker, or when the schema,
+    /// the zero threshold, or the custom bounds changed.
+    pub fn has_comparable_layout(&self, previous: &Histogram) -> bool {
+        !is_stale_nan(self.sum)
+            && !is_stale_nan(previous.sum)
+            && self.schema == previous.schema
+            && self.zero_threshold.to_bits() == previous.zero_threshold.to_bits()
+            && same_custom_values(&self.custom_values, &previous.custom_values)
+    }
+
+    /// Returns whether the histogram is a counter reset compared to the previous one.
+    ///
+    /// Like Prometheus, the total count, the zero count, or any bucket going down
+    /// is a reset, and so is a bucket disappearing. A schema or zero threshold
+    /// change isn't, as the buckets can't be compared, nor are stale markers.
+    pub fn detect_counter_reset(&self, previous: &Histogram) -> bool {
+        if is_stale_nan(self.sum) || is_stale_nan(previous.sum) {
+            return false;
+        }
+        if self.count < previous.count {
+            return true;
+        }
+        if self.schema != previous.schema
+            || self.zero_threshold.to_bits() != previous.zero_threshold.to_bits()
+        {
+            return false;
+        }
+        if is_custom_buckets_schema(self.schema)
+            && !same_custom_values(&self.custom_values, &previous.custom_values)
+        {
+            return true;
+        }
+        self.zero_count < previous.zero_count
+            || counter_reset_in_any_bucket(
+                previous.positive_bucket_iterator(),
+                self.positive_bucket_iterator(),
+            )
+            || counter_reset_in_any_bucket(
+                previous.negative_bucket_iterator(),
+                self.negative_bucket_iterator(),
+            )
+    }
+
+    /// Returns the histogram with its buckets moved to the given spans.
+    ///
+    /// This is how Prometheus recodes a chunk when new buckets appear.
+    /// The new spans must cover all the non-empty buckets of the histogram.
+    pub(crate) fn expand_spans(&self, positive_spans: &[Span], negative_spans: &[Span]) -> Self {
+        let to_deltas = |counts: Vec<u64>| {
+            let mut previous = 0i64;
+            counts
+                .into_iter()
+                .map(|count| {
+                    let delta = (count as i64).wrapping_sub(previous);
+                    previous = count as i64;
+                    delta
+                })
+                .collect()
+        };
+        Histogram {
+            positive_spans: positive_spans.to_vec(),
+            negative_spans: negative_spans.to_vec(),
+            positive_buckets: to_deltas(expand_buckets(
+                self.positive_bucket_iterator(),
+                positive_spans,
+            )),
+            negative_buckets: to_deltas(expand_buckets(
+                self.negative_bucket_iterator(),
+                negative_spans,
+            )),
+            ..self.clone()
+        }
+    }
+
+    /// Converts the histogram to a float histogram, with absolute buckets.
+    pub fn to_float(&self) -> FloatHistogram {
+        FloatHistogram {
+            schema: self.schema,
+            zero_threshold: self.zero_threshold,
+            zero_count: self.zero_count as f64,
+            count: self.count as f64,
+            sum: self.sum,
+            positive_spans: self.positive_spans.clone(),
+            negative_spans: self.negative_spans.clone(),
+            positive_buckets: absolute_buckets(&self.positive_buckets),
+            negative_buckets: absolute_buckets(&self.negative_buckets),
+            custom_values: self.custom_values.clone(),
+        }
+    }
+}
+
+fn to_unsigned_bucket(bucket: Bucket<i64>) -> Bucket<u64> {
+    Bucket {
+        lower: bucket.lower,
+        upper: bucket.upper,
+        lower_inclusive: bucket.lower_inclusive,
+        upper_inclusive: bucket.upper_inclusive,
+        count: bucket.count as u64,
+        index: bucket.index,
+    }
+}
+
+impl FloatHistogram {
+    /// Iterates over the positive buckets.
+    pub fn positive_bucket_iterator(&self) -> impl Iterator<Item = Bucket<f64>> + '_ {
+        BucketIterator::new(
+            self.schema,
+            &self.custom_values,
+            true,
+            false,
+            &self.positive_spans,
+            &self.positive_buckets,
+        )
+    }
+
+    /// Iterates over the negative buckets.
+    ///
+    /// The buckets are ordered by increasing absolute values.
+    pub fn negative_bucket_iterator(&self) -> impl Iterator<Item = Bucket<f64>> + '_ {
+        BucketIterator::new(
+            self.schema,
+            &self.custom_values,
+            false,
+            false,
+            &self.negative_spans,
+            &self.negative_buckets,
+        )
+    }
+
+    /// Returns the zero bucket, from minus to plus the zero threshold.
+    pub fn zero_bucket(&self) -> Bucket<f64> {
+        Bucket {
+            lower: -self.zero_threshold,
+            upper: self.zero_threshold,
+            lower_inclusive: true,
+            upper_inclusive: true,
+            count: self.zero_count,
+            index: i32::MIN,
+        }
+    }
+
+    /// Iterates over all the buckets, from the lowest to the highest values.
+    ///
+    /// Like in Prometheus, the negative buckets come first, then the zero bucket
+    /// if it isn't empty, and then the positive buckets. The buckets overlapping
+    /// the zero bucket are trimmed to the zero threshold. It can iterate backwards.
+    pub fn all_bucket_iterator(&self) -> impl DoubleEndedIterator<Item = Bucket<f64>> {
+        let mut buckets = self
+            .negative_bucket_iterator()
+            .map(|mut bucket| {
+                bucket.upper = bucket.upper.min(-self.zero_threshold);
+                bucket
+            })
+            .collect::<Vec<_>>();
+        buckets.reverse();
+        if self.zero_count > 0.0 {
+            buckets.push(self.zero_bucket());
+        }
+        let custom_buckets = is_custom_buckets_schema(self.schema);
+        buckets.extend(self.positive_bucket_iterator().map(|mut bucket| {
+            // The first custom bucket starts at -Inf.
+            if !custom_buckets {
+                bucket.lower = bucket.lower.max(self.zero_threshold);
+            }
+            bucket
+        }));
+        buckets.into_iter()
+    }
+
+    /// Checks that the spans match the buckets, that no bucket is negative,
+    /// and that the custom bounds are consistent with the schema.
+    ///
+    /// Like Prometheus, the count isn't compared to the buckets,
+    /// as the floating point precision can make them differ.
+    pub fn validate(&self) -> Result<(), RustyChunkEncError> {
+        check_layout(
+            self.schema,
+            &self.custom_values,
+            self.zero_threshold,
+            self.zero_count != 0.0,
+            &self.positive_spans,
+            self.positive_buckets.len(),
+            &self.negative_spans,
+            self.negative_buckets.len(),
+        )?;
+        check_float_buckets(&self.negative_buckets, "negative")?;
+        check_float_buckets(&self.positive_buckets, "positive")
+    }
+
+    /// Returns whether the buckets can be compared with the previous histogram
+    /// to detect counter resets.
+    ///
+    /// See [`Histogram::has_comparable_layout`].
+    pub fn has_comparable_layout(&self, previous: &FloatHistogram) -> bool {
+        !is_stale_nan(self.sum)
+            && !is_stale_nan(previous.sum)
+            && self.schema == previous.schema
+            && self.zero_threshold.to_bits() == previous.zero_threshold.to_bits()
+            && same_custom_values(&self.custom_values, &previous.custom_values)
+    }
+
+    /// Returns whether the histogram is a counter reset compared to the previous one.
+    ///
+    /// See [`Histogram::detect_counter_reset`].
+    pub fn detect_counter_reset(&self, previous: &FloatHistogram) -> bool {
+        if is_stale_nan(self.sum) || is_stale_nan(previous.sum) {
+            return false;
+        }
+        if self.count < previous.count {
+            return true;
+        }
+        if self.schema != previous.schema
+            || self.zero_threshold.to_bits() != previous.zero_threshold.to_bits()
+        {
+            return false;
+        }
+        if is_custom_buckets_schema(self.schema)
+            && !same_custom_values(&self.custom_values, &previous.custom_values)
+        {
+            return true;
+        }
+        self.zero_count < previous.zero_count
+            || counter_reset_in_any_bucket(
+                previous.positive_bucket_iterator(),
+                self.positive_bucket_iterator(),
+            )
+            || counter_reset_in_any_bucket(
+                previous.negative_bucket_iterator(),
+                self.negative_bucket_iterator(),
+            )
+    }
+
+    /// Returns the histogram with its buckets moved to the given spans.
+    ///
+    /// See [`Histogram::expand_spans`].
+    pub(crate) fn expand_spans(&self, positive_spans: &[Span], negative_spans: &[Span]) -> Self {
+        FloatHistogram {
+            positive_spans: positive_spans.to_vec(),
+            negative_spans: negative_spans.to_vec(),
+            positive_buckets: expand_buckets(self.positive_bucket_iterator(), positive_spans),
+            negative_buckets: expand_buckets(self.negative_bucket_iterator(), negative_spans),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the histogram with a lower resolution.
+    ///
+    /// The buckets are merged into the buckets of the target schema,
+    /// which must not be higher than the histogram's schema.
+    pub fn reduce_resolution(&self, target_schema: i32) -> Result<Self, RustyChunkEncError> {
+        if is_custom_buckets_schema(self.schema) || is_custom_buckets_schema(target_schema) {
+            return Err(RustyChunkEncError::InvalidHistogram(
+                "cannot reduce the resolution of custom buckets".to_string(),
+            ));
+        }
+        if target_schema > self.schema {
+            return Err(RustyChunkEncError::InvalidHistogram(format!(
+                "cannot increase the resolution from schema {} to schema {}",
+                self.schema, target_schema
+            )));
+        }
+        let (positive_spans, positive_buckets) = reduce_resolution(
+            &self.positive_spans,
+            &self.positive_buckets,
+            self.schema,
+            target_schema,
+        );
+        let (negative_spans, negative_buckets) = reduce_resolution(
+            &self.negative_spans,
+            &self.negative_buckets,
+            self.schema,
+            target_schema,
+        );
+        Ok(FloatHistogram {
+            schema: target_schema,
+            positive_spans,
+            negative_spans,
+            positive_buckets,
+            negative_buckets,
+            ..self.clone()
+        })
+    }
+
+    /// Returns the zero count and zero threshold the histogram would have
+    /// with a larger zero threshold.
+    ///
+    /// Like Prometheus, when the threshold falls within a populated bucket,
+    /// it's raised to the upper bound of the bucket.
+    fn zero_count_for_larger_threshold(&self, larger_threshold: f64) -> (f64, f64) {
+        let mut threshold = larger_threshold;
+        'outer: loop {
+            let mut count = self.zero_count;
+            for bucket in self.positive_bucket_iterator() {
+                if bucket.lower >= threshold {
+                    break;
+                }
+                count += bucket.count;
+                if bucket.upper > threshold {
+                    if bucket.count != 0.0 {
+                        threshold = bucket.upper;
+                    }
+                    break;
+                }
+            }
+            for bucket in self.negative_bucket_iterator() {
+                if bucket.upper <= -threshold {
+                    break;
+                }
+                count += bucket.count;
+                if bucket.lower < -threshold {
+                    if bucket.count != 0.0 {
+                        threshold = -bucket.lower;
+                        continue 'outer;
+                    }
+                    break;
+                }
+            }
+            return (count, threshold);
+        }
+    }
+
+    /// Removes the buckets that are now within the zero bucket, and the empty buckets.
+    fn trim_buckets_in_zero_bucket(&mut self) {
+        let zero_threshold = self.zero_threshold;
+        let positive = self
+            .positive_bucket_iterator()
+            .filter(|bucket| bucket.lower >= zero_threshold && bucket.count != 0.0)
+            .map(|bucket| (bucket.index, bucket.count))
+            .collect();
+        let negative = self
+            .negative_bucket_iterator()
+            .filter(|bucket| bucket.upper <= -zero_threshold && bucket.count != 0.0)
+            .map(|bucket| (bucket.index, bucket.count))
+            .collect();
+        (self.positive_spans, self.positive_buckets) = spans_and_buckets(positive);
+        (self.negative_spans, self.negative_buckets) = spans_and_buckets(negative);
+    }
+
+    /// Gives both histograms the largest zero threshold,
+    /// and returns the zero count of the other histogram with it.
+    fn reconcile_zero_buckets(&mut self, other: &FloatHistogram) -> f64 {
+        let mut other_zero_count = other.zero_count;
+        let mut other_zero_threshold = other.zero_threshold;
+        while other_zero_threshold != self.zero_threshold {
+            if self.zero_threshold > other_zero_threshold {
+                (other_zero_count, other_zero_threshold) =
+                    other.zero_count_for_larger_threshold(self.zero_threshold);
+            }
+            if other_zero_threshold > self.zero_threshold {
+                (self.zero_count, self.zero_threshold) =
+                    self.zero_count_for_larger_threshold(other_zero_threshold);
+                self.trim_buckets_in_zero_bucket();
+            }
+        }
+        other_zero_count
+    }
+
+    fn add_or_subtract(
+        &self,
+        other: &FloatHistogram,
+        subtract: bool,
+    ) -> Result<FloatHistogram, RustyChunkEncError> {
+        let custom_buckets = is_custom_buckets_schema(self.schema);
+        if custom_buckets != is_custom_buckets_schema(other.schema) {
+            return Err(RustyChunkEncError::InvalidHistogram(
+                "cannot combine custom buckets with exponential buckets".to_string(),
+            ));
+        }
+        if custom_buckets && !same_custom_values(&self.custom_values, &other.custom_values) {
+            return Err(RustyChunkEncError::InvalidHistogram(
+                "cannot combine histograms with different custom bounds".to_string(),
+            ));
+        }
+
+        // The zero buckets are reconciled at the original resolutions,
+        // then the higher resolution side is reduced to the lower one.
+        let mut histogram = self.clone();
+        let mut other = other.clone();
+        if !custom_buckets {
+            other.zero_count = histogram.reconcile_zero_buckets(&other);
+            other.zero_threshold = histogram.zero_threshold;
+            // The other histogram's buckets within the reconciled zero bucket
+            // are already in its zero count.
+            other.trim_buckets_in_zero_bucket();
+        }
+        let schema = histogram.schema.min(other.schema);
+        let mut histogram = histogram.reduce_resolution_if_needed(schema)?;
+        let other = other.reduce_resolution_if_needed(schema)?;
+
+        let sign = if subtract { -1.0 } else { 1.0 };
+        histogram.zero_count += sign * other.zero_count;
+        histogram.count += sign * other.count;
+        histogram.sum += sign * other.sum;
+
+        (histogram.positive_spans, histogram.positive_buckets) = add_buckets(
+            &histogram.positive_spans,
+            &histogram.positive_buckets,
+            &other.positive_spans,
+            &other.positive_buckets,
+            subtract,
+        );
+        (histogram.negative_spans, histogram.negative_buckets) = add_buckets(
+            &histogram.negative_spans,
+            &histogram.negative_buckets,
+            &other.negative_spans,
+            &other.negative_buckets,
+            subtract,
+        );
+        Ok(histogram)
+    }
+
+    fn reduce_resolution_if_needed(&self, schema: i32) -> Result<Self, RustyChunkEncError> {
+        if self.schema == schema {
+            Ok(self.clone())
+        } else {
+            self.reduce_resolution(schema)
+        }
+    }
+
+    /// Adds two histograms, like Prometheus' `FloatHistogram.Add`.
+    ///
+    /// When the schemas differ, the histogram with the higher resolution
+    /// is reduced to the lower one. When the zero thresholds differ,
+    /// the largest one is used. Histograms with custom buckets can only
+    /// be added to histograms with the same custom bounds.
+    pub fn add(&self, other: &FloatHistogram) -> Result<FloatHistogram, RustyChunkEncError> {
+        self.add_or_subtract(other, false)
+    }
+
+    /// Subtracts the other histogram, like Prometheus' `FloatHistogram.Sub`.
+    ///
+    /// See [`FloatHistogram::add`]. The buckets can become negative.
+    pub fn sub(&self, other: &FloatHistogram) -> Result<FloatHistogram, RustyChunkEncError> {
+        self.add_or_subtract(other, true)
+    }
+
+    /// Multiplies the counts and the sum by a factor, like Prometheus' `FloatHistogram.Mul`.
+    pub fn mul(&self, factor: f64) -> FloatHistogram {
+        FloatHistogram {
+            zero_count: self.zero_count * factor,
+            count: self.count * factor,
+            sum: self.sum * factor,
+            positive_buckets: self.positive_buckets.iter().map(|b| b * factor).collect(),
+            negative_buckets: self.negative_buckets.iter().map(|b| b * factor).collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Divides the counts and the sum by a scalar, like Prometheus' `FloatHistogram.Div`.
+    ///
+    /// Dividing by zero gives infinite counts, or NaN for the empty ones.
+    pub fn div(&self, scalar: f64) -> FloatHistogram {
+        FloatHistogram {
+            zero_count: self.zero_count / scalar,
+            count: self.count / scalar,
+            sum: self.sum / scalar,
+            positive_buckets: self.positive_buckets.iter().map(|b| b / scalar).collect(),
+            negative_buckets: self.negative_buckets.iter().map(|b| b / scalar).collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Converts the float histogram to an integer histogram.
+    ///
+    /// It fails if a count isn't a positive integer.
+    pub fn to_integer(&self) -> Result<Histogram, RustyChunkEncError> {
+        let to_count = |value: f64, name: &str| {
+            float_to_count(value).ok_or_else(|| {
+                RustyChunkEncError::InvalidHistogram(format!(
+                    "{} of {} is not an integer",
+                    name, value
+                ))
+            })
+        };
+        Ok(Histogram {
+            schema: self.schema,
+            zero_threshold: self.zero_threshold,
+            zero_count: to_count(self.zero_count, "zero count")?,
+            count: to_count(self.count, "count")?,
+            sum: self.sum,
+            positive_spans: self.positive_spans.clone(),
+            negative_spans: self.negative_spans.clone(),
+            positive_buckets: delta_buckets(&self.positive_buckets, "positive")?,
+            negative_buckets: delta_buckets(&self.negative_buckets, "negative")?,
+            custom_values: self.custom_values.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_histogram() -> Histogram {
+        Histogram {
+            schema: 0,
+            zero_threshold: 0.001,
+            zero_count: 2,
+            count: 9,
+            sum: 18.4,
+            positive_spans: vec![
+                Span {
+                    offset: 0,
+                    length: 2,
+                },
+                Span {
+                    offset: 1,
+                    length: 2,
+                },
+            ],
+            negative_spans: vec![Span {
+                offset: 1,
+                length: 1,
+            }],
+            positive_buckets: vec![1, 1, -1, 0],
+            negative_buckets: vec![2],
+            custom_values: vec![],
+        }
+    }
+
+    #[test]
+    fn test_bucket_bound() {
+        assert_eq!(bucket_bound(0, 0), 1.0);
+        assert_eq!(bucket_bound(1, 0), 2.0);
+        assert_eq!(bucket_bound(-1, 0), 0.5);
+        assert_eq!(bucket_bound(1, -1), 4.0);
+        assert_eq!(bucket_bound(-2, -2), 2f64.powi(-8));
+        assert_eq!(bucket_bound(-1, 1), 0.7071067811865475);
+        // One ulp below the square root of 2, like Prometheus.
+        assert_eq!(bucket_bound(1, 1), f64::from_bits(0x3FF6_A09E_667F_3BCC));
+        assert_eq!(bucket_bound(4, 3), f64::from_bits(0x3FF6_A09E_667F_3BCC));
+        assert_eq!(bucket_bound(3, 2), 1.6817928305074288);
+        assert_eq!(bucket_bound(-1, 8), 0.9972960560854698);
+        assert_eq!(bucket_bound(1024, 0), f64::MAX);
+        assert_eq!(bucket_bound(256, -2), f64::MAX);
+        assert_eq!(bucket_bound(1025, 0), f64::INFINITY);
+        assert_eq!(bucket_bound(-1074, 0), f64::from_bits(1));
+    }
+
+    #[test]
+    fn test_bucket_iterators() {
+        let histogram = test_histogram();
+
+        let positive_buckets = histogram.positive_bucket_iterator().collect::<Vec<_>>();
+        assert_eq!(
+            positive_buckets
+                .iter()
+                .map(|bucket| (bucket.index, bucket.lower, bucket.upper, bucket.count))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, 0.5, 1.0, 1),
+                (1, 1.0, 2.0, 2),
+                (3, 4.0, 8.0, 1),
+                (4, 8.0, 16.0, 1)
+            ]
+        );
+        assert!(positive_buckets
+            .iter()
+            .all(|bucket| !bucket.lower_inclusive && bucket.upper_inclusive));
+
+        let negative_buckets = histogram.negative_bucket_iterator().collect::<Vec<_>>();
+        assert_eq!(
+            negative_buckets,
+            vec![Bucket {
+                lower: -2.0,
+                upper: -1.0,
+                lower_inclusive: true,
+                upper_inclusive: false,
+                count: 2,
+                index: 1,
+            }]
+        );
+
+        let float_histogram = histogram.to_float();
+        assert_eq!(
+            float_histogram
+                .positive_bucket_iterator()
+                .map(|bucket| (bucket.index, bucket.count))
+                .collect::<Vec<_>>(),
+            vec![(0, 1.0), (1, 2.0), (3, 1.0), (4, 1.0)]
+        );
+    }
+
+    #[test]
+    fn test_bucket_iterator_with_empty_spans() {
+        let histogram = Histogram {
+            positive_spans: vec![
+                Span {
+                    offset: -2,
+                    length: 0,
+                },
+                Span {
+                    offset: 0,
+                    length: 1,
+                },
+                Span {
+                    offset: 2,
+                    length: 0,
+                },
+                Span {
+                    offset: 3,
+                    length: 1,
+                },
+            ],
+            positive_buckets: vec![4, -1],
+            ..Default::default()
+        };
+        assert_eq!(
+            histogram
+                .positive_bucket_iterator()
+                .map(|bucket| (bucket.index, bucket.count))
+                .collect::<Vec<_>>(),
+            vec![(-2, 4), (4, 3)]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(test_histogram().validate().is_ok());
+        assert!(test_histogram().to_float().validate().is_ok());
+
+        let invalid_histograms = vec![
+            Histogram {
+                schema: 9,
+                ..test_histogram()
+            },
+            Histogram {
+                count: 13,
+                ..test_histogram()
+            },
+            Histogram {
+                positive_buckets: vec![1, 1, -1],
+                ..test_histogram()
+            },
+            Histogram {
+                positive_buckets: vec![1, 1, -3, 0],
+                ..test_histogram()
+            },
+            Histogram {
+                negative_spans: vec![
+                    Span {
+                        offset: 1,
+                        length: 0,
+                    },
+                    Span {
+                        offset: -1,
+                        length: 1,
+                    },
+                ],
+                ..test_histogram()
+            },
+        ];
+        for histogram in invalid_histograms {
+            assert!(histogram.validate().is_err());
+        }
+
+        // The count can be larger than the buckets when the sum is NaN
+        let histogram = Histogram {
+            count: 13,
+            sum: f64::NAN,
+            ..test_histogram()
+        };
+        assert!(histogram.validate().is_ok());
+
+        let float_histogram = FloatHistogram {
+            positive_buckets: vec![1.0, -1.0, 1.0, 1.0],
+            ..test_histogram().to_float()
+        };
+        assert!(float_histogram.validate().is_err());
+    }
+
+    #[test]
+    fn test_conversions() {
+        let histogram = test_histogram();
+        let float_histogram = histogram.to_float();
+        assert_eq!(float_histogram.count, 9.0);
+        assert_eq!(float_histogram.positive_buckets, vec![1.0, 2.0, 1.0, 1.0]);
+        assert_eq!(float_histogram.negative_buckets, vec![2.0]);
+        assert_eq!(float_histogram.to_integer().unwrap(), histogram);
+
+        let float_histogram = FloatHistogram {
+            positive_buckets: vec![1.0, 2.5, 1.0, 1.0],
+            ..float_histogram
+        };
+        assert!(float_histogram.to_integer().is_err());
+    }
+
+    fn test_custom_buckets_histogram() -> Histogram {
+        Histogram {
+            schema: CUSTOM_BUCKETS_SCHEMA,
+            count: 6,
+            sum: 12.5,
+            positive_spans: vec![
+                Span {
+                    offset: 0,
+                    length: 2,
+                },
+                Span {
+                    offset: 1,
+                    length: 1,
+                },
+            ],
+            positive_buckets: vec![1, 2, -1],
+            custom_values: vec![0.5, 1.0, 2.5],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_custom_buckets() {
+        let histogram = test_custom_buckets_histogram();
+        assert!(histogram.validate().is_ok());
+        assert!(histogram.to_float().validate().is_ok());
+
+        let buckets = histogram.positive_bucket_iterator().collect::<Vec<_>>();
+        assert_eq!(
+            buckets,
+            vec![
+                Bucket {
+                    lower: f64::NEG_INFINITY,
+                    upper: 0.5,
+                    lower_inclusive: true,
+                    upper_inclusive: true,
+                    count: 1,
+                    index: 0,
+                },
+                Bucket {
+                    lower: 0.5,
+                    upper: 1.0,
+                    lower_inclusive: false,
+                    upper_inclusive: true,
+                    count: 3,
+                    index: 1,
+                },
+                Bucket {
+                    lower: 2.5,
+                    upper: f64::INFINITY,
+                    lower_inclusive: false,
+                    upper_inclusive: true,
+                    count: 2,
+                    index: 3,
+                },
+            ]
+        );
+
+        let invalid_histograms = vec![
+            // Not increasing
+            Histogram {
+                custom_values: vec![0.5, 0.5, 2.5],
+                ..test_custom_buckets_histogram()
+            },
+            // Explicit +Inf bound
+            Histogram {
+                custom_values: vec![0.5, 1.0, f64::INFINITY],
+                ..test_custom_buckets_histogram()
+            },
+            // Not enough bounds for the spans
+            Histogram {
+                custom_values: vec![0.5, 1.0],
+                ..test_custom_buckets_histogram()
+            },
+            Histogram {
+                zero_threshold: 0.001,
+                ..test_custom_buckets_histogram()
+            },
+            Histogram {
+                negative_spans: vec![Span {
+                    offset: 0,
+                    length: 1,
+                }],
+                negative_buckets: vec![1],
+                count: 7,
+                ..test_custom_buckets_histogram()
+            },
+            // Custom bounds with an exponential schema
+            Histogram {
+                custom_values: vec![1.0],
+                ..test_histogram()
+            },
+        ];
+        for histogram in invalid_histograms {
+            assert!(histogram.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_detect_counter_reset() {
+        let previous = test_histogram();
+        assert!(!previous.detect_counter_reset(&previous));
+
+        // A new bucket isn't a reset
+        let histogram = Histogram {
+            positive_spans: vec![Span {
+                offset: 0,
+                length: 5,
+            }],
+            positive_buckets: vec![1, 1, -2, 1, 0],
+            ..test_histogram()
+        };
+        assert!(histogram.validate().is_ok());
+        assert!(!histogram.detect_counter_reset(&previous));
+        assert!(!histogram
+            .to_float()
+            .detect_counter_reset(&previous.to_float()));
+
+        let resets = vec![
+            // The count goes down
+            Histogram {
+                count: 8,
+                ..test_histogram()
+            },
+            // The zero count goes down
+            Histogram {
+                zero_count: 1,
+                count: 10,
+                negative_buckets: vec![4],
+                ..test_histogram()
+            },
+            // A bucket goes down
+            Histogram {
+                count: 12,
+                positive_buckets: vec![1, 0, 2, 0],
+                ..test_histogram()
+            },
+            // A bucket disappears
+            Histogram {
+                count: 11,
+                positive_spans: vec![Span {
+                    offset: 0,
+                    length: 2,
+                }],
+                positive_buckets: vec![1, 1],
+                negative_buckets: vec![6],
+                ..test_histogram()
+            },
+        ];
+        for histogram in resets {
+            assert!(histogram.detect_counter_reset(&previous));
+            assert!(histogram
+                .to_float()
+                .detect_counter_reset(&previous.to_float()));
+        }
+
+        // Different schemas can't be compared
+        let histogram = Histogram {
+            schema: 1,
+            ..test_histogram()
+        };
+        assert!(!histogram.detect_counter_reset(&previous));
+
+        // Different custom bounds are a reset
+        let histogram = Histogram {
+            custom_values: vec![0.5, 1.0, 3.0],
+            ..test_custom_buckets_histogram()
+        };
+        assert!(histogram.detect_counter_reset(&test_custom_buckets_histogram()));
+    }
+
+    #[test]
+    fn test_merge_and_expand_spans() {
+        let histogram = test_histogram();
+        let spans = merge_spans(
+            &histogram.positive_spans,
+            &[
+                Span {
+                    offset: -2,
+                    length: 1,
+                },
+                Span {
+                    offset: 3,
+                    length: 2,
+                },
+            ],
+        );
+        assert_eq!(
+            spans,
+            vec![
+                Span {
+                    offset: -2,
+                    length: 1,
+                },
+                Span {
+                    offset: 1,
+                    length: 5,
+                },
+            ]
+        );
+
+        let expanded = histogram.expand_spans(&spans, &histogram.negative_spans);
+        assert!(expanded.validate().is_ok());
+        assert_eq!(expanded.positive_buckets, vec![0, 1, 1, -2, 1, 0]);
+        assert_eq!(
+            histogram
+                .to_float()
+                .expand_spans(&spans, &histogram.negative_spans)
+                .positive_buckets,
+            vec![0.0, 1.0, 2.0, 0.0, 1.0, 1.0]
+        );
+    }
+
+    fn float_histogram(schema: i32, first_index: i32, buckets: Vec<f64>) -> FloatHistogram {
+        FloatHistogram {
+            schema,
+            count: buckets.iter().sum(),
+            sum: 10.0,
+            positive_spans: vec![Span {
+                offset: first_index,
+                length: buckets.len() as u32,
+            }],
+            positive_buckets: buckets,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_reduce_resolution() {
+        let histogram = FloatHistogram {
+            negative_spans: vec![Span {
+                offset: 0,
+                length: 1,
+            }],
+            negative_buckets: vec![5.0],
+            ..float_histogram(1, 1, vec![1.0, 2.0, 3.0, 4.0])
+        };
+        let reduced = histogram.reduce_resolution(0).unwrap();
+        assert_eq!(reduced.schema, 0);
+        assert_eq!(
+            reduced.positive_spans,
+            vec![Span {
+                offset: 1,
+                length: 2
+            }]
+        );
+        assert_eq!(reduced.positive_buckets, vec![3.0, 7.0]);
+        assert_eq!(reduced.negative_buckets, vec![5.0]);
+        assert_eq!(reduced.count, histogram.count);
+
+        assert!(histogram.reduce_resolution(2).is_err());
+        assert!(test_custom_buckets_histogram()
+            .to_float()
+            .reduce_resolution(0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_add_and_sub() {
+        let a = FloatHistogram {
+            positive_spans: vec![Span {
+                offset: 0,
+                length: 2,
+            }],
+            ..float_histogram(0, 0, vec![1.0, 2.0])
+        };
+        let b = FloatHistogram {
+            positive_spans: vec![
+                Span {
+                    offset: 1,
+                    length: 1,
+                },
+                Span {
+                    offset: 1,
+                    length: 1,
+                },
+            ],
+            positive_buckets: vec![1.0, 4.0],
+            count: 5.0,
+            ..float_histogram(0, 0, vec![])
+        };
+        let sum = a.add(&b).unwrap();
+        assert_eq!(sum.count, 8.0);
+        assert_eq!(sum.sum, 20.0);
+        assert_eq!(
+            sum.positive_spans,
+            vec![
+                Span {
+                    offset: 0,
+                    length: 2
+                },
+                Span {
+                    offset: 1,
+                    length: 1
+                }
+            ]
+        );
+        assert_eq!(sum.positive_buckets, vec![1.0, 3.0, 4.0]);
+        assert!(sum.validate().is_ok());
+
+        let difference = sum.sub(&b).unwrap();
+        assert_eq!(difference.count, 3.0);
+        assert_eq!(difference.positive_buckets, vec![1.0, 2.0, 0.0]);
+
+        // The higher resolution is reduced
+        let high_resolution = float_histogram(1, 1, vec![1.0, 2.0, 3.0, 4.0]);
+        let low_resolution = float_histogram(0, 2, vec![5.0]);
+        for sum in [
+            high_resolution.add(&low_resolution).unwrap(),
+            low_resolution.add(&high_resolution).unwrap(),
+        ] {
+            assert_eq!(sum.schema, 0);
+            assert_eq!(sum.positive_buckets, vec![3.0, 12.0]);
+            assert_eq!(sum.count, 15.0);
+        }
+    }
+
+    #[test]
+    fn test_add_with_different_zero_thresholds() {
+        let a = FloatHistogram {
+            zero_threshold: 0.001,
+            zero_count: 1.0,
+            count: 6.0,
+            ..float_histogram(0, 0, vec![2.0, 3.0])
+        };
+        let b = FloatHistogram {
+            zero_threshold: 0.6,
+            zero_count: 2.0,
+            count: 3.0,
+            ..float_histogram(0, 1, vec![1.0])
+        };
+        // The zero bucket widens to cover the populated (0.5, 1] bucket.
+        for sum in [a.add(&b).unwrap(), b.add(&a).unwrap()] {
+            assert_eq!(sum.zero_threshold, 1.0);
+            assert_eq!(sum.zero_count, 5.0);
+            assert_eq!(sum.count, 9.0);
+            assert_eq!(
+                sum.positive_spans,
+                vec![Span {
+                    offset: 1,
+                    length: 1
+                }]
+            );
+            assert_eq!(sum.positive_buckets, vec![4.0]);
+        }
+    }
+
+    #[test]
+    fn test_add_with_different_schemas_and_zero_thresholds() {
+        let a = FloatHistogram {
+            zero_threshold: 0.6,
+            zero_count: 1.0,
+            count: 6.0,
+            ..float_histogram(1, 0, vec![2.0, 3.0])
+        };
+        let b = FloatHistogram {
+            zero_threshold: 0.001,
+            zero_count: 1.0,
+            count: 5.0,
+            ..float_histogram(0, 1, vec![4.0])
+        };
+        // The (0.707, 1] bucket stays out of the zero bucket, even though
+        // it falls in the (0.5, 1] bucket once the resolution is reduced.
+        for sum in [a.add(&b).unwrap(), b.add(&a).unwrap()] {
+            assert_eq!(sum.schema, 0);
+            assert_eq!(sum.zero_threshold, 0.6);
+            assert_eq!(sum.zero_count, 2.0);
+            assert_eq!(sum.count, 11.0);
+            assert_eq!(
+                sum.positive_spans,
+                vec![Span {
+                    offset: 0,
+                    length: 2
+                }]
+            );
+            assert_eq!(sum.positive_buckets, vec![2.0, 7.0]);
+        }
+    }
+
+    #[test]
+    fn test_add_custom_buckets() {
+        let histogram = test_custom_buckets_histogram().to_float();
+        let sum = histogram.add(&histogram).unwrap();
+        assert_eq!(sum.count, 12.0);
+        assert_eq!(sum.positive_buckets, vec![2.0, 6.0, 4.0]);
+        assert_eq!(sum.custom_values, histogram.custom_values);
+
+        let other_bounds = FloatHistogram {
+            custom_values: vec![0.5, 1.0, 3.0],
+            ..histogram.clone()
+        };
+        assert!(histogram.add(&other_bounds).is_err());
+        assert!(histogram.add(&test_histogram().to_float()).is_err());
+    }
+
+    #[test]
+    fn test_mul_and_div() {
+        let histogram = test_histogram().to_float();
+        let doubled = histogram.mul(2.0);
+        assert_eq!(doubled.count, 18.0);
+        assert_eq!(doubled.zero_count, 4.0);
+        assert_eq!(doubled.sum, 36.8);
+        assert_eq!(doubled.positive_buckets, vec![2.0, 4.0, 2.0, 2.0]);
+        assert_eq!(doubled.negative_buckets, vec![4.0]);
+        assert_eq!(doubled.div(2.0), histogram);
+
+        let divided = histogram.div(0.0);
+        assert_eq!(divided.count, f64::INFINITY);
+    }
+}
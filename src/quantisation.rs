@@ -0,0 +1,146 @@
+use crate::xor::XORSample;
+
+/// A lossy quantisation of the values, applied before the XOR encoding.
+///
+/// Noisy values differ in their low mantissa bits, that `varbit_xor` must
+/// then write for every sample. Dropping that noise makes the chunks smaller,
+/// and they are still valid Prometheus XOR chunks.
+///
+/// NaN values, like the staleness markers, and infinities are kept as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantisation {
+    /// Rounds the values to this number of significant decimal digits.
+    ///
+    /// It is at least 1 digit, and values with 17 digits or more are unchanged.
+    SignificantDigits(u32),
+    /// Clears this number of the lowest mantissa bits, rounding towards zero.
+    ///
+    /// It is at most the 52 bits of the mantissa.
+    MantissaBits(u32),
+}
+
+impl Quantisation {
+    /// Returns the quantised value.
+    pub fn apply(&self, value: f64) -> f64 {
+        if !value.is_finite() || value == 0.0 {
+            return value;
+        }
+        match *self {
+            Quantisation::SignificantDigits(digits) => round_to_significant_digits(value, digits),
+            Quantisation::MantissaBits(bits) => {
+                let bits = bits.min(52);
+                f64::from_bits(value.to_bits() & !((1u64 << bits) - 1))
+            }
+        }
+    }
+
+    /// Returns the quantised samples, and the maximum absolute error introduced.
+    pub fn apply_to_samples(&self, samples: &[XORSample]) -> (Vec<XORSample>, f64) {
+        let mut max_error: f64 = 0.0;
+        let quantised_samples = samples
+            .iter()
+            .map(|sample| {
+                let value = self.apply(sample.value);
+                if value.is_finite() {
+                    max_error = max_error.max((value - sample.value).abs());
+                }
+                XORSample {
+                    timestamp: sample.timestamp,
+                    value,
+                }
+            })
+            .collect();
+        (quantised_samples, max_error)
+    }
+}
+
+fn round_to_significant_digits(value: f64, digits: u32) -> f64 {
+    // 17 significant digits are enough for any f64.
+    if digits >= 17 {
+        return value;
+    }
+    let digits = digits.max(1) as i32;
+    let exponent = value.abs().log10().floor() as i32;
+    let power = digits - 1 - exponent;
+    // Scaling by an exact power of ten when possible, so the result is the
+    // closest f64 to the rounded decimal value.
+    let rounded = if power >= 0 {
+        let scale = 10f64.powi(power);
+        (value * scale).round() / scale
+    } else {
+        let scale = 10f64.powi(-power);
+        (value / scale).round() * scale
+    };
+    // Extreme values overflow while scaling.
+    if rounded.is_finite() {
+        rounded
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::{is_stale_nan, STALE_NAN};
+
+    #[test]
+    fn test_significant_digits() {
+        let quantisation = Quantisation::SignificantDigits(3);
+        assert_eq!(quantisation.apply(21.347891), 21.3);
+        assert_eq!(quantisation.apply(-0.00123456), -0.00123);
+        assert_eq!(quantisation.apply(987654.0), 988000.0);
+        assert_eq!(quantisation.apply(0.0), 0.0);
+        assert_eq!(quantisation.apply(f64::INFINITY), f64::INFINITY);
+        assert!(is_stale_nan(quantisation.apply(STALE_NAN)));
+        assert_eq!(quantisation.apply(f64::MAX), f64::MAX);
+        assert_eq!(
+            Quantisation::SignificantDigits(0).apply(1234.5),
+            Quantisation::SignificantDigits(1).apply(1234.5)
+        );
+        assert_eq!(
+            Quantisation::SignificantDigits(17).apply(0.1 + 0.2),
+            0.1 + 0.2
+        );
+    }
+
+    #[test]
+    fn test_mantissa_bits() {
+        let value = f64::from_bits(0x4035_5917_3D2E_48F1);
+        assert_eq!(
+            Quantisation::MantissaBits(20).apply(value).to_bits(),
+            0x4035_5917_3D20_0000
+        );
+        assert_eq!(Quantisation::MantissaBits(0).apply(value), value);
+        // Only the sign and the exponent are left.
+        assert_eq!(Quantisation::MantissaBits(100).apply(value), 16.0);
+        assert_eq!(Quantisation::MantissaBits(8).apply(-1.0), -1.0);
+        assert!(is_stale_nan(
+            Quantisation::MantissaBits(52).apply(STALE_NAN)
+        ));
+    }
+
+    #[test]
+    fn test_apply_to_samples() {
+        let samples = vec![
+            XORSample {
+                timestamp: 1000,
+                value: 20.04,
+            },
+            XORSample::stale(2000),
+            XORSample {
+                timestamp: 3000,
+                value: 19.96,
+            },
+        ];
+        let (quantised, max_error) = Quantisation::SignificantDigits(2).apply_to_samples(&samples);
+        assert_eq!(quantised[0].value, 20.0);
+        assert!(quantised[1].is_stale_marker());
+        assert_eq!(quantised[2].value, 20.0);
+        assert!((max_error - 0.04).abs() < 1e-12);
+        assert_eq!(
+            quantised.iter().map(|s| s.timestamp).collect::<Vec<_>>(),
+            [1000, 2000, 3000]
+        );
+    }
+}
@@ -0,0 +1,454 @@
+use crate::{
+    aggr_chunk::{AggrChunk, AggrType},
+    errors::RustyChunkEncError,
+    histogram::{FloatHistogramIterator, FloatHistogramSample, HistogramIterator, HistogramSample},
+    native_histogram::{FloatHistogram, Histogram},
+    xor::{XORIterator, XORSample},
+};
+
+/// The type of the current sample of a [`SampleIterator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    /// No sample, the iterator is exhausted.
+    None,
+    Float,
+    Histogram,
+    FloatHistogram,
+}
+
+/// An iterator over the samples of a chunk, whatever its encoding.
+///
+/// Modelled on Prometheus' `chunkenc.Iterator`: `next` and `seek` return the
+/// type of the current sample, that is then read with the matching `at` method.
+/// Unlike Prometheus, the `at` methods return None instead of panicking
+/// when there is no current sample, or when it has another type.
+///
+/// It is implemented by the streaming iterators of each encoding, and by
+/// [`ChunkSampleIterator`] over the decoded samples of any chunk.
+pub trait SampleIterator {
+    /// Advances to the next sample, and returns its type.
+    fn next(&mut self) -> ValueType;
+
+    /// Advances to the first sample with a timestamp equal or greater than `t`,
+    /// and returns its type.
+    ///
+    /// It does nothing if the current sample is already there.
+    fn seek(&mut self, t: i64) -> ValueType;
+
+    /// Returns the current float sample.
+    fn at(&self) -> Option<(i64, f64)>;
+
+    /// Returns the current histogram sample.
+    fn at_histogram(&self) -> Option<(i64, Histogram)>;
+
+    /// Returns the current histogram or float histogram sample, as a float histogram.
+    fn at_float_histogram(&self) -> Option<(i64, FloatHistogram)>;
+
+    /// Returns the timestamp of the current sample, or 0 when there is none.
+    fn at_t(&self) -> i64;
+
+    /// Returns the error that stopped the iteration, if any.
+    fn err(&self) -> Option<&RustyChunkEncError>;
+}
+
+fn value_type(found: bool, value_type: ValueType) -> ValueType {
+    if found {
+        value_type
+    } else {
+        ValueType::None
+    }
+}
+
+impl SampleIterator for XORIterator<'_> {
+    fn next(&mut self) -> ValueType {
+        value_type(Iterator::next(self).is_some(), ValueType::Float)
+    }
+    fn seek(&mut self, t: i64) -> ValueType {
+        value_type(XORIterator::seek(self, t), ValueType::Float)
+    }
+    fn at(&self) -> Option<(i64, f64)> {
+        (self.num_read > 0 && !self.exhausted).then(|| XORIterator::at(self))
+    }
+    fn at_histogram(&self) -> Option<(i64, Histogram)> {
+        None
+    }
+    fn at_float_histogram(&self) -> Option<(i64, FloatHistogram)> {
+        None
+    }
+    fn at_t(&self) -> i64 {
+        SampleIterator::at(self).map_or(0, |(t, _)| t)
+    }
+    fn err(&self) -> Option<&RustyChunkEncError> {
+        XORIterator::err(self)
+    }
+}
+
+impl SampleIterator for HistogramIterator<'_> {
+    fn next(&mut self) -> ValueType {
+        value_type(self.advance(), ValueType::Histogram)
+    }
+    fn seek(&mut self, t: i64) -> ValueType {
+        value_type(HistogramIterator::seek(self, t), ValueType::Histogram)
+    }
+    fn at(&self) -> Option<(i64, f64)> {
+        None
+    }
+    fn at_histogram(&self) -> Option<(i64, Histogram)> {
+        self.current_timestamp()?;
+        HistogramIterator::at(self).map(|sample| (sample.timestamp, sample.histogram))
+    }
+    fn at_float_histogram(&self) -> Option<(i64, FloatHistogram)> {
+        SampleIterator::at_histogram(self).map(|(t, histogram)| (t, histogram.to_float()))
+    }
+    fn at_t(&self) -> i64 {
+        self.current_timestamp().unwrap_or(0)
+    }
+    fn err(&self) -> Option<&RustyChunkEncError> {
+        HistogramIterator::err(self)
+    }
+}
+
+impl SampleIterator for FloatHistogramIterator<'_> {
+    fn next(&mut self) -> ValueType {
+        value_type(self.advance(), ValueType::FloatHistogram)
+    }
+    fn seek(&mut self, t: i64) -> ValueType {
+        value_type(
+            FloatHistogramIterator::seek(self, t),
+            ValueType::FloatHistogram,
+        )
+    }
+    fn at(&self) -> Option<(i64, f64)> {
+        None
+    }
+    fn at_histogram(&self) -> Option<(i64, Histogram)> {
+        None
+    }
+    fn at_float_histogram(&self) -> Option<(i64, FloatHistogram)> {
+        self.current_timestamp()?;
+        FloatHistogramIterator::at(self).map(|sample| (sample.timestamp, sample.histogram))
+    }
+    fn at_t(&self) -> i64 {
+        self.current_timestamp().unwrap_or(0)
+    }
+    fn err(&self) -> Option<&RustyChunkEncError> {
+        FloatHistogramIterator::err(self)
+    }
+}
+
+/// The decoded samples of a chunk.
+#[derive(Debug, Clone, Copy)]
+enum ChunkSamples<'a> {
+    Float(&'a [XORSample]),
+    Histogram(&'a [HistogramSample]),
+    FloatHistogram(&'a [FloatHistogramSample]),
+}
+
+/// A [`SampleIterator`] over the decoded samples of a chunk.
+///
+/// It is returned by [`crate::chunk::Chunk::iterator`] and [`AggrChunk::iterator`].
+#[derive(Debug, Clone)]
+pub struct ChunkSampleIterator<'a> {
+    samples: ChunkSamples<'a>,
+    /// The number of samples read, the current one included.
+    position: usize,
+}
+
+impl<'a> ChunkSampleIterator<'a> {
+    /// Creates an iterator over float samples.
+    pub fn new_float(samples: &'a [XORSample]) -> Self {
+        Self::new(ChunkSamples::Float(samples))
+    }
+
+    /// Creates an iterator over histogram samples.
+    pub fn new_histogram(samples: &'a [HistogramSample]) -> Self {
+        Self::new(ChunkSamples::Histogram(samples))
+    }
+
+    /// Creates an iterator over float histogram samples.
+    pub fn new_float_histogram(samples: &'a [FloatHistogramSample]) -> Self {
+        Self::new(ChunkSamples::FloatHistogram(samples))
+    }
+
+    fn new(samples: ChunkSamples<'a>) -> Self {
+        Self {
+            samples,
+            position: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self.samples {
+            ChunkSamples::Float(samples) => samples.len(),
+            ChunkSamples::Histogram(samples) => samples.len(),
+            ChunkSamples::FloatHistogram(samples) => samples.len(),
+        }
+    }
+
+    fn timestamp(&self, index: usize) -> i64 {
+        match self.samples {
+            ChunkSamples::Float(samples) => samples[index].timestamp,
+            ChunkSamples::Histogram(samples) => samples[index].timestamp,
+            ChunkSamples::FloatHistogram(samples) => samples[index].timestamp,
+        }
+    }
+
+    /// Returns the index of the current sample.
+    fn current(&self) -> Option<usize> {
+        (1..=self.len())
+            .contains(&self.position)
+            .then(|| self.position - 1)
+    }
+
+    fn value_type(&self) -> ValueType {
+        if self.current().is_none() {
+            return ValueType::None;
+        }
+        match self.samples {
+            ChunkSamples::Float(_) => ValueType::Float,
+            ChunkSamples::Histogram(_) => ValueType::Histogram,
+            ChunkSamples::FloatHistogram(_) => ValueType::FloatHistogram,
+        }
+    }
+}
+
+impl SampleIterator for ChunkSampleIterator<'_> {
+    fn next(&mut self) -> ValueType {
+        if self.position <= self.len() {
+            self.position += 1;
+        }
+        self.value_type()
+    }
+    fn seek(&mut self, t: i64) -> ValueType {
+        let len = self.len();
+        if self.position > len {
+            return ValueType::None;
+        }
+        let start = self.position.saturating_sub(1);
+        let index = (start..len)
+            .find(|&index| self.timestamp(index) >= t)
+            .unwrap_or(len);
+        self.position = index + 1;
+        self.value_type()
+    }
+    fn at(&self) -> Option<(i64, f64)> {
+        match self.samples {
+            ChunkSamples::Float(samples) => {
+                let sample = &samples[self.current()?];
+                Some((sample.timestamp, sample.value))
+            }
+            _ => None,
+        }
+    }
+    fn at_histogram(&self) -> Option<(i64, Histogram)> {
+        match self.samples {
+            ChunkSamples::Histogram(samples) => {
+                let sample = &samples[self.current()?];
+                Some((sample.timestamp, sample.histogram.clone()))
+            }
+            _ => None,
+        }
+    }
+    fn at_float_histogram(&self) -> Option<(i64, FloatHistogram)> {
+        match self.samples {
+            ChunkSamples::Histogram(samples) => {
+                let sample = &samples[self.current()?];
+                Some((sample.timestamp, sample.histogram.to_float()))
+            }
+            ChunkSamples::FloatHistogram(samples) => {
+                let sample = &samples[self.current()?];
+                Some((sample.timestamp, sample.histogram.clone()))
+            }
+            ChunkSamples::Float(_) => None,
+        }
+    }
+    fn at_t(&self) -> i64 {
+        self.current().map_or(0, |index| self.timestamp(index))
+    }
+    fn err(&self) -> Option<&RustyChunkEncError> {
+        None
+    }
+}
+
+impl AggrChunk {
+    /// Returns a [`SampleIterator`] over the samples of one aggregate,
+    /// or None when the aggregate is missing.
+    pub fn iterator(&self, aggr_type: AggrType) -> Option<ChunkSampleIterator<'_>> {
+        let chunk = self.get(aggr_type)?;
+        Some(ChunkSampleIterator::new_float(chunk.samples()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk::Chunk,
+        histogram::{CounterResetHeader, FloatHistogramChunk, HistogramChunk},
+        xor::XORChunk,
+    };
+
+    fn chunks() -> Vec<Chunk> {
+        vec![
+            Chunk::new_xor(vec![
+                XORSample {
+                    timestamp: 10,
+                    value: 1.0,
+                },
+                XORSample {
+                    timestamp: 20,
+                    value: 2.0,
+                },
+            ]),
+            Chunk::Histogram(HistogramChunk::new(
+                vec![HistogramSample {
+                    timestamp: 30,
+                    histogram: Histogram {
+                        count: 3,
+                        zero_count: 3,
+                        sum: 1.5,
+                        ..Default::default()
+                    },
+                }],
+                CounterResetHeader::Unknown,
+            )),
+            Chunk::FloatHistogram(FloatHistogramChunk::new(
+                vec![
+                    FloatHistogramSample {
+                        timestamp: 40,
+                        histogram: FloatHistogram {
+                            count: 4.0,
+                            zero_count: 4.0,
+                            ..Default::default()
+                        },
+                    },
+                    FloatHistogramSample {
+                        timestamp: 50,
+                        histogram: FloatHistogram {
+                            count: 5.0,
+                            zero_count: 5.0,
+                            ..Default::default()
+                        },
+                    },
+                ],
+                CounterResetHeader::Gauge,
+            )),
+        ]
+    }
+
+    #[test]
+    fn test_mixed_chunks() {
+        let chunks = chunks();
+        let mut counts = Vec::new();
+        for chunk in &chunks {
+            let mut iterator = chunk.iterator(AggrType::Count);
+            assert_eq!(iterator.at_t(), 0);
+            loop {
+                match iterator.next() {
+                    ValueType::None => break,
+                    ValueType::Float => {
+                        let (t, value) = iterator.at().unwrap();
+                        assert!(iterator.at_histogram().is_none());
+                        counts.push((t, value));
+                    }
+                    ValueType::Histogram | ValueType::FloatHistogram => {
+                        let (t, histogram) = iterator.at_float_histogram().unwrap();
+                        assert!(iterator.at().is_none());
+                        counts.push((t, histogram.count));
+                    }
+                }
+            }
+            assert!(iterator.err().is_none());
+            assert_eq!(iterator.next(), ValueType::None);
+            assert!(iterator.at_float_histogram().is_none());
+            assert_eq!(iterator.at_t(), 0);
+        }
+        assert_eq!(
+            counts,
+            [(10, 1.0), (20, 2.0), (30, 3.0), (40, 4.0), (50, 5.0)]
+        );
+
+        let mut iterator = chunks[1].iterator(AggrType::Count);
+        assert_eq!(iterator.next(), ValueType::Histogram);
+        assert_eq!(iterator.at_histogram().unwrap().1.count, 3);
+
+        let mut iterator = chunks[2].iterator(AggrType::Count);
+        assert_eq!(iterator.seek(45), ValueType::FloatHistogram);
+        let (t, histogram) = iterator.at_float_histogram().unwrap();
+        assert_eq!((t, histogram.count), (50, 5.0));
+        assert_eq!(iterator.seek(51), ValueType::None);
+
+        let empty_chunk = Chunk::new_xor(vec![]);
+        let mut iterator = empty_chunk.iterator(AggrType::Count);
+        assert_eq!(iterator.next(), ValueType::None);
+        assert!(iterator.err().is_none());
+    }
+
+    #[test]
+    fn test_aggr_chunk() {
+        let xor_chunk = chunks().into_iter().next().unwrap().as_xor();
+        let aggr_chunk = Chunk::Aggr(AggrChunk::new([xor_chunk, None, None, None, None]));
+        let mut iterator = aggr_chunk.iterator(AggrType::Count);
+        assert_eq!(iterator.seek(15), ValueType::Float);
+        assert_eq!(iterator.at(), Some((20, 2.0)));
+        // A missing aggregate has no samples
+        let mut iterator = aggr_chunk.iterator(AggrType::Sum);
+        assert_eq!(iterator.next(), ValueType::None);
+
+        let Chunk::Aggr(aggr_chunk) = &aggr_chunk else {
+            unreachable!()
+        };
+        assert!(aggr_chunk.iterator(AggrType::Count).is_some());
+        assert!(aggr_chunk.iterator(AggrType::Sum).is_none());
+    }
+
+    #[test]
+    fn test_seek() {
+        let chunk = Chunk::new_xor(
+            (0..100)
+                .map(|i| XORSample {
+                    timestamp: i * 10,
+                    value: i as f64,
+                })
+                .collect(),
+        );
+        let mut iterator = chunk.iterator(AggrType::Count);
+        assert_eq!(iterator.seek(-10), ValueType::Float);
+        assert_eq!(iterator.at(), Some((0, 0.0)));
+        assert_eq!(iterator.seek(415), ValueType::Float);
+        assert_eq!(iterator.at_t(), 420);
+        // No-op when already there
+        assert_eq!(iterator.seek(100), ValueType::Float);
+        assert_eq!(iterator.at_t(), 420);
+        assert_eq!(iterator.next(), ValueType::Float);
+        assert_eq!(iterator.at_t(), 430);
+        assert_eq!(iterator.seek(990), ValueType::Float);
+        assert_eq!(iterator.next(), ValueType::None);
+        assert_eq!(iterator.seek(0), ValueType::None);
+        assert!(iterator.at().is_none());
+
+        let mut iterator = chunk.iterator(AggrType::Count);
+        assert_eq!(iterator.seek(991), ValueType::None);
+        assert_eq!(iterator.next(), ValueType::None);
+    }
+
+    #[test]
+    fn test_streaming_iterator() {
+        let chunk = XORChunk::new(
+            (0..10)
+                .map(|i| XORSample {
+                    timestamp: i * 10,
+                    value: i as f64,
+                })
+                .collect(),
+        );
+        let mut buffer = Vec::new();
+        chunk.write(&mut buffer).unwrap();
+        let mut iterator = XORIterator::new(&buffer);
+        let iterator: &mut dyn SampleIterator = &mut iterator;
+        assert_eq!(iterator.seek(45), ValueType::Float);
+        assert_eq!(iterator.at(), Some((50, 5.0)));
+        assert_eq!(iterator.seek(95), ValueType::None);
+        assert!(iterator.err().is_none());
+    }
+}
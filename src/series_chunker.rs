@@ -0,0 +1,333 @@
+use crate::{
+    chunk::Chunk,
+    encoder::xor_encoder::{sample_bit_len, XORReadIterator},
+    errors::RustyChunkEncError,
+    varint::varint_len,
+    xor::XORSample,
+};
+
+/// Prometheus' default chunk range, two hours in milliseconds.
+pub const DEFAULT_CHUNK_RANGE: i64 = 2 * 60 * 60 * 1000;
+
+/// Prometheus' default target number of samples per chunk.
+pub const DEFAULT_SAMPLES_PER_CHUNK: usize = 120;
+
+/// Prometheus' maximum size of a XOR chunk, before a new chunk is cut.
+pub const DEFAULT_MAX_BYTES_PER_CHUNK: usize = 1024;
+
+/// Cuts a series of samples into XOR chunks, like the Prometheus head.
+///
+/// A new chunk is cut when:
+/// - the sample crosses the end of the current chunk range, aligned on multiples of the chunk range,
+/// - the sample crosses the predicted end of the chunk, computed once the chunk has
+///   a quarter of its target number of samples, so that the samples are spread evenly
+///   between the chunks of the chunk range,
+/// - the chunk has twice its target number of samples,
+/// - or the chunk is larger than the maximum number of bytes.
+///
+/// The size of the chunk is tracked without encoding it, the samples are only
+/// encoded once when the chunk is written.
+#[derive(Debug, Clone)]
+pub struct SeriesChunker {
+    chunk_range: i64,
+    samples_per_chunk: usize,
+    max_bytes_per_chunk: usize,
+    samples: Vec<XORSample>,
+    /// The state of the encoder after the last sample of the open chunk.
+    encoder_state: Option<XORReadIterator>,
+    /// The encoded size of the open chunk.
+    num_bits: usize,
+    next_at: i64,
+}
+
+impl Default for SeriesChunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SeriesChunker {
+    /// Creates a chunker with Prometheus' default settings.
+    pub fn new() -> Self {
+        Self {
+            chunk_range: DEFAULT_CHUNK_RANGE,
+            samples_per_chunk: DEFAULT_SAMPLES_PER_CHUNK,
+            max_bytes_per_chunk: DEFAULT_MAX_BYTES_PER_CHUNK,
+            samples: Vec::new(),
+            encoder_state: None,
+            num_bits: 0,
+            next_at: i64::MIN,
+        }
+    }
+
+    /// Sets the chunk range, in milliseconds.
+    pub fn with_chunk_range(mut self, chunk_range: i64) -> Self {
+        assert!(chunk_range > 0, "the chunk range must be positive");
+        self.chunk_range = chunk_range;
+        self
+    }
+
+    /// Sets the target number of samples per chunk.
+    pub fn with_samples_per_chunk(mut self, samples_per_chunk: usize) -> Self {
+        self.samples_per_chunk = samples_per_chunk;
+        self
+    }
+
+    /// Sets the maximum size of a chunk, in bytes.
+    pub fn with_max_bytes_per_chunk(mut self, max_bytes_per_chunk: usize) -> Self {
+        self.max_bytes_per_chunk = max_bytes_per_chunk;
+        self
+    }
+
+    /// Returns the samples of the open chunk.
+    pub fn samples(&self) -> &[XORSample] {
+        &self.samples
+    }
+
+    /// Appends a sample.
+    ///
+    /// The samples must be sorted by timestamp.
+    /// Returns the previous chunk when the sample had to start a new chunk.
+    pub fn append(&mut self, sample: XORSample) -> Result<Option<Chunk>, RustyChunkEncError> {
+        let mut previous_chunk = None;
+
+        if let (Some(first), Some(last)) = (self.samples.first(), self.samples.last()) {
+            if sample.timestamp < last.timestamp {
+                return Err(RustyChunkEncError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "samples aren't sorted by timestamp ascending",
+                )));
+            }
+
+            let num_samples = self.samples.len();
+            if num_samples == self.samples_per_chunk / 4 {
+                self.next_at =
+                    compute_chunk_end_time(first.timestamp, last.timestamp, self.next_at, 4.0);
+            }
+            if sample.timestamp >= self.next_at
+                || num_samples >= self.samples_per_chunk * 2
+                || num_samples >= u16::MAX as usize
+                || self.num_bits.div_ceil(8) > self.max_bytes_per_chunk
+            {
+                previous_chunk = self.cut();
+            }
+        }
+
+        if self.samples.is_empty() {
+            self.next_at = range_for_timestamp(sample.timestamp, self.chunk_range);
+        }
+        self.track_encoded_size(&sample)?;
+        self.samples.push(sample);
+        Ok(previous_chunk)
+    }
+
+    /// Returns the open chunk, if it has samples.
+    pub fn finish(mut self) -> Option<Chunk> {
+        self.cut()
+    }
+
+    /// Cuts all the samples into chunks.
+    pub fn chunks<I: IntoIterator<Item = XORSample>>(
+        mut self,
+        samples: I,
+    ) -> Result<Vec<Chunk>, RustyChunkEncError> {
+        let mut chunks = Vec::new();
+        for sample in samples {
+            if let Some(chunk) = self.append(sample)? {
+                chunks.push(chunk);
+            }
+        }
+        chunks.extend(self.finish());
+        Ok(chunks)
+    }
+
+    fn cut(&mut self) -> Option<Chunk> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        self.encoder_state = None;
+        self.num_bits = 0;
+        Some(Chunk::new_xor(std::mem::take(&mut self.samples)))
+    }
+
+    /// Adds the size of the sample to the size of the open chunk.
+    fn track_encoded_size(&mut self, sample: &XORSample) -> std::io::Result<()> {
+        match &self.encoder_state {
+            None => {
+                // The number of samples, the timestamp and the raw value.
+                self.num_bits = (2 + varint_len(sample.timestamp) + 8) * 8;
+                self.encoder_state = Some(XORReadIterator::from_first_sample(sample));
+            }
+            Some(state) => {
+                let (sample_bits, new_state) =
+                    sample_bit_len(state, self.samples.len() == 1, sample)?;
+                self.num_bits += sample_bits;
+                self.encoder_state = Some(new_state);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the end of the chunk range containing the timestamp.
+fn range_for_timestamp(timestamp: i64, chunk_range: i64) -> i64 {
+    (timestamp / chunk_range * chunk_range).saturating_add(chunk_range)
+}
+
+/// Predicts the end time of a chunk, so the chunks of the chunk range
+/// get about the same number of samples.
+///
+/// `ratio_to_full` is the inverse of how full the chunk currently is.
+fn compute_chunk_end_time(start: i64, current: i64, max_time: i64, ratio_to_full: f64) -> i64 {
+    let n = (max_time - start) as f64 / ((current - start + 1) as f64 * ratio_to_full);
+    // Catch rounding errors and stick to the end of the range.
+    if n <= 1.0 {
+        return max_time;
+    }
+    (start as f64 + (max_time - start) as f64 / n.floor()) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read_chunks, xor::XORChunk, ChunksDiskFormat};
+
+    fn xor_samples(chunk: &Chunk) -> &[XORSample] {
+        match chunk {
+            Chunk::XOR(chunk) => chunk.samples(),
+            _ => panic!("not a XOR chunk"),
+        }
+    }
+
+    fn samples(count: i64, interval: i64, value: impl Fn(i64) -> f64) -> Vec<XORSample> {
+        (0..count)
+            .map(|i| XORSample {
+                timestamp: i * interval,
+                value: value(i),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compute_chunk_end_time() {
+        assert_eq!(range_for_timestamp(0, 1000), 1000);
+        assert_eq!(range_for_timestamp(1500, 1000), 2000);
+        assert_eq!(range_for_timestamp(2000, 1000), 3000);
+
+        // A quarter of a chunk in 1/16 of the range, 4 chunks in the range.
+        assert_eq!(compute_chunk_end_time(0, 999, 16000, 4.0), 4000);
+        // Less than one chunk in the range.
+        assert_eq!(compute_chunk_end_time(0, 9999, 16000, 4.0), 16000);
+    }
+
+    #[test]
+    fn test_series_chunker_regular_scrapes() {
+        // One sample every 15 seconds for 4 hours.
+        let input = samples(4 * 240, 15000, |i| i as f64);
+        let chunks = SeriesChunker::new().chunks(input.clone()).unwrap();
+
+        assert_eq!(chunks.len(), 8);
+        for chunk in &chunks {
+            let samples = xor_samples(chunk);
+            assert_eq!(samples.len(), 120);
+            // No chunk crosses a chunk range boundary.
+            assert_eq!(
+                samples[0].timestamp / DEFAULT_CHUNK_RANGE,
+                samples[samples.len() - 1].timestamp / DEFAULT_CHUNK_RANGE
+            );
+        }
+        let output = chunks
+            .iter()
+            .flat_map(|chunk| xor_samples(chunk).to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(output, input);
+
+        // Ready for the disk format.
+        let chunk_disk_format = ChunksDiskFormat::new(chunks, None);
+        let mut buffer: Vec<u8> = Vec::new();
+        chunk_disk_format.write(&mut buffer).unwrap();
+        let (_, parsed) = read_chunks(&buffer, None).unwrap();
+        assert_eq!(parsed, chunk_disk_format);
+    }
+
+    #[test]
+    fn test_series_chunker_limits() {
+        // Very frequent samples are spread evenly too.
+        let chunks = SeriesChunker::new()
+            .chunks(samples(1000, 1, |_| 42.0))
+            .unwrap();
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| xor_samples(chunk).len())
+                .collect::<Vec<_>>(),
+            [120, 120, 120, 120, 120, 120, 120, 120, 40]
+        );
+
+        // When the samples rate increases, the chunk is cut at twice the target.
+        let mut input = samples(30, 1000, |_| 42.0);
+        input.extend(
+            samples(1000, 1, |_| 42.0)
+                .into_iter()
+                .map(|sample| XORSample {
+                    timestamp: sample.timestamp + 30000,
+                    value: sample.value,
+                }),
+        );
+        let chunks = SeriesChunker::new().chunks(input).unwrap();
+        assert_eq!(xor_samples(&chunks[0]).len(), 240);
+
+        // Random values are cut on the size of the chunk.
+        let chunks = SeriesChunker::new()
+            .chunks(samples(1000, 1, |i| (i as f64).sqrt()))
+            .unwrap();
+        assert!(chunks.len() > 5);
+        for chunk in &chunks {
+            let mut buffer: Vec<u8> = Vec::new();
+            chunk.write(&mut buffer).unwrap();
+            // The last sample may go over the limit.
+            assert!(buffer.len() < DEFAULT_MAX_BYTES_PER_CHUNK + 32);
+        }
+
+        // A large target is still limited to the u16 number of samples.
+        let chunks = SeriesChunker::new()
+            .with_samples_per_chunk(usize::MAX / 2)
+            .with_max_bytes_per_chunk(usize::MAX)
+            .chunks(samples(70000, 1, |_| 42.0))
+            .unwrap();
+        assert_eq!(xor_samples(&chunks[0]).len(), u16::MAX as usize);
+    }
+
+    #[test]
+    fn test_series_chunker_encoded_size() {
+        let mut chunker = SeriesChunker::new();
+        for sample in samples(100, 15000, |i| (i as f64).sqrt()) {
+            chunker.append(sample).unwrap();
+            let mut buffer: Vec<u8> = Vec::new();
+            XORChunk::new(chunker.samples().to_vec())
+                .write(&mut buffer)
+                .unwrap();
+            assert_eq!(chunker.num_bits.div_ceil(8), buffer.len());
+        }
+    }
+
+    #[test]
+    fn test_series_chunker_errors() {
+        let mut chunker = SeriesChunker::new();
+        chunker.append(XORSample::stale(10)).unwrap();
+        assert!(chunker
+            .append(XORSample {
+                timestamp: 9,
+                value: 1.0
+            })
+            .is_err());
+        assert_eq!(chunker.samples(), [XORSample::stale(10)]);
+        assert_eq!(
+            chunker.num_bits.div_ceil(8),
+            XORChunk::new(chunker.samples().to_vec())
+                .encoded_len()
+                .unwrap()
+        );
+        assert!(SeriesChunker::new().finish().is_none());
+    }
+}
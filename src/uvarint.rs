@@ -1,6 +1,8 @@
-use nom::{bytes::complete::take, IResult};
+use nom::{bytes::complete::take, error::ErrorKind, IResult};
 
-pub use crate::encoder::uvarint_encoder::write_uvarint;
+use crate::bit_reader::BitReader;
+
+pub use crate::encoder::uvarint_encoder::{uvarint_len, write_uvarint};
 
 /// Parses a Golang uvarint.
 pub fn read_uvarint(input: &[u8]) -> IResult<&[u8], u64> {
@@ -33,6 +35,30 @@ pub fn read_uvarint(input: &[u8]) -> IResult<&[u8], u64> {
     )))
 }
 
+impl BitReader<'_> {
+    /// Reads a Golang uvarint, that doesn't have to be byte aligned.
+    pub fn read_uvarint(&mut self) -> Result<u64, ErrorKind> {
+        let mut x: u64 = 0;
+        let mut s: usize = 0;
+
+        for i in 0..10 {
+            let byte = self.read_bits(8)? as u8;
+
+            if byte < 0x80 {
+                if i == 9 && byte > 1 {
+                    return Err(ErrorKind::TooLarge);
+                }
+                return Ok(x | (byte as u64) << s);
+            }
+
+            x |= ((byte & 0x7f) as u64) << s;
+            s += 7;
+        }
+
+        Err(ErrorKind::TooLarge)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -87,4 +113,19 @@ mod tests {
         let result = read_uvarint(input);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_bit_reader() {
+        let mut reader = BitReader::new(b"\xac\x02\x80");
+        assert_eq!(reader.read_uvarint(), Ok(300));
+        assert_eq!(reader.read_uvarint(), Err(ErrorKind::Eof));
+
+        // Not byte aligned: a 1 bit, then 300.
+        let mut reader = BitReader::new(b"\xd6\x01\x00");
+        assert_eq!(reader.read_bit(), Ok(true));
+        assert_eq!(reader.read_uvarint(), Ok(300));
+
+        let mut reader = BitReader::new(b"\x80\x80\x80\x80\x80\x80\x80\x80\x80\x02");
+        assert_eq!(reader.read_uvarint(), Err(ErrorKind::TooLarge));
+    }
 }
@@ -0,0 +1,30 @@
+/// The bits of Prometheus' staleness marker, a specific NaN value.
+pub const STALE_NAN_BITS: u64 = 0x7ff0000000000002;
+
+/// Prometheus' staleness marker.
+///
+/// It marks the end of a series, for example when a target disappears.
+/// It is a NaN, so use [`is_stale_nan`] to look for it instead of `==`.
+pub const STALE_NAN: f64 = f64::from_bits(STALE_NAN_BITS);
+
+/// Returns true if the value is Prometheus' staleness marker.
+///
+/// Other NaN values aren't staleness markers.
+#[inline]
+pub fn is_stale_nan(value: f64) -> bool {
+    value.to_bits() == STALE_NAN_BITS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_stale_nan() {
+        assert!(is_stale_nan(STALE_NAN));
+        assert!(is_stale_nan(f64::from_bits(0x7ff0000000000002)));
+        assert!(!is_stale_nan(f64::NAN));
+        assert!(!is_stale_nan(0.0));
+        assert!(!is_stale_nan(f64::INFINITY));
+    }
+}
@@ -1,7 +1,6 @@
 //! Prometheus' varbit encoding.
 //!
-//! The writers are not implemented yet as they are only
-//! used in histograms, that are not implemented yet.
+//! It is used by the histogram chunks.
 use nom::{
     bits::complete::{bool, take},
     IResult,
@@ -9,6 +8,8 @@ use nom::{
 
 use crate::NomBitInput;
 
+pub use crate::encoder::varbit_encoder::{write_varbit_int, write_varbit_uint};
+
 /// Reads a varbit-encoded integer from the input.
 ///
 /// Prometheus' varbitint starts with a bucket category of variable length.
@@ -1,35 +1,12 @@
-use nom::{
-    bits::complete::{bool, take},
-    IResult,
-};
+use nom::{error::ErrorKind, IResult};
 
-use crate::NomBitInput;
+use crate::{
+    bit_reader::{parse_bits, BitReader},
+    NomBitInput,
+};
 
 pub use crate::encoder::varbit_ts_encoder::write_varbit_ts;
 
-/// Reads a varbit-encoded integer from the input.
-///
-/// Prometheus' varbitint starts with a bucket category of variable length.
-/// It consists of 1 bits and a final 0, up to 8 bits.
-/// When it's 8 bits long, the final 0 is skipped.
-///
-/// It consists of 9 categories.
-fn read_varbit_ts_bucket(input: NomBitInput) -> IResult<NomBitInput, u8> {
-    let mut remaining_input = input;
-
-    for i in 0..4 {
-        let (new_remaining_input, bit) = bool(remaining_input)?;
-        remaining_input = new_remaining_input;
-        // If we read a 0, it's a sign that we reached the end of the bucket category.
-        if !bit {
-            return Ok((remaining_input, i));
-        }
-    }
-
-    // If we read 4 bits already, there is no final 0.
-    Ok((remaining_input, 4))
-}
-
 #[inline]
 fn varbit_ts_bucket_to_num_bits(bucket: u8) -> u8 {
     match bucket {
@@ -42,20 +19,48 @@ fn varbit_ts_bucket_to_num_bits(bucket: u8) -> u8 {
     }
 }
 
-/// Reads a Prometheus varbit timestamp encoded number from the input.
-pub fn read_varbit_ts(input: NomBitInput) -> IResult<NomBitInput, i64> {
-    let (remaining_input, bucket) = read_varbit_ts_bucket(input)?;
-    let num_bits = varbit_ts_bucket_to_num_bits(bucket);
+impl BitReader<'_> {
+    /// Reads the bucket category of a varbit-encoded integer.
+    ///
+    /// Prometheus' varbitint starts with a bucket category of variable length.
+    /// It consists of 1 bits and a final 0, up to 4 bits.
+    /// When it's 4 bits long, the final 0 is skipped.
+    ///
+    /// It consists of 5 categories.
+    #[inline]
+    fn read_varbit_ts_bucket(&mut self) -> Result<u8, ErrorKind> {
+        for i in 0..4 {
+            // If we read a 0, it's a sign that we reached the end of the bucket category.
+            if !self.read_bit()? {
+                return Ok(i);
+            }
+        }
 
-    // Shortcut for the 0 use case as nothing more has to be read.
-    if bucket == 0 {
-        return Ok((remaining_input, 0));
+        // If we read 4 bits already, there is no final 0.
+        Ok(4)
     }
 
-    let (remaining_input, mut value): (_, i64) = take(num_bits)(remaining_input)?;
-    if num_bits != 64 && value > (1 << (num_bits - 1)) {
-        value -= 1 << num_bits;
+    /// Reads a Prometheus varbit timestamp encoded number.
+    #[inline]
+    pub fn read_varbit_ts(&mut self) -> Result<i64, ErrorKind> {
+        let bucket = self.read_varbit_ts_bucket()?;
+
+        // Shortcut for the 0 use case as nothing more has to be read.
+        if bucket == 0 {
+            return Ok(0);
+        }
+
+        let num_bits = varbit_ts_bucket_to_num_bits(bucket);
+        let mut value = self.read_bits(num_bits)? as i64;
+        if num_bits != 64 && value > (1 << (num_bits - 1)) {
+            value -= 1 << num_bits;
+        }
+
+        Ok(value)
     }
+}
 
-    Ok((remaining_input, value))
+/// Reads a Prometheus varbit timestamp encoded number from the input.
+pub fn read_varbit_ts(input: NomBitInput) -> IResult<NomBitInput, i64> {
+    parse_bits(input, BitReader::read_varbit_ts)
 }
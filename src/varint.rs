@@ -2,7 +2,7 @@ use nom::IResult;
 
 use crate::uvarint::read_uvarint;
 
-pub use crate::encoder::varint_encoder::write_varint;
+pub use crate::encoder::varint_encoder::{varint_len, write_varint};
 
 /// Parses a Golang varint.
 pub fn read_varint(input: &[u8]) -> IResult<&[u8], i64> {
@@ -1,15 +1,18 @@
 use nom::{
-    bits, bytes,
+    error::ErrorKind,
     number::complete::{be_f64, be_u16},
     sequence::tuple,
     IResult,
 };
 
 use crate::{
-    chunk::ChunkWithBlockChunkRef, uvarint::read_uvarint, varbit_xor::read_varbit_xor,
+    bit_reader::BitReader,
+    chunk::ChunkWithBlockChunkRef,
+    compression_stats::CompressionStats,
+    errors::RustyChunkEncError,
+    value::{is_stale_nan, STALE_NAN},
     varint::read_varint,
 };
-use crate::{varbit_ts::read_varbit_ts, NomBitInput};
 
 /// A Prometheus XOR chunk.
 ///
@@ -67,14 +70,38 @@ impl XORChunk {
 }
 
 /// A sample of a Prometheus XOR chunk.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// Samples are equal when their values have the same bits,
+/// so NaN values, like the staleness markers, are equal to themselves.
+#[derive(Debug, Clone)]
 pub struct XORSample {
     pub timestamp: i64,
     pub value: f64,
 }
 
+impl PartialEq for XORSample {
+    fn eq(&self, other: &Self) -> bool {
+        self.timestamp == other.timestamp && self.value.to_bits() == other.value.to_bits()
+    }
+}
+
+impl XORSample {
+    /// Creates a staleness marker at the given timestamp.
+    pub fn stale(timestamp: i64) -> Self {
+        Self {
+            timestamp,
+            value: STALE_NAN,
+        }
+    }
+
+    /// Returns true if the sample is a staleness marker.
+    pub fn is_stale_marker(&self) -> bool {
+        is_stale_nan(self.value)
+    }
+}
+
 #[derive(Debug)]
-struct XORWriteIterator {
+pub(crate) struct XORWriteIterator {
     pub timestamp: i64,
     pub value: f64,
     pub leading_bits_count: u8,
@@ -87,114 +114,111 @@ fn read_first_sample(input: &[u8]) -> IResult<&[u8], XORSample> {
     Ok((remaining_input, XORSample { timestamp, value }))
 }
 
-fn read_second_sample<'a>(
+/// Reads the second sample.
+///
+/// The bits of the sample are counted in the statistics, if any.
+#[inline]
+fn read_second_sample(
+    reader: &mut BitReader,
     first_timestamp: i64,
     first_value: f64,
-) -> impl Fn(NomBitInput<'a>) -> IResult<NomBitInput<'a>, XORWriteIterator> {
-    move |input: NomBitInput<'a>| {
-        let (
-            remaining_input,
-            (timestamp_delta, (value, new_leading_bits_count, new_trailing_bits_count)),
-        ) = tuple((bytes(read_uvarint), read_varbit_xor(first_value, 0, 0)))(input)?;
-
-        let timestamp = first_timestamp
-            + i64::try_from(timestamp_delta).map_err(|_| {
-                nom::Err::Error(nom::error::Error::new(
-                    remaining_input,
-                    nom::error::ErrorKind::TooLarge,
-                ))
-            })?;
-
-        Ok((
-            remaining_input,
-            XORWriteIterator {
-                timestamp,
-                value,
-                leading_bits_count: new_leading_bits_count,
-                trailing_bits_count: new_trailing_bits_count,
-                timestamp_delta,
-            },
-        ))
+    stats: Option<&mut CompressionStats>,
+) -> Result<XORWriteIterator, ErrorKind> {
+    let start = reader.bit_position();
+    let timestamp_delta = reader.read_uvarint()?;
+    let value_start = reader.bit_position();
+    let (value, leading_bits_count, trailing_bits_count) =
+        reader.read_varbit_xor(first_value, 0, 0)?;
+    if let Some(stats) = stats {
+        stats.add_xor_sample(
+            value_start - start,
+            false,
+            reader.bit_position() - value_start,
+            leading_bits_count,
+            trailing_bits_count,
+        );
     }
+
+    let timestamp =
+        first_timestamp + i64::try_from(timestamp_delta).map_err(|_| ErrorKind::TooLarge)?;
+
+    Ok(XORWriteIterator {
+        timestamp,
+        value,
+        leading_bits_count,
+        trailing_bits_count,
+        timestamp_delta,
+    })
 }
 
-fn read_n_sample<'a>(
+/// Reads the third and following samples.
+///
+/// The bits of the sample are counted in the statistics, if any.
+#[inline]
+fn read_n_sample(
+    reader: &mut BitReader,
     previous_iterator: &XORWriteIterator,
-) -> impl Fn(NomBitInput<'a>) -> IResult<NomBitInput<'a>, XORWriteIterator> {
-    let previous_timestamp = previous_iterator.timestamp;
-    let previous_value = previous_iterator.value;
-    let previous_leading_bits_count = previous_iterator.leading_bits_count;
-    let previous_trailing_bits_count = previous_iterator.trailing_bits_count;
-    let previous_timestamp_delta = previous_iterator.timestamp_delta;
-
-    move |input: NomBitInput<'a>| {
-        let (
-            remaining_input,
-            (timestamp_delta_of_delta, (value, new_leading_bits_count, new_trailing_bits_count)),
-        ) = tuple((
-            read_varbit_ts,
-            read_varbit_xor(
-                previous_value,
-                previous_leading_bits_count,
-                previous_trailing_bits_count,
-            ),
-        ))(input)?;
-
-        let timestamp_delta = ((previous_timestamp_delta as i64) + timestamp_delta_of_delta) as u64;
-        let timestamp = previous_timestamp + timestamp_delta as i64;
-
-        Ok((
-            remaining_input,
-            XORWriteIterator {
-                timestamp,
-                value,
-                leading_bits_count: new_leading_bits_count,
-                trailing_bits_count: new_trailing_bits_count,
-                timestamp_delta,
-            },
-        ))
+    stats: Option<&mut CompressionStats>,
+) -> Result<XORWriteIterator, ErrorKind> {
+    let start = reader.bit_position();
+    let timestamp_delta_of_delta = reader.read_varbit_ts()?;
+    let value_start = reader.bit_position();
+    let (value, leading_bits_count, trailing_bits_count) = reader.read_varbit_xor(
+        previous_iterator.value,
+        previous_iterator.leading_bits_count,
+        previous_iterator.trailing_bits_count,
+    )?;
+    if let Some(stats) = stats {
+        stats.add_xor_sample(
+            value_start - start,
+            true,
+            reader.bit_position() - value_start,
+            leading_bits_count,
+            trailing_bits_count,
+        );
     }
+
+    let timestamp_delta =
+        ((previous_iterator.timestamp_delta as i64) + timestamp_delta_of_delta) as u64;
+    let timestamp = previous_iterator.timestamp + timestamp_delta as i64;
+
+    Ok(XORWriteIterator {
+        timestamp,
+        value,
+        leading_bits_count,
+        trailing_bits_count,
+        timestamp_delta,
+    })
 }
 
-fn read_following_samples<'a>(
+fn read_following_samples(
+    reader: &mut BitReader,
     first_timestamp: i64,
     first_value: f64,
     num_samples: u16,
-) -> impl Fn(NomBitInput<'a>) -> IResult<NomBitInput<'a>, Vec<XORSample>> {
-    move |input: NomBitInput<'a>| {
-        let mut samples = Vec::with_capacity(num_samples as usize);
+) -> Result<Vec<XORSample>, ErrorKind> {
+    let mut samples = Vec::with_capacity(num_samples as usize);
+    samples.push(XORSample {
+        timestamp: first_timestamp,
+        value: first_value,
+    });
+
+    if num_samples > 1 {
+        let mut iterator = read_second_sample(reader, first_timestamp, first_value, None)?;
         samples.push(XORSample {
-            timestamp: first_timestamp,
-            value: first_value,
+            timestamp: iterator.timestamp,
+            value: iterator.value,
         });
 
-        if num_samples > 1 {
-            let (remaining_input_bits, iterator) =
-                read_second_sample(first_timestamp, first_value)(input)?;
-
+        for _ in 2..num_samples {
+            iterator = read_n_sample(reader, &iterator, None)?;
             samples.push(XORSample {
                 timestamp: iterator.timestamp,
                 value: iterator.value,
             });
-
-            let mut iterator = iterator;
-            let mut remaining_input_bits = remaining_input_bits;
-            for _ in 2..num_samples {
-                let (tmp_remaining_input_bits, tmp_iterator) =
-                    read_n_sample(&iterator)(remaining_input_bits)?;
-                iterator = tmp_iterator;
-                remaining_input_bits = tmp_remaining_input_bits;
-
-                samples.push(XORSample {
-                    timestamp: iterator.timestamp,
-                    value: iterator.value,
-                });
-            }
-
-            return Ok((remaining_input_bits, samples));
         }
-        Ok((input, samples))
     }
+    Ok(samples)
 }
 
 /// Reads a XOR chunk from the input data.
@@ -207,17 +231,17 @@ fn read_following_samples<'a>(
 pub fn read_xor_chunk_data(input: &[u8]) -> IResult<&[u8], XORChunk> {
     let (remaining_input, (num_samples, first_sample)) = tuple((be_u16, read_first_sample))(input)?;
 
-    let (remaining_input, all_samples) = bits(read_following_samples(
+    let mut reader = BitReader::new(remaining_input);
+    let all_samples = read_following_samples(
+        &mut reader,
         first_sample.timestamp,
         first_sample.value,
         num_samples,
-    ))(remaining_input)?;
-
-    //println!("all samples: {:?}", all_samples);
-    //panic!("stop");
+    )
+    .map_err(|kind| nom::Err::Error(nom::error::Error::new(reader.remaining_bytes(), kind)))?;
 
     Ok((
-        remaining_input,
+        reader.remaining_bytes(),
         XORChunk {
             samples: all_samples,
             block_chunk_ref: None,
@@ -226,6 +250,162 @@ pub fn read_xor_chunk_data(input: &[u8]) -> IResult<&[u8], XORChunk> {
     ))
 }
 
+/// A streaming iterator over the samples of XOR chunk data.
+///
+/// Unlike [`read_xor_chunk_data`], it borrows the encoded bytes
+/// and decodes the samples one at a time, without allocating.
+/// Like Prometheus' `xorIterator`, it stops on the first decoding error,
+/// that is then available through [`XORIterator::err`].
+#[derive(Debug)]
+pub struct XORIterator<'a> {
+    pub(crate) reader: BitReader<'a>,
+    num_samples: u16,
+    pub(crate) num_read: u16,
+    pub(crate) state: XORWriteIterator,
+    pub(crate) error: Option<RustyChunkEncError>,
+    /// Whether the iteration ended, after the last sample or on an error.
+    pub(crate) exhausted: bool,
+    /// The compression statistics of the samples read so far, when enabled.
+    pub(crate) stats: Option<Box<CompressionStats>>,
+}
+
+impl<'a> XORIterator<'a> {
+    /// Creates an iterator over XOR chunk data, without the chunk header.
+    ///
+    /// It takes the same input as [`read_xor_chunk_data`].
+    pub fn new(input: &'a [u8]) -> Self {
+        Self {
+            reader: BitReader::new(input),
+            num_samples: 0,
+            num_read: 0,
+            state: XORWriteIterator {
+                timestamp: 0,
+                value: 0.0,
+                leading_bits_count: 0,
+                trailing_bits_count: 0,
+                timestamp_delta: 0,
+            },
+            error: None,
+            exhausted: false,
+            stats: None,
+        }
+    }
+
+    /// Counts the bits of the samples read in compression statistics.
+    pub(crate) fn with_stats(mut self) -> Self {
+        self.stats = Some(Box::default());
+        self
+    }
+
+    /// Returns the error that stopped the iteration, if any.
+    pub fn err(&self) -> Option<&RustyChunkEncError> {
+        self.error.as_ref()
+    }
+
+    /// Returns the last sample read.
+    pub fn at(&self) -> (i64, f64) {
+        (self.state.timestamp, self.state.value)
+    }
+
+    /// Advances to the first sample with a timestamp equal or greater than `t`.
+    ///
+    /// Like Prometheus' `Iterator.Seek`, it does nothing if the current sample
+    /// is already there. It returns false when the iterator is exhausted,
+    /// or on errors.
+    pub fn seek(&mut self, t: i64) -> bool {
+        if self.exhausted {
+            return false;
+        }
+        while self.num_read == 0 || self.state.timestamp < t {
+            if self.next().is_none() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns false when the chunk has no samples.
+    fn read_header_and_first_sample(&mut self) -> Result<bool, RustyChunkEncError> {
+        let (remaining_input, num_samples) = be_u16(self.reader.remaining_bytes())?;
+        self.num_samples = num_samples;
+        if let Some(stats) = self.stats.as_deref_mut() {
+            stats.num_chunks = 1;
+            stats.num_samples = num_samples as usize;
+            stats.header_bits = 16;
+        }
+        if num_samples == 0 {
+            return Ok(false);
+        }
+        let sample_input = remaining_input;
+        let (remaining_input, first_sample) = read_first_sample(sample_input)?;
+        if let Some(stats) = self.stats.as_deref_mut() {
+            // A varint timestamp, and a raw value.
+            stats.timestamp_bits += (sample_input.len() - remaining_input.len() - 8) * 8;
+            stats.value_bits += 64;
+        }
+        self.reader = BitReader::new(remaining_input);
+        self.state.timestamp = first_sample.timestamp;
+        self.state.value = first_sample.value;
+        Ok(true)
+    }
+
+    fn read_next_sample(&mut self) -> Result<(), RustyChunkEncError> {
+        self.state = if self.num_read == 1 {
+            read_second_sample(
+                &mut self.reader,
+                self.state.timestamp,
+                self.state.value,
+                self.stats.as_deref_mut(),
+            )
+        } else {
+            read_n_sample(&mut self.reader, &self.state, self.stats.as_deref_mut())
+        }
+        .map_err(RustyChunkEncError::from_bit_reader_error)?;
+        Ok(())
+    }
+}
+
+impl Iterator for XORIterator<'_> {
+    type Item = (i64, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.exhausted {
+            return None;
+        }
+        let result = if self.num_read == 0 {
+            self.read_header_and_first_sample()
+        } else if self.num_read >= self.num_samples {
+            Ok(false)
+        } else {
+            self.read_next_sample().map(|_| true)
+        };
+        match result {
+            Ok(false) => {
+                self.exhausted = true;
+                None
+            }
+            Ok(true) => {
+                self.num_read += 1;
+                Some(self.at())
+            }
+            Err(error) => {
+                self.error = Some(error);
+                self.exhausted = true;
+                None
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.num_read == 0 {
+            (0, Some(u16::MAX as usize))
+        } else {
+            let remaining = (self.num_samples - self.num_read) as usize;
+            (0, Some(remaining))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::encoder::uvarint_encoder::write_uvarint;
@@ -258,7 +438,132 @@ mod tests {
         // Append a zero for the xor bit, so it reuses the previous value
         buffer.push(0);
 
-        let error = read_second_sample(0, 42.0)((&buffer, 0)).unwrap_err();
-        assert!(error.to_string().contains("TooLarge"),);
+        let error = read_second_sample(&mut BitReader::new(&buffer), 0, 42.0, None).unwrap_err();
+        assert_eq!(error, ErrorKind::TooLarge);
+    }
+
+    #[test]
+    fn test_xor_iterator() {
+        let samples = (0..1000)
+            .map(|i| XORSample {
+                timestamp: 7200000 + i * 15000 + (i % 7),
+                value: if i % 3 == 0 { 42.0 } else { (i as f64).sin() },
+            })
+            .collect::<Vec<_>>();
+        let mut buffer: Vec<u8> = Vec::new();
+        XORChunk::new(samples.clone()).write(&mut buffer).unwrap();
+
+        let mut iterator = XORIterator::new(&buffer);
+        let mut count = 0;
+        for ((timestamp, value), sample) in (&mut iterator).zip(samples.iter()) {
+            assert_eq!(timestamp, sample.timestamp);
+            assert_eq!(value.to_bits(), sample.value.to_bits());
+            count += 1;
+        }
+        assert_eq!(count, samples.len());
+        assert!(iterator.next().is_none());
+        assert!(iterator.err().is_none());
+        assert_eq!(iterator.at(), (samples[999].timestamp, samples[999].value));
+
+        // Same as the allocating reader, including the padding bug
+        let input = b"\x00\x01\x80\xF4\xEE\x06\x40\xC7\x70\x00\x00\x00\x00\x00\x00";
+        assert_eq!(
+            XORIterator::new(input).collect::<Vec<_>>(),
+            vec![(7200000, 12000.0)]
+        );
+
+        let mut iterator = XORIterator::new(&[0x00, 0x00]);
+        assert!(iterator.next().is_none());
+        assert!(iterator.err().is_none());
+    }
+
+    #[test]
+    fn test_xor_iterator_errors() {
+        let samples = (0..10)
+            .map(|i| XORSample {
+                timestamp: i * 1000,
+                value: i as f64 * 1.5,
+            })
+            .collect::<Vec<_>>();
+        let mut buffer: Vec<u8> = Vec::new();
+        XORChunk::new(samples).write(&mut buffer).unwrap();
+        buffer.truncate(buffer.len() - 3);
+
+        let mut iterator = XORIterator::new(&buffer);
+        let read = (&mut iterator).count();
+        assert!(read > 0 && read < 10);
+        assert!(iterator.err().is_some());
+        // It stays stopped
+        assert!(iterator.next().is_none());
+
+        let mut iterator = XORIterator::new(&[0x00]);
+        assert!(iterator.next().is_none());
+        assert!(iterator.err().is_some());
+    }
+
+    #[test]
+    fn test_xor_iterator_seek() {
+        let samples = (0..100)
+            .map(|i| XORSample {
+                timestamp: i * 1000,
+                value: i as f64,
+            })
+            .collect::<Vec<_>>();
+        let mut buffer: Vec<u8> = Vec::new();
+        XORChunk::new(samples).write(&mut buffer).unwrap();
+
+        let mut iterator = XORIterator::new(&buffer);
+        assert!(iterator.seek(41500));
+        assert_eq!(iterator.at(), (42000, 42.0));
+        // No-op when already there
+        assert!(iterator.seek(10000));
+        assert!(iterator.seek(42000));
+        assert_eq!(iterator.at(), (42000, 42.0));
+        assert_eq!(iterator.next(), Some((43000, 43.0)));
+
+        assert!(iterator.seek(99000));
+        assert_eq!(iterator.at(), (99000, 99.0));
+        assert!(!iterator.seek(99001));
+        assert!(iterator.next().is_none());
+        assert!(iterator.err().is_none());
+
+        assert!(!XORIterator::new(&[0x00, 0x00]).seek(0));
+    }
+
+    #[test]
+    fn test_stale_markers() {
+        let samples = vec![
+            XORSample {
+                timestamp: 1000,
+                value: 1.0,
+            },
+            XORSample {
+                timestamp: 2000,
+                value: f64::NAN,
+            },
+            XORSample::stale(3000),
+        ];
+        assert!(!samples[0].is_stale_marker());
+        assert!(!samples[1].is_stale_marker());
+        assert!(samples[2].is_stale_marker());
+        assert_eq!(samples[1], samples[1].clone());
+        assert_ne!(samples[1], samples[2]);
+        assert_ne!(
+            XORSample {
+                timestamp: 0,
+                value: 0.0
+            },
+            XORSample {
+                timestamp: 0,
+                value: -0.0
+            }
+        );
+
+        let chunk = XORChunk::new(samples);
+        let mut buffer: Vec<u8> = Vec::new();
+        chunk.write(&mut buffer).unwrap();
+        let (_, parsed_chunk) = read_xor_chunk_data(&buffer).unwrap();
+        assert_eq!(parsed_chunk, chunk);
+        assert!(parsed_chunk.samples()[2].is_stale_marker());
     }
 }
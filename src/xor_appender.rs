@@ -0,0 +1,318 @@
+use bitstream_io::{BigEndian, BitWrite, BitWriter};
+
+use crate::{
+    encoder::xor_encoder::{
+        sample_bit_len, write_first_sample, write_n_sample, write_second_sample, XORReadIterator,
+    },
+    errors::RustyChunkEncError,
+    varint::varint_len,
+    xor::{XORIterator, XORSample},
+};
+
+/// Appends samples to an encoded XOR chunk, one at a time.
+///
+/// Unlike [`crate::xor::XORChunk::write`], it keeps the state of the encoder
+/// between samples, so appending a sample doesn't re-encode the whole chunk.
+/// Like Prometheus' `xorAppender`, it writes the samples in place and updates
+/// the number of samples at the start of the chunk.
+///
+/// The encoded bytes are the same as the ones written by [`crate::xor::XORChunk::write`].
+#[derive(Debug, Clone)]
+pub struct XORAppender {
+    bytes: Vec<u8>,
+    /// Number of bits used in the last byte, 0 when the last byte is full.
+    bit_position: u32,
+    state: Option<XORReadIterator>,
+}
+
+impl Default for XORAppender {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl XORAppender {
+    /// Creates an appender on a new, empty, chunk.
+    pub fn new() -> Self {
+        Self {
+            bytes: vec![0x00, 0x00],
+            bit_position: 0,
+            state: None,
+        }
+    }
+
+    /// Creates an appender that resumes an existing chunk.
+    ///
+    /// It takes the same input as [`crate::xor::read_xor_chunk_data`],
+    /// and replays the samples to find the state of the encoder
+    /// and the bit position of the end of the chunk, like Prometheus' `XORChunk.Appender`.
+    /// The data following the chunk in the input is ignored.
+    pub fn from_bytes(input: &[u8]) -> Result<Self, RustyChunkEncError> {
+        let mut iterator = XORIterator::new(input);
+        // The encoder keeps asking for new leading and trailing bits counts
+        // until a value is different from the first one.
+        let mut first_value: Option<f64> = None;
+        let mut same_values = true;
+        for (_, value) in &mut iterator {
+            match first_value {
+                None => first_value = Some(value),
+                Some(first_value) => same_values &= value.to_bits() == first_value.to_bits(),
+            }
+        }
+        if let Some(error) = iterator.error.take() {
+            return Err(error);
+        }
+        if iterator.num_read == 0 {
+            return Ok(Self::new());
+        }
+
+        let (remaining_input, bit_position) = iterator.reader.nom_input();
+        let mut length = input.len() - remaining_input.len();
+        if bit_position > 0 {
+            length += 1;
+        }
+        let state = &iterator.state;
+        let (leading_bits_count, trailing_bits_count) = if same_values {
+            (0xff, 0)
+        } else {
+            (state.leading_bits_count, state.trailing_bits_count)
+        };
+
+        Ok(Self {
+            bytes: input[..length].to_vec(),
+            bit_position: bit_position as u32,
+            state: Some(XORReadIterator {
+                timestamp: state.timestamp,
+                value: state.value,
+                leading_bits_count,
+                trailing_bits_count,
+                timestamp_delta: state.timestamp_delta as i64,
+            }),
+        })
+    }
+
+    /// Returns the number of samples in the chunk.
+    pub fn num_samples(&self) -> usize {
+        u16::from_be_bytes([self.bytes[0], self.bytes[1]]) as usize
+    }
+
+    /// Returns the encoded chunk data, without the chunk header.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the encoded chunk data, without the chunk header.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Returns an iterator over the samples of the chunk.
+    pub fn iter(&self) -> XORIterator<'_> {
+        XORIterator::new(&self.bytes)
+    }
+
+    /// Returns the number of bytes appending the sample would add to the chunk.
+    ///
+    /// It doesn't append the sample. It fails like [`XORAppender::append`]
+    /// when the timestamp is lower than the previous one.
+    pub fn appended_len(&self, timestamp: i64, value: f64) -> std::io::Result<usize> {
+        let sample = XORSample { timestamp, value };
+        match &self.state {
+            None => Ok(varint_len(timestamp) + 8),
+            Some(state) => {
+                let (bits, _) = sample_bit_len(state, self.num_samples() == 1, &sample)?;
+                // The last byte of the chunk may have free bits.
+                let free_bits = (8 - self.bit_position as usize) % 8;
+                Ok(bits.saturating_sub(free_bits).div_ceil(8))
+            }
+        }
+    }
+
+    /// Appends a sample to the chunk.
+    ///
+    /// The timestamp must be equal or greater than the previous one.
+    /// On errors, the chunk is left unchanged.
+    pub fn append(&mut self, timestamp: i64, value: f64) -> std::io::Result<()> {
+        let num_samples = self.num_samples();
+        if num_samples >= u16::MAX as usize {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "too many samples for one chunk",
+            ));
+        }
+        let sample = XORSample { timestamp, value };
+
+        match &self.state {
+            None => {
+                write_first_sample(&sample, &mut self.bytes)?;
+                self.state = Some(XORReadIterator {
+                    timestamp,
+                    value,
+                    leading_bits_count: 0xff,
+                    trailing_bits_count: 0,
+                    timestamp_delta: 0,
+                });
+            }
+            Some(state) => {
+                // The bits are written to a small buffer that starts with
+                // the bits of the last, incomplete, byte of the chunk.
+                let mut tail: Vec<u8> = Vec::with_capacity(16);
+                let mut bit_writer = BitWriter::endian(&mut tail, BigEndian);
+                if self.bit_position > 0 {
+                    let last_byte = self.bytes[self.bytes.len() - 1];
+                    bit_writer.write(self.bit_position, last_byte >> (8 - self.bit_position))?;
+                }
+
+                let new_state = if num_samples == 1 {
+                    let first_sample = XORSample {
+                        timestamp: state.timestamp,
+                        value: state.value,
+                    };
+                    write_second_sample(&sample, &first_sample, &mut bit_writer)?
+                } else {
+                    write_n_sample(state, &sample, &mut bit_writer)?
+                };
+
+                let (bit_position, partial_byte) = bit_writer.into_unwritten();
+                if bit_position > 0 {
+                    tail.push(partial_byte << (8 - bit_position));
+                }
+
+                if self.bit_position > 0 {
+                    self.bytes.pop();
+                }
+                self.bytes.extend_from_slice(&tail);
+                self.bit_position = bit_position;
+                self.state = Some(new_state);
+            }
+        }
+
+        self.bytes[..2].copy_from_slice(&(num_samples as u16 + 1).to_be_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xor::XORChunk;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_xor_appender() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+
+        for _ in 0..32 {
+            let mut timestamp: i64 = rng.gen_range(1234567890..1357908642);
+            let mut value: f64 = rng.gen_range(-10000.0..10000.0);
+            let mut samples = Vec::new();
+            let mut appender = XORAppender::new();
+            assert_eq!(appender.num_samples(), 0);
+            assert_eq!(appender.iter().count(), 0);
+
+            for _ in 0..rng.gen_range(1..200) {
+                let len = appender.bytes().len() + appender.appended_len(timestamp, value).unwrap();
+                appender.append(timestamp, value).unwrap();
+                assert_eq!(appender.bytes().len(), len);
+                samples.push(XORSample { timestamp, value });
+
+                let mut buffer: Vec<u8> = Vec::new();
+                XORChunk::new(samples.clone()).write(&mut buffer).unwrap();
+                assert_eq!(appender.bytes(), buffer);
+
+                timestamp += rng.gen_range(0..30000);
+                if rng.gen_bool(0.33) {
+                    value += 1.0;
+                } else if rng.gen_bool(0.33) {
+                    value = rng.gen();
+                }
+            }
+
+            assert_eq!(appender.num_samples(), samples.len());
+            assert_eq!(
+                appender.iter().collect::<Vec<_>>(),
+                samples
+                    .iter()
+                    .map(|sample| (sample.timestamp, sample.value))
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_xor_appender_errors() {
+        let mut appender = XORAppender::new();
+        appender.append(10, 1.0).unwrap();
+        assert!(appender.append(9, 2.0).is_err());
+        appender.append(11, 2.0).unwrap();
+        assert!(appender.append(10, 3.0).is_err());
+        assert_eq!(appender.num_samples(), 2);
+        assert_eq!(appender.iter().collect::<Vec<_>>(), [(10, 1.0), (11, 2.0)]);
+
+        let mut appender = XORAppender::new();
+        for i in 0..u16::MAX as i64 {
+            appender.append(i, i as f64).unwrap();
+        }
+        assert!(appender.append(u16::MAX as i64, 0.0).is_err());
+        assert_eq!(appender.num_samples(), u16::MAX as usize);
+    }
+
+    #[test]
+    fn test_xor_appender_from_bytes() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+
+        for _ in 0..32 {
+            let mut timestamp: i64 = rng.gen_range(1234567890..1357908642);
+            let mut value: f64 = rng.gen_range(-10000.0..10000.0);
+            let mut samples = Vec::new();
+            for _ in 0..rng.gen_range(1..200) {
+                samples.push(XORSample { timestamp, value });
+                timestamp += rng.gen_range(0..30000);
+                if rng.gen_bool(0.33) {
+                    value += 1.0;
+                } else if rng.gen_bool(0.33) {
+                    value = rng.gen();
+                }
+            }
+            let mut expected: Vec<u8> = Vec::new();
+            XORChunk::new(samples.clone()).write(&mut expected).unwrap();
+
+            let split = rng.gen_range(1..=samples.len());
+            let mut buffer: Vec<u8> = Vec::new();
+            XORChunk::new(samples[..split].to_vec())
+                .write(&mut buffer)
+                .unwrap();
+            // Followed by another chunk
+            XORChunk::new(samples.clone()).write(&mut buffer).unwrap();
+
+            let mut appender = XORAppender::from_bytes(&buffer).unwrap();
+            assert_eq!(appender.num_samples(), split);
+            for sample in &samples[split..] {
+                appender.append(sample.timestamp, sample.value).unwrap();
+            }
+            assert_eq!(appender.bytes(), expected);
+        }
+    }
+
+    #[test]
+    fn test_xor_appender_from_bytes_edge_cases() {
+        let appender = XORAppender::from_bytes(&[0x00, 0x00]).unwrap();
+        assert_eq!(appender.num_samples(), 0);
+
+        // The same value many times, then different values.
+        let mut appender = XORAppender::new();
+        for i in 0..10 {
+            appender.append(i, 42.0).unwrap();
+        }
+        let mut resumed = XORAppender::from_bytes(appender.bytes()).unwrap();
+        for i in 10..20 {
+            appender.append(i, i as f64).unwrap();
+            resumed.append(i, i as f64).unwrap();
+        }
+        assert_eq!(resumed.bytes(), appender.bytes());
+
+        let bytes = appender.into_bytes();
+        assert!(XORAppender::from_bytes(&bytes[..bytes.len() - 2]).is_err());
+        assert!(XORAppender::from_bytes(&[0x00]).is_err());
+    }
+}
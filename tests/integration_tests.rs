@@ -4,14 +4,30 @@ mod test_data;
 
 #[cfg(test)]
 mod tests {
+    use bitstream_io::{BigEndian, BitWrite, BitWriter};
     use rusty_chunkenc::{
         chunks::read_chunks,
-        index, uvarint, varbit,
+        histogram::{FloatHistogramChunk, HistogramChunk},
+        histogram_appender::{FloatHistogramAppender, HistogramAppender},
+        index,
+        native_histogram::{FloatHistogram, Histogram, Span},
+        uvarint, varbit,
         xor::{read_xor_chunk_data, XORChunk, XORSample},
     };
 
     use super::*;
 
+    /// The Go test data comes with an extra byte of 0 when the bit stream
+    /// ends with a full byte.
+    /// See https://github.com/prometheus/prometheus/pull/14854
+    fn assert_eq_with_padding_bug(buffer: &[u8], encoded: &[u8]) {
+        if encoded.len() == buffer.len() + 1 && encoded.last() == Some(&0) {
+            assert_eq!(buffer, &encoded[..buffer.len()]);
+        } else {
+            assert_eq!(buffer, encoded);
+        }
+    }
+
     #[test]
     fn test_read_varbit_int() {
         let test_data = &test_data::TEST_DATA;
@@ -34,6 +50,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_write_varbit_int() {
+        for varbit_int in &test_data::TEST_DATA.varbit_ints {
+            let mut buffer: Vec<u8> = Vec::new();
+            let mut bit_writer = BitWriter::endian(&mut buffer, BigEndian);
+            varbit::write_varbit_int(varbit_int.v, &mut bit_writer).unwrap();
+            bit_writer.byte_align().unwrap();
+            assert_eq_with_padding_bug(&buffer, &varbit_int.e);
+        }
+    }
+
+    #[test]
+    fn test_write_varbit_uint() {
+        for varbit_uint in &test_data::TEST_DATA.varbit_uints {
+            let mut buffer: Vec<u8> = Vec::new();
+            let mut bit_writer = BitWriter::endian(&mut buffer, BigEndian);
+            varbit::write_varbit_uint(varbit_uint.v, &mut bit_writer).unwrap();
+            bit_writer.byte_align().unwrap();
+            assert_eq_with_padding_bug(&buffer, &varbit_uint.e);
+        }
+    }
+
     #[test]
     fn test_read_uvarint() {
         let test_data = &test_data::TEST_DATA;
@@ -73,6 +111,92 @@ mod tests {
         }
     }
 
+    fn spans(spans: &[test_data::JSpan]) -> Vec<Span> {
+        spans
+            .iter()
+            .map(|span| Span {
+                offset: span.o,
+                length: span.l,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_go_histogram_data() {
+        assert!(!test_data::TEST_DATA.histogram_series.is_empty());
+        assert!(!test_data::TEST_DATA.float_histogram_series.is_empty());
+    }
+
+    #[test]
+    fn test_write_histogram_chunks() {
+        for series in &test_data::TEST_DATA.histogram_series {
+            let mut appender = if series.g {
+                HistogramAppender::new_gauge()
+            } else {
+                HistogramAppender::new()
+            };
+            let mut chunks: Vec<HistogramChunk> = Vec::new();
+            for sample in &series.s {
+                let histogram = Histogram {
+                    schema: sample.schema,
+                    zero_threshold: sample.zt,
+                    zero_count: sample.zc,
+                    count: sample.c,
+                    sum: sample.sum,
+                    positive_spans: spans(&sample.ps),
+                    negative_spans: spans(&sample.ns),
+                    positive_buckets: sample.pb.clone(),
+                    negative_buckets: sample.nb.clone(),
+                    custom_values: vec![],
+                };
+                chunks.extend(appender.append(sample.ts, histogram).unwrap());
+            }
+            chunks.extend(appender.finish());
+
+            assert_eq!(chunks.len(), series.c.len());
+            for (chunk, encoded) in chunks.iter().zip(&series.c) {
+                let mut buffer: Vec<u8> = Vec::new();
+                chunk.write(&mut buffer).unwrap();
+                assert_eq_with_padding_bug(&buffer, encoded);
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_float_histogram_chunks() {
+        for series in &test_data::TEST_DATA.float_histogram_series {
+            let mut appender = if series.g {
+                FloatHistogramAppender::new_gauge()
+            } else {
+                FloatHistogramAppender::new()
+            };
+            let mut chunks: Vec<FloatHistogramChunk> = Vec::new();
+            for sample in &series.s {
+                let histogram = FloatHistogram {
+                    schema: sample.schema,
+                    zero_threshold: sample.zt,
+                    zero_count: sample.zc,
+                    count: sample.c,
+                    sum: sample.sum,
+                    positive_spans: spans(&sample.ps),
+                    negative_spans: spans(&sample.ns),
+                    positive_buckets: sample.pb.clone(),
+                    negative_buckets: sample.nb.clone(),
+                    custom_values: vec![],
+                };
+                chunks.extend(appender.append(sample.ts, histogram).unwrap());
+            }
+            chunks.extend(appender.finish());
+
+            assert_eq!(chunks.len(), series.c.len());
+            for (chunk, encoded) in chunks.iter().zip(&series.c) {
+                let mut buffer: Vec<u8> = Vec::new();
+                chunk.write(&mut buffer).unwrap();
+                assert_eq_with_padding_bug(&buffer, encoded);
+            }
+        }
+    }
+
     #[test]
     fn test_read_chunks_disk_format() {
         let chunk_data = chunk_data::CHUNK_DATA;
@@ -36,12 +36,66 @@ pub struct JChunk {
     pub e: Vec<u8>,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct JSpan {
+    pub o: i32,
+    pub l: u32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct JHistogramSample {
+    pub ts: i64,
+    pub schema: i32,
+    pub zt: f64,
+    pub zc: u64,
+    pub c: u64,
+    pub sum: f64,
+    pub ps: Vec<JSpan>,
+    pub ns: Vec<JSpan>,
+    pub pb: Vec<i64>,
+    pub nb: Vec<i64>,
+}
+
+/// Histograms appended by Prometheus' `HistogramAppender`, and the chunks it wrote.
+#[derive(Deserialize, Debug)]
+pub struct JHistogramSeries {
+    pub g: bool,
+    pub s: Vec<JHistogramSample>,
+    #[serde(deserialize_with = "deserialize_bytes_vec")]
+    pub c: Vec<Vec<u8>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct JFloatHistogramSample {
+    pub ts: i64,
+    pub schema: i32,
+    pub zt: f64,
+    pub zc: f64,
+    pub c: f64,
+    pub sum: f64,
+    pub ps: Vec<JSpan>,
+    pub ns: Vec<JSpan>,
+    pub pb: Vec<f64>,
+    pub nb: Vec<f64>,
+}
+
+/// Float histograms appended by Prometheus' `FloatHistogramAppender`, and the chunks it wrote.
+#[derive(Deserialize, Debug)]
+pub struct JFloatHistogramSeries {
+    pub g: bool,
+    pub s: Vec<JFloatHistogramSample>,
+    #[serde(deserialize_with = "deserialize_bytes_vec")]
+    pub c: Vec<Vec<u8>>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct TestJson {
     pub varbit_ints: Vec<JVarbitInt>,
     pub varbit_uints: Vec<JVarbitUint>,
     pub uvarints: Vec<JUvarint>,
     pub chunks: Vec<JChunk>,
+    pub histogram_series: Vec<JHistogramSeries>,
+    pub float_histogram_series: Vec<JFloatHistogramSeries>,
 }
 
 fn deserialize_bytes<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
@@ -54,6 +108,20 @@ where
         .map_err(serde::de::Error::custom)
 }
 
+fn deserialize_bytes_vec<'de, D>(deserializer: D) -> Result<Vec<Vec<u8>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|s| {
+            general_purpose::STANDARD_NO_PAD
+                .decode(s)
+                .map_err(serde::de::Error::custom)
+        })
+        .collect()
+}
+
 pub static TEST_DATA: Lazy<TestJson> = Lazy::new(|| {
     let file_content = include_str!("test_data.json");
     serde_json::from_str(file_content).expect("Failed to parse test_data.json")